@@ -54,7 +54,6 @@ pub async fn run() {
         .into();
     monkey.material.render_states.cull = Cull::Back;
     monkey.set_transformation(Mat4::from_translation(vec3(2.0, -2.0, 0.0)));
-    let original_color = monkey.material.albedo;
 
     let mut cone = Gm::new(
         Mesh::new(&context, &CpuMesh::cube()),
@@ -78,7 +77,7 @@ pub async fn run() {
         colors: Some(vec![Srgba::GREEN; no_instances]),
         ..Default::default()
     };
-    let mut instanced_mesh = Gm::new(
+    let instanced_mesh = Gm::new(
         InstancedMesh::new(&context, &instances, &sphere),
         PhysicalMaterial::new_opaque(
             &context,
@@ -89,6 +88,10 @@ pub async fn run() {
         ),
     );
 
+    let mut outline = SelectionOutlineEffect::new(&context);
+    outline.pulse_frequency = Some(1.0);
+    let mut selection: Vec<Selection> = Vec::new();
+
     // main loop
     window.render_loop(move |mut frame_input| {
         let mut change = frame_input.first_frame;
@@ -100,11 +103,8 @@ pub async fn run() {
             } = *event
             {
                 if button == MouseButton::Left {
-                    // Reset colors and pick mesh position
-                    let mut instances = instances.clone();
-                    instanced_mesh.set_instances(&instances);
-                    monkey.material.albedo = original_color;
-                    cone.material.albedo = Srgba::BLUE;
+                    // Reset selection and pick mesh position
+                    selection.clear();
                     pick_mesh.set_transformation(Mat4::from_translation(vec3(0.0, 0.0, 0.0)));
 
                     // Pick
@@ -120,24 +120,9 @@ pub async fn run() {
                         pick_mesh.set_transformation(
                             Mat4::from_translation(pick.position) * Mat4::from_scale(0.3),
                         );
-                        match pick.geometry_id {
-                            0 => {
-                                monkey.material.albedo = Srgba::RED;
-                            }
-                            1 => {
-                                cone.material.albedo = Srgba::RED;
-                            }
-                            2 => {
-                                instances.colors.as_mut().unwrap()[pick.instance_id as usize] =
-                                    Srgba::RED;
-                                instanced_mesh.set_instances(&instances);
-                            }
-                            _ => {
-                                unreachable!()
-                            }
-                        };
-                        change = true;
+                        selection.push(pick.into());
                     }
+                    change = true;
                 }
             }
         }
@@ -145,24 +130,28 @@ pub async fn run() {
         change |= control.handle_events(&mut camera, &mut frame_input.events);
 
         // draw
+        outline.time = frame_input.accumulated_time as f32;
         if change {
-            frame_input
-                .screen()
-                .clear(ClearState::color_and_depth(1.0, 1.0, 1.0, 1.0, 1.0))
-                .render(
-                    &camera,
-                    monkey
-                        .into_iter()
-                        .chain(&instanced_mesh)
-                        .chain(&cone)
-                        .chain(&pick_mesh),
-                    &[&ambient, &directional],
-                );
+            outline.update(
+                &camera,
+                monkey.into_iter().chain(&cone).chain(&instanced_mesh),
+                &selection,
+            );
         }
+        frame_input
+            .screen()
+            .clear(ClearState::color_and_depth(1.0, 1.0, 1.0, 1.0, 1.0))
+            .render(
+                &camera,
+                monkey
+                    .into_iter()
+                    .chain(&instanced_mesh)
+                    .chain(&cone)
+                    .chain(&pick_mesh),
+                &[&ambient, &directional],
+            )
+            .apply_screen_effect(&outline, &camera, &[], None, None);
 
-        FrameOutput {
-            swap_buffers: change,
-            ..Default::default()
-        }
+        FrameOutput::default()
     });
 }
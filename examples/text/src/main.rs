@@ -19,7 +19,12 @@ pub fn main() {
 	Aliquam fermentum mattis lectus. Nunc luctus. Integer accumsan pede quis risus. Vestibulum et ante. 
 	Morbi dolor. In nisl. Curabitur malesuada. 
 	Morbi tincidunt semper tortor. Maecenas hendrerit. Vivamus fermentum ante ut wisi. Nunc mattis. Praesent nunc. Suspendisse potenti. Morbi sapien. 
-	Quisque sapien libero, ornare eget, tincidunt semper, convallis vel, sem. Vestibulum ante ipsum primis in faucibus orci luctus et ultrices posuere cubilia Curae; ", TextLayoutOptions { line_height: 1.1 });
+	Quisque sapien libero, ornare eget, tincidunt semper, convallis vel, sem. Vestibulum ante ipsum primis in faucibus orci luctus et ultrices posuere cubilia Curae; ", TextLayoutOptions {
+        line_height: 1.1,
+        max_width: Some(1100.0),
+        align: TextAlign::Justify,
+        ..Default::default()
+    });
 
     let text_generator = TextGenerator::new(include_bytes!("font1.ttf"), 0, 100.0).unwrap();
     let text_mesh2 = text_generator.generate("Hi!\nHow are you?", TextLayoutOptions::default());
@@ -66,6 +71,26 @@ pub fn main() {
         0.0,
     )));
 
+    let mut sdf_text_generator =
+        SdfTextGenerator::new(&context, include_bytes!("font1.ttf"), 0, 60.0).unwrap();
+    let text_mesh3 =
+        sdf_text_generator.generate("Crisp at any scale", TextLayoutOptions::default());
+    let mut text3 = Gm::new(
+        Mesh::new(&context, &text_mesh3),
+        TextMaterial {
+            color: Srgba::new(255, 200, 0, 255),
+            outline_color: Srgba::BLACK,
+            outline_width: 0.04,
+            shadow_color: Srgba::new(0, 0, 0, 128),
+            ..TextMaterial::new(&context, &sdf_text_generator)
+        },
+    );
+    text3.set_transformation(Mat4::from_translation(vec3(
+        50.0,
+        camera.viewport().height as f32 - 650.0,
+        0.0,
+    )));
+
     // Render loop
     window.render_loop(move |mut frame_input| {
         camera.set_viewport(frame_input.viewport);
@@ -78,7 +103,7 @@ pub fn main() {
         frame_input
             .screen()
             .clear(ClearState::color_and_depth(1.0, 1.0, 1.0, 1.0, 1.0))
-            .render(&camera, [&text0, &text1, &text2], &[]);
+            .render(&camera, [&text0, &text1, &text2, &text3], &[]);
         FrameOutput::default()
     });
 }
@@ -6,6 +6,20 @@ use std::sync::RwLock;
 #[doc(hidden)]
 pub use crate::context::HasContext;
 
+///
+/// Determines how the programs in [Context::programs] are identified, see [Context::set_program_cache_key].
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ProgramCacheKey {
+    /// Programs are identified by the shader IDs returned by the geometry, material/effect and lights.
+    /// This is the fastest option, but requires that each variation of a shader source has a unique ID.
+    #[default]
+    ShaderIds,
+    /// Programs are identified by the final vertex and fragment shader source, so two different programs can never collide.
+    /// This requires that the shader source is generated for each draw call, which has a small performance cost.
+    ShaderSource,
+}
+
 ///
 /// Contains the low-level OpenGL/WebGL graphics context as well as other "global" variables.
 /// Implements Deref with the low-level graphics context as target, so you can call low-level functionality
@@ -17,6 +31,7 @@ pub struct Context {
     pub(super) vao: crate::context::VertexArray,
     /// A cache of programs to avoid recompiling a [Program] every frame.
     pub programs: Arc<RwLock<HashMap<Vec<u8>, Program>>>,
+    program_cache_key: Arc<RwLock<ProgramCacheKey>>,
 }
 
 impl Context {
@@ -44,11 +59,27 @@ impl Context {
                 context,
                 vao,
                 programs: Arc::new(RwLock::new(HashMap::new())),
+                program_cache_key: Arc::new(RwLock::new(ProgramCacheKey::default())),
             }
         };
         Ok(c)
     }
 
+    ///
+    /// Sets how the programs in [Context::programs] are identified, see [ProgramCacheKey].
+    /// This applies to all clones of this context.
+    ///
+    pub fn set_program_cache_key(&self, key: ProgramCacheKey) {
+        *self.program_cache_key.write().unwrap() = key;
+    }
+
+    ///
+    /// Returns how the programs in [Context::programs] are identified, see [ProgramCacheKey].
+    ///
+    pub fn program_cache_key(&self) -> ProgramCacheKey {
+        *self.program_cache_key.read().unwrap()
+    }
+
     ///
     /// Set the scissor test for this context (see [ScissorBox]).
     ///
@@ -32,6 +32,14 @@ pub enum RendererError {
     #[cfg(feature = "text")]
     #[error("Failed to find font with index {0} in the given font collection")]
     MissingFont(u32),
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("failed reading or writing the point cloud octree {0} with error: {1}")]
+    PointCloudOctreeIo(String, std::io::Error),
+    #[cfg(not(target_arch = "wasm32"))]
+    #[error("the file {0} is not a valid point cloud octree")]
+    InvalidPointCloudOctree(String),
+    #[error("failed parsing Gaussian splats: {0}")]
+    GaussianSplatsParsing(String),
     #[error("CoreError: {0}")]
     CoreError(#[from] CoreError),
 }
@@ -92,18 +100,53 @@ macro_rules! impl_render_target_extensions_body {
             viewer: impl Viewer,
             objects: impl IntoIterator<Item = impl Object>,
             lights: &[&dyn Light],
+        ) -> &Self {
+            self.render_partially_with_decals(scissor_box, viewer, objects, &[], lights)
+        }
+
+        ///
+        /// Render the objects like [Self::render] and project the given decals onto the surfaces of the objects,
+        /// where [Decal::material_types] specifies which objects are affected by each decal.
+        /// For objects with a [MaterialType::Deferred] material, the decals are applied to the geometry buffer before the lighting pass.
+        /// For objects with a [MaterialType::Opaque] material, the decals are blended on top of the objects before the transparent objects are rendered.
+        ///
+        pub fn render_with_decals(
+            &self,
+            viewer: impl Viewer,
+            objects: impl IntoIterator<Item = impl Object>,
+            decals: &[&Decal],
+            lights: &[&dyn Light],
+        ) -> &Self {
+            self.render_partially_with_decals(self.scissor_box(), viewer, objects, decals, lights)
+        }
+
+        ///
+        /// Render the objects and decals like [Self::render_with_decals] into the part of this render target defined by the scissor box.
+        ///
+        pub fn render_partially_with_decals(
+            &self,
+            scissor_box: ScissorBox,
+            viewer: impl Viewer,
+            objects: impl IntoIterator<Item = impl Object>,
+            decals: &[&Decal],
+            lights: &[&dyn Light],
         ) -> &Self {
             let frustum = Frustum::new(viewer.projection() * viewer.view());
             let (mut deferred_objects, mut forward_objects): (Vec<_>, Vec<_>) = objects
                 .into_iter()
                 .filter(|o| frustum.contains(o.aabb()))
                 .partition(|o| o.material_type() == MaterialType::Deferred);
+            let decals = decals
+                .iter()
+                .filter(|d| frustum.contains(d.aabb()))
+                .collect::<Vec<_>>();
+            let geometry_pass_camera = GeometryPassCamera(&viewer);
+            let viewport = geometry_pass_camera.viewport();
 
             // Deferred
+            let mut deferred_depth_texture = None;
             if deferred_objects.len() > 0 {
                 // Geometry pass
-                let geometry_pass_camera = GeometryPassCamera(&viewer);
-                let viewport = geometry_pass_camera.viewport();
                 deferred_objects.sort_by(|a, b| cmp_render_order(&geometry_pass_camera, a, b));
                 let geometry_pass_texture = Texture2DArray::new_empty::<[u8; 4]>(
                     &self.context,
@@ -137,6 +180,33 @@ macro_rules! impl_render_target_extensions_body {
                 })
                 .unwrap();
 
+                // Decal pass
+                let deferred_decals = decals
+                    .iter()
+                    .filter(|d| d.material_types.contains(&MaterialType::Deferred))
+                    .collect::<Vec<_>>();
+                if deferred_decals.len() > 0 {
+                    let geometry_pass_texture_copy = Texture2DArray::new_empty::<[u8; 4]>(
+                        &self.context,
+                        viewport.width,
+                        viewport.height,
+                        3,
+                        Interpolation::Nearest,
+                        Interpolation::Nearest,
+                        None,
+                        Wrapping::ClampToEdge,
+                        Wrapping::ClampToEdge,
+                    );
+                    for decal in deferred_decals {
+                        decal.apply_to_geometry_buffer(
+                            &geometry_pass_camera,
+                            &geometry_pass_texture,
+                            &geometry_pass_texture_copy,
+                            &geometry_pass_depth_texture,
+                        );
+                    }
+                }
+
                 // Lighting pass
                 self.apply_screen_effect_partially(
                     scissor_box,
@@ -149,20 +219,115 @@ macro_rules! impl_render_target_extensions_body {
                     }),
                     Some(DepthTexture::Single(&geometry_pass_depth_texture)),
                 );
+                deferred_depth_texture = Some(geometry_pass_depth_texture);
             }
 
             // Forward
             forward_objects.sort_by(|a, b| cmp_render_order(&viewer, a, b));
-            self.write_partially::<RendererError>(scissor_box, || {
-                for object in forward_objects {
-                    object.render(&viewer, lights);
+            let forward_decals = decals
+                .iter()
+                .filter(|d| d.material_types.contains(&MaterialType::Opaque))
+                .collect::<Vec<_>>();
+            if forward_decals.len() > 0 {
+                let (opaque_objects, transparent_objects): (Vec<_>, Vec<_>) = forward_objects
+                    .into_iter()
+                    .partition(|o| o.material_type() == MaterialType::Opaque);
+                self.write_partially::<RendererError>(scissor_box, || {
+                    for object in opaque_objects.iter() {
+                        object.render(&viewer, lights);
+                    }
+                    Ok(())
+                })
+                .unwrap();
+
+                // Combine the depth of the deferred and opaque objects, so the decals can be projected onto both
+                let depth_texture = DepthTexture2D::new::<f32>(
+                    &self.context,
+                    viewport.width,
+                    viewport.height,
+                    Wrapping::ClampToEdge,
+                    Wrapping::ClampToEdge,
+                );
+                let depth_target = depth_texture.as_depth_target();
+                if let Some(deferred_depth_texture) = &deferred_depth_texture {
+                    depth_target.apply_screen_effect(
+                        &CopyEffect {
+                            write_mask: WriteMask::DEPTH,
+                            blend: Blend::Disabled,
+                        },
+                        &geometry_pass_camera,
+                        &[],
+                        None,
+                        Some(DepthTexture::Single(deferred_depth_texture)),
+                    );
+                } else {
+                    depth_target.clear(ClearState::depth(1.0));
                 }
-                Ok(())
-            })
-            .unwrap();
+                // Only the depth is needed, so the objects are rendered without shading
+                let depth_material = DepthMaterial {
+                    render_states: RenderStates {
+                        write_mask: WriteMask::DEPTH,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                };
+                depth_target
+                    .write::<RendererError>(|| {
+                        for object in opaque_objects.iter() {
+                            render_with_material(
+                                &self.context,
+                                &geometry_pass_camera,
+                                object,
+                                &depth_material,
+                                &[],
+                            )?;
+                        }
+                        Ok(())
+                    })
+                    .unwrap();
+
+                self.write_partially::<RendererError>(scissor_box, || {
+                    for decal in forward_decals {
+                        decal.render_forward(
+                            &viewer,
+                            &depth_texture,
+                            deferred_depth_texture.as_ref(),
+                            lights,
+                        );
+                    }
+                    for object in transparent_objects {
+                        object.render(&viewer, lights);
+                    }
+                    Ok(())
+                })
+                .unwrap();
+            } else {
+                self.write_partially::<RendererError>(scissor_box, || {
+                    for object in forward_objects {
+                        object.render(&viewer, lights);
+                    }
+                    Ok(())
+                })
+                .unwrap();
+            }
             self
         }
 
+        ///
+        /// Render the objects like [Self::render], except that the objects found to be hidden behind other objects by the given [OcclusionCuller] are not rendered.
+        /// The occlusion culler must be updated with the depth of the previous frame or a depth pre-pass before calling this method,
+        /// and the number of culled objects is available from [OcclusionCuller::stats] afterwards.
+        ///
+        pub fn render_with_occlusion_culling(
+            &self,
+            viewer: impl Viewer,
+            objects: impl IntoIterator<Item = impl Object>,
+            lights: &[&dyn Light],
+            occlusion_culler: &mut OcclusionCuller,
+        ) -> &Self {
+            self.render(viewer, occlusion_culler.cull(objects), lights)
+        }
+
         ///
         /// Render the geometries with the given [Material] using the given viewer and lights into this render target.
         /// Use an empty array for the `lights` argument, if the material does not require lights to be rendered.
@@ -405,6 +570,65 @@ fn combine_ids(
     id
 }
 
+///
+/// Returns the program from the program cache in the context, compiling it first if it is not already in the cache.
+/// The program is identified by the shader IDs, unless the context is set to use the shader source as key (see [ProgramCacheKey])
+/// or any of the shader IDs is the `FromSource` ID, in which case the program is identified by the vertex and fragment shader source.
+/// If `clipping` is specified, the fragment shader is wrapped with the clipping functionality, with or without capping depending on the value (see [Clipping]).
+///
+#[allow(clippy::too_many_arguments)]
+fn cached_program<'a>(
+    context: &Context,
+    programs: &'a mut std::collections::HashMap<Vec<u8>, Program>,
+    geometry: GeometryId,
+    effect_material: EffectMaterialId,
+    lights: &[&dyn Light],
+    clipping: Option<bool>,
+    vertex_shader_source: impl FnOnce() -> String,
+    fragment_shader_source: impl FnOnce() -> String,
+) -> Result<&'a Program, RendererError> {
+    let fragment_shader_source = || match clipping {
+        Some(capping) => clipping_fragment_shader_source(fragment_shader_source(), capping),
+        None => fragment_shader_source(),
+    };
+    let light_ids = lights.iter().map(|l| l.id()).collect::<Vec<_>>();
+    let key_by_source = context.program_cache_key() == ProgramCacheKey::ShaderSource
+        || geometry == GeometryId::FromSource
+        || effect_material == EffectMaterialId::FromSource
+        || light_ids.contains(&LightId::FromSource);
+    let id = if key_by_source {
+        let vertex_shader_source = vertex_shader_source();
+        let fragment_shader_source = fragment_shader_source();
+        // The shader ID keys never start with the FromSource geometry ID, so this cannot collide with a shader ID key
+        let mut id = GeometryId::FromSource.0.to_le_bytes().to_vec();
+        id.extend(vertex_shader_source.as_bytes());
+        id.push(0);
+        id.extend(fragment_shader_source.as_bytes());
+        if !programs.contains_key(&id) {
+            programs.insert(
+                id.clone(),
+                Program::from_source(context, &vertex_shader_source, &fragment_shader_source)?,
+            );
+        }
+        id
+    } else {
+        let mut id = combine_ids(geometry, effect_material, light_ids.into_iter());
+        if let Some(capping) = clipping {
+            // A key with a FromSource light ID is never used as a shader ID key, so this cannot collide with a key without clipping
+            id.push(LightId::FromSource.0);
+            id.push(if capping { 1 } else { 0 });
+        }
+        if !programs.contains_key(&id) {
+            programs.insert(
+                id.clone(),
+                Program::from_source(context, &vertex_shader_source(), &fragment_shader_source())?,
+            );
+        }
+        id
+    };
+    Ok(programs.get(&id).unwrap())
+}
+
 ///
 /// Render the given [Geometry] with the given [Material].
 /// Must be called in the callback given as input to a [RenderTarget], [ColorTarget] or [DepthTarget] write method.
@@ -417,23 +641,31 @@ pub fn render_with_material(
     material: impl Material,
     lights: &[&dyn Light],
 ) -> Result<(), RendererError> {
-    let id = combine_ids(geometry.id(), material.id(), lights.iter().map(|l| l.id()));
-
-    let mut programs = context.programs.write().unwrap();
-    if !programs.contains_key(&id) {
-        programs.insert(
-            id.clone(),
-            Program::from_source(
-                context,
-                &geometry.vertex_shader_source(),
-                &material.fragment_shader_source(lights),
-            )?,
-        );
+    let clipping = viewer.clipping();
+    let clipped = is_clipped(&clipping, &geometry.aabb());
+    let mut render_states = material.render_states();
+    let capping = clipped
+        && clipping.cap_color.is_some()
+        && material.material_type() == MaterialType::Opaque
+        && render_states.cull != Cull::Front;
+    if capping {
+        render_states.cull = Cull::None;
     }
-    let program = programs.get(&id).unwrap();
+    let mut programs = context.programs.write().unwrap();
+    let program = cached_program(
+        context,
+        &mut programs,
+        geometry.id(),
+        material.id(),
+        lights,
+        if clipped { Some(capping) } else { None },
+        || geometry.vertex_shader_source(),
+        || material.fragment_shader_source(lights),
+    )?;
 
     material.use_uniforms(program, &viewer, lights);
-    geometry.draw(&viewer, program, material.render_states());
+    use_clipping_uniforms(program, &viewer);
+    geometry.draw(&viewer, program, render_states);
     Ok(())
 }
 
@@ -451,25 +683,20 @@ pub fn render_with_effect(
     color_texture: Option<ColorTexture>,
     depth_texture: Option<DepthTexture>,
 ) -> Result<(), RendererError> {
-    let id = combine_ids(
+    let clipped = is_clipped(&viewer.clipping(), &geometry.aabb());
+    let mut programs = context.programs.write().unwrap();
+    let program = cached_program(
+        context,
+        &mut programs,
         geometry.id(),
         effect.id(color_texture, depth_texture),
-        lights.iter().map(|l| l.id()),
-    );
-
-    let mut programs = context.programs.write().unwrap();
-    if !programs.contains_key(&id) {
-        programs.insert(
-            id.clone(),
-            Program::from_source(
-                context,
-                &geometry.vertex_shader_source(),
-                &effect.fragment_shader_source(lights, color_texture, depth_texture),
-            )?,
-        );
-    }
-    let program = programs.get(&id).unwrap();
+        lights,
+        if clipped { Some(false) } else { None },
+        || geometry.vertex_shader_source(),
+        || effect.fragment_shader_source(lights, color_texture, depth_texture),
+    )?;
     effect.use_uniforms(program, &viewer, lights, color_texture, depth_texture);
+    use_clipping_uniforms(program, &viewer);
     geometry.draw(&viewer, program, effect.render_states());
     Ok(())
 }
@@ -485,23 +712,20 @@ pub fn apply_screen_material(
     viewer: impl Viewer,
     lights: &[&dyn Light],
 ) {
-    let id = combine_ids(
+    let mut programs = context.programs.write().unwrap();
+    let program = match cached_program(
+        context,
+        &mut programs,
         GeometryId::Screen,
         material.id(),
-        lights.iter().map(|l| l.id()),
-    );
-
-    let mut programs = context.programs.write().unwrap();
-    let program = programs.entry(id).or_insert_with(|| {
-        match Program::from_source(
-            context,
-            full_screen_vertex_shader_source(),
-            &material.fragment_shader_source(lights),
-        ) {
-            Ok(program) => program,
-            Err(err) => panic!("{}", err.to_string()),
-        }
-    });
+        lights,
+        None,
+        || full_screen_vertex_shader_source().to_string(),
+        || material.fragment_shader_source(lights),
+    ) {
+        Ok(program) => program,
+        Err(err) => panic!("{}", err.to_string()),
+    };
     material.use_uniforms(program, &viewer, lights);
     full_screen_draw(
         context,
@@ -524,23 +748,20 @@ pub fn apply_screen_effect(
     color_texture: Option<ColorTexture>,
     depth_texture: Option<DepthTexture>,
 ) {
-    let id = combine_ids(
+    let mut programs = context.programs.write().unwrap();
+    let program = match cached_program(
+        context,
+        &mut programs,
         GeometryId::Screen,
         effect.id(color_texture, depth_texture),
-        lights.iter().map(|l| l.id()),
-    );
-
-    let mut programs = context.programs.write().unwrap();
-    let program = programs.entry(id).or_insert_with(|| {
-        match Program::from_source(
-            context,
-            full_screen_vertex_shader_source(),
-            &effect.fragment_shader_source(lights, color_texture, depth_texture),
-        ) {
-            Ok(program) => program,
-            Err(err) => panic!("{}", err.to_string()),
-        }
-    });
+        lights,
+        None,
+        || full_screen_vertex_shader_source().to_string(),
+        || effect.fragment_shader_source(lights, color_texture, depth_texture),
+    ) {
+        Ok(program) => program,
+        Err(err) => panic!("{}", err.to_string()),
+    };
     effect.use_uniforms(program, &viewer, lights, color_texture, depth_texture);
     full_screen_draw(context, program, effect.render_states(), viewer.viewport());
 }
@@ -581,23 +802,25 @@ pub fn cmp_render_order(
 /// The pixel coordinate must be in physical pixels, where (viewport.x, viewport.y) indicate the bottom left corner of the viewport
 /// and (viewport.x + viewport.width, viewport.y + viewport.height) indicate the top right corner.
 /// Returns ```None``` if no geometry was hit between the near (`z_near`) and far (`z_far`) plane for this camera.
+/// The [Camera::clipping] is applied, so geometry that is clipped away is not hit.
 ///
 pub fn pick(
     context: &Context,
-    camera: &three_d_asset::Camera,
+    camera: &Camera,
     pixel: impl Into<PhysicalPoint> + Copy,
     geometries: impl IntoIterator<Item = impl Geometry>,
     culling: Cull,
 ) -> Result<Option<IntersectionResult>, RendererError> {
     let pos = camera.position_at_pixel(pixel);
     let dir = camera.view_direction_at_pixel(pixel);
-    ray_intersect(
+    ray_intersect_with_clipping(
         context,
         pos + dir * camera.z_near(),
         dir,
         camera.z_far() - camera.z_near(),
         geometries,
         culling,
+        &camera.clipping,
     )
 }
 
@@ -624,6 +847,29 @@ pub fn ray_intersect(
     max_depth: f32,
     geometries: impl IntoIterator<Item = impl Geometry>,
     culling: Cull,
+) -> Result<Option<IntersectionResult>, RendererError> {
+    ray_intersect_with_clipping(
+        context,
+        position,
+        direction,
+        max_depth,
+        geometries,
+        culling,
+        &Clipping::default(),
+    )
+}
+
+///
+/// Finds the closest intersection like [ray_intersect], except that the geometry that is clipped away by the given [Clipping] is not hit.
+///
+pub fn ray_intersect_with_clipping(
+    context: &Context,
+    position: Vec3,
+    direction: Vec3,
+    max_depth: f32,
+    geometries: impl IntoIterator<Item = impl Geometry>,
+    culling: Cull,
+    clipping: &Clipping,
 ) -> Result<Option<IntersectionResult>, RendererError> {
     use crate::core::*;
     let viewport = Viewport::new_at_origo(1, 1);
@@ -632,7 +878,7 @@ pub fn ray_intersect(
     } else {
         direction.cross(vec3(1.0, 0.0, 0.0))
     };
-    let camera = Camera::new_orthographic(
+    let mut camera = Camera::new_orthographic(
         viewport,
         position,
         position + direction,
@@ -641,6 +887,7 @@ pub fn ray_intersect(
         0.0,
         max_depth,
     );
+    camera.clipping = clipping.clone();
     let texture = Texture2D::new_empty::<[f32; 4]>(
         context,
         viewport.width,
@@ -687,6 +934,151 @@ pub fn ray_intersect(
     }
 }
 
+///
+/// The maximum number of depth layers that are searched by [pick_region] when including occluded geometries.
+///
+pub const MAX_PICK_REGION_LAYERS: usize = 16;
+
+///
+/// Finds all geometries and instances that are visible inside the given rectangle as seen from the given camera, for example for a rubber-band selection.
+/// The rectangle must be in physical pixels, similar to the pixel given to [pick].
+/// Returns the set of unique `(geometry_id, instance_id)` pairs, where the geometry ID is the index of the geometry in the given list of geometries
+/// and the instance ID is the [gl_InstanceID](https://registry.khronos.org/OpenGL-Refpages/gl4/html/gl_InstanceID.xhtml), which is 0 for geometries that are not instanced.
+///
+/// If `include_occluded` is true, geometries and instances that are inside the rectangle but hidden behind other geometries are also returned.
+/// These are found by repeatedly rendering the geometries while removing the closest surface in each pixel (depth peeling),
+/// which is done at most [MAX_PICK_REGION_LAYERS] times.
+/// The [Camera::clipping] is applied, so geometry that is clipped away is not returned.
+///
+pub fn pick_region(
+    context: &Context,
+    camera: &Camera,
+    region: impl Into<ScissorBox>,
+    geometries: impl IntoIterator<Item = impl Geometry>,
+    culling: Cull,
+    include_occluded: bool,
+) -> Result<std::collections::HashSet<(u32, u32)>, RendererError> {
+    use crate::core::*;
+    let mut result = std::collections::HashSet::new();
+    let region = region.into().intersection(camera.viewport());
+    if region.width == 0 || region.height == 0 {
+        return Ok(result);
+    }
+    let geometries = geometries.into_iter().collect::<Vec<_>>();
+    let viewer = RegionCamera {
+        camera,
+        viewport: Viewport {
+            x: camera.viewport().x - region.x,
+            y: camera.viewport().y - region.y,
+            width: camera.viewport().width,
+            height: camera.viewport().height,
+        },
+    };
+    let new_texture = || {
+        Texture2D::new_empty::<[f32; 4]>(
+            context,
+            region.width,
+            region.height,
+            Interpolation::Nearest,
+            Interpolation::Nearest,
+            None,
+            Wrapping::ClampToEdge,
+            Wrapping::ClampToEdge,
+        )
+    };
+    let layers = [new_texture(), new_texture()];
+    let depth_texture = DepthTexture2D::new::<f32>(
+        context,
+        region.width,
+        region.height,
+        Wrapping::ClampToEdge,
+        Wrapping::ClampToEdge,
+    );
+    let layer_count = if include_occluded {
+        MAX_PICK_REGION_LAYERS
+    } else {
+        1
+    };
+    for layer in 0..layer_count {
+        let previous = &layers[(layer + 1) % 2];
+        let current = &layers[layer % 2];
+        let mut material = RegionPickMaterial {
+            render_states: RenderStates {
+                cull: culling,
+                ..Default::default()
+            },
+            geometry_id: 0,
+            previous_layer: previous,
+            peel: layer > 0,
+        };
+        let pixels = RenderTarget::new(
+            current.as_color_target(None),
+            depth_texture.as_depth_target(),
+        )
+        .clear(ClearState::color_and_depth(f32::MAX, 0.0, 0.0, 0.0, 1.0))
+        .write::<RendererError>(|| {
+            for (id, geometry) in geometries.iter().enumerate() {
+                material.geometry_id = id as u32;
+                render_with_material(context, &viewer, geometry, &material, &[])?;
+            }
+            Ok(())
+        })?
+        .read_color::<[f32; 4]>();
+        let mut hit = false;
+        for pixel in pixels.iter().filter(|p| p[3] > 0.5) {
+            hit = true;
+            result.insert((pixel[1].to_bits(), pixel[2].to_bits()));
+        }
+        if !hit {
+            break;
+        }
+    }
+    Ok(result)
+}
+
+struct RegionCamera<'a> {
+    camera: &'a Camera,
+    viewport: Viewport,
+}
+
+impl Viewer for RegionCamera<'_> {
+    fn position(&self) -> Vec3 {
+        self.camera.position()
+    }
+
+    fn view(&self) -> Mat4 {
+        self.camera.view()
+    }
+
+    fn projection(&self) -> Mat4 {
+        self.camera.projection()
+    }
+
+    fn viewport(&self) -> Viewport {
+        self.viewport
+    }
+
+    fn z_near(&self) -> f32 {
+        self.camera.z_near()
+    }
+
+    fn z_far(&self) -> f32 {
+        self.camera.z_far()
+    }
+
+    fn color_mapping(&self) -> ColorMapping {
+        ColorMapping::None
+    }
+
+    fn tone_mapping(&self) -> ToneMapping {
+        ToneMapping::None
+    }
+
+    fn clipping(&self) -> Clipping {
+        self.camera.clipping()
+    }
+}
+
 struct GeometryPassCamera<T>(T);
 
 impl<T: Viewer> Viewer for GeometryPassCamera<T> {
@@ -721,4 +1113,8 @@ impl<T: Viewer> Viewer for GeometryPassCamera<T> {
     fn tone_mapping(&self) -> ToneMapping {
         self.0.tone_mapping()
     }
+
+    fn clipping(&self) -> Clipping {
+        self.0.clipping()
+    }
 }
@@ -44,6 +44,10 @@ mod fog;
 #[doc(inline)]
 pub use fog::*;
 
+mod eye_dome_lighting;
+#[doc(inline)]
+pub use eye_dome_lighting::*;
+
 mod copy;
 #[doc(inline)]
 pub use copy::*;
@@ -56,10 +60,18 @@ mod fxaa;
 #[doc(inline)]
 pub use fxaa::*;
 
+mod volumetric_light;
+#[doc(inline)]
+pub use volumetric_light::*;
+
 mod water;
 #[doc(inline)]
 pub use water::*;
 
+mod selection_outline;
+#[doc(inline)]
+pub use selection_outline::*;
+
 pub(crate) mod lighting_pass;
 
 use crate::renderer::*;
@@ -85,12 +97,16 @@ pub trait Effect {
     ///
     /// **Note:** The first 16 bits are reserved to internally implemented effects, so if implementing the [Effect] trait
     /// outside of this crate, always return an id in the public use range as defined by [EffectMaterialId].
+    /// The default implementation returns [EffectMaterialId::FromSource] which means that the shader is identified by its source instead,
+    /// at the cost of generating the shader source for each draw call.
     ///
     fn id(
         &self,
-        color_texture: Option<ColorTexture>,
-        depth_texture: Option<DepthTexture>,
-    ) -> EffectMaterialId;
+        _color_texture: Option<ColorTexture>,
+        _depth_texture: Option<DepthTexture>,
+    ) -> EffectMaterialId {
+        EffectMaterialId::FromSource
+    }
 
     ///
     /// Sends the uniform data needed for this effect to the fragment shader.
@@ -0,0 +1,95 @@
+use crate::renderer::*;
+
+///
+/// An effect which shades a scene using only the depth, so the shape of unlit geometry, typically a scanned point cloud rendered with [PointCloudGeometry], is easier to see.
+/// Each pixel is darkened depending on how much closer to the viewer the surrounding pixels are,
+/// which gives the edges of nearby surfaces a dark outline and makes the surfaces behind appear shaded.
+///
+/// Must be applied with both a color and a depth texture, for example the color and depth textures of a [RenderTarget] that the scene has been rendered into.
+///
+#[derive(Clone, Debug)]
+pub struct EyeDomeLightingEffect {
+    /// How dark the shading is.
+    pub strength: f32,
+    /// The distance in physical pixels to the surrounding pixels used for calculating the shading. A larger radius gives wider outlines.
+    pub radius: f32,
+}
+
+impl Default for EyeDomeLightingEffect {
+    fn default() -> Self {
+        Self {
+            strength: 1.0,
+            radius: 1.4,
+        }
+    }
+}
+
+impl Effect for EyeDomeLightingEffect {
+    fn fragment_shader_source(
+        &self,
+        _lights: &[&dyn Light],
+        color_texture: Option<ColorTexture>,
+        depth_texture: Option<DepthTexture>,
+    ) -> String {
+        format!(
+            "{}\n{}\n{}\n{}",
+            include_str!("../../core/shared.frag"),
+            color_texture
+                .expect("Must supply a color texture to apply an eye-dome lighting effect")
+                .fragment_shader_source(),
+            depth_texture
+                .expect("Must supply a depth texture to apply an eye-dome lighting effect")
+                .fragment_shader_source(),
+            include_str!("shaders/eye_dome_lighting_effect.frag")
+        )
+    }
+
+    fn id(
+        &self,
+        color_texture: Option<ColorTexture>,
+        depth_texture: Option<DepthTexture>,
+    ) -> EffectMaterialId {
+        EffectMaterialId::EyeDomeLightingEffect(
+            color_texture
+                .expect("Must supply a color texture to apply an eye-dome lighting effect"),
+            depth_texture
+                .expect("Must supply a depth texture to apply an eye-dome lighting effect"),
+        )
+    }
+
+    fn use_uniforms(
+        &self,
+        program: &Program,
+        viewer: &dyn Viewer,
+        _lights: &[&dyn Light],
+        color_texture: Option<ColorTexture>,
+        depth_texture: Option<DepthTexture>,
+    ) {
+        color_texture
+            .expect("Must supply a color texture to apply an eye-dome lighting effect")
+            .use_uniforms(program);
+        depth_texture
+            .expect("Must supply a depth texture to apply an eye-dome lighting effect")
+            .use_uniforms(program);
+        let viewport = viewer.viewport();
+        program.use_uniform(
+            "viewProjectionInverse",
+            (viewer.projection() * viewer.view()).invert().unwrap(),
+        );
+        program.use_uniform("eyePosition", viewer.position());
+        program.use_uniform(
+            "pixelSize",
+            vec2(1.0 / viewport.width as f32, 1.0 / viewport.height as f32),
+        );
+        program.use_uniform("radius", self.radius);
+        program.use_uniform("strength", self.strength);
+    }
+
+    fn render_states(&self) -> RenderStates {
+        RenderStates {
+            depth_test: DepthTest::Always,
+            cull: Cull::Back,
+            ..Default::default()
+        }
+    }
+}
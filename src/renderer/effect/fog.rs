@@ -1,5 +1,63 @@
 use crate::renderer::*;
 
+///
+/// Determines how the amount of fog is calculated from the distance to the viewer and, for [FogMode::ExponentialHeight], the height.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum FogMode {
+    /// The fog increases linearly from no fog at the `start` distance to full fog at the `end` distance.
+    Linear {
+        /// The distance from the viewer where the fog starts.
+        start: f32,
+        /// The distance from the viewer where the fog completely covers the scene.
+        end: f32,
+    },
+    /// The amount of fog is `1 - exp(-density * distance)`.
+    Exponential,
+    /// The amount of fog is `1 - exp(-(density * distance)^2)`.
+    #[default]
+    ExponentialSquared,
+    /// The density of the fog is [FogEffect::density] at the base height and decreases exponentially with the height above it,
+    /// which is useful for example for fog in valleys.
+    /// The amount of fog is calculated by integrating the density along the line between the viewer and the surface.
+    ExponentialHeight {
+        /// The height (y-coordinate) where the density of the fog is [FogEffect::density].
+        base_height: f32,
+        /// How fast the density decreases with the height above the base height.
+        falloff: f32,
+    },
+}
+
+///
+/// Light from a directional light, typically the sun, which is scattered towards the viewer by the fog, see [FogEffect::in_scattering].
+/// The fog appears brighter, with the color of the light, when looking towards the light.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FogInScattering {
+    /// The direction the light shines.
+    pub direction: Vec3,
+    /// The color of the light.
+    pub color: Srgba,
+    /// The intensity of the light.
+    pub intensity: f32,
+    /// Determines how concentrated the scattered light is around the direction towards the light. Higher values give a smaller glow.
+    pub exponent: f32,
+}
+
+impl FogInScattering {
+    ///
+    /// Constructs the in-scattering from the direction, color and intensity of the given directional light.
+    ///
+    pub fn from_light(light: &DirectionalLight) -> Self {
+        Self {
+            direction: light.direction,
+            color: light.color,
+            intensity: light.intensity,
+            exponent: 8.0,
+        }
+    }
+}
+
 ///
 /// An effect that simulates fog, ie. the area where it is applied gets hazy when objects are far away.
 ///
@@ -9,6 +67,10 @@ pub struct FogEffect {
     pub color: Srgba,
     /// The density of the fog.
     pub density: f32,
+    /// Determines how the amount of fog is calculated.
+    pub mode: FogMode,
+    /// Optional light which is scattered towards the viewer by the fog.
+    pub in_scattering: Option<FogInScattering>,
     /// Determines the variation on the density as a function of time.
     pub animation: f32,
     /// The time used for the animation.
@@ -20,6 +82,8 @@ impl Default for FogEffect {
         Self {
             color: Srgba::WHITE,
             density: 0.2,
+            mode: FogMode::default(),
+            in_scattering: None,
             animation: 1.0,
             time: 0.0,
         }
@@ -81,6 +145,29 @@ impl Effect for FogEffect {
         );
         program.use_uniform("fogColor", Vec4::from(self.color));
         program.use_uniform("fogDensity", self.density);
+        let (mode, parameters): (i32, Vec2) = match self.mode {
+            FogMode::Linear { start, end } => (0, vec2(start, end.max(start + 0.0001))),
+            FogMode::Exponential => (1, vec2(0.0, 0.0)),
+            FogMode::ExponentialSquared => (2, vec2(0.0, 0.0)),
+            FogMode::ExponentialHeight {
+                base_height,
+                falloff,
+            } => (3, vec2(base_height, falloff.max(0.0))),
+        };
+        program.use_uniform("fogMode", mode);
+        program.use_uniform("fogParameters", parameters);
+        let in_scattering = self.in_scattering.unwrap_or(FogInScattering {
+            direction: vec3(0.0, -1.0, 0.0),
+            color: Srgba::BLACK,
+            intensity: 0.0,
+            exponent: 1.0,
+        });
+        program.use_uniform("sunDirection", -in_scattering.direction.normalize());
+        program.use_uniform(
+            "sunColor",
+            in_scattering.color.to_linear_srgb().truncate() * in_scattering.intensity,
+        );
+        program.use_uniform("sunExponent", in_scattering.exponent.max(0.0));
         program.use_uniform("animation", self.animation);
         program.use_uniform("time", 0.001 * self.time);
         program.use_uniform("eyePosition", viewer.position());
@@ -0,0 +1,229 @@
+use crate::core::*;
+use crate::renderer::*;
+
+///
+/// Identifies a selected geometry, or a single instance of an instanced geometry, for example the result of [pick].
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Selection {
+    /// The index of the selected geometry in the list of geometries given to [SelectionOutlineEffect::update].
+    pub geometry_id: u32,
+    /// The index of the selected instance, ie. [gl_InstanceID](https://registry.khronos.org/OpenGL-Refpages/gl4/html/gl_InstanceID.xhtml).
+    /// If `None`, all instances of the geometry are selected. Use `Some(0)` or `None` for geometries that are not instanced.
+    pub instance_id: Option<u32>,
+}
+
+impl Selection {
+    ///
+    /// Selects the entire geometry with the given ID, including all of its instances.
+    ///
+    pub fn geometry(geometry_id: u32) -> Self {
+        Self {
+            geometry_id,
+            instance_id: None,
+        }
+    }
+
+    ///
+    /// Selects a single instance of the geometry with the given ID.
+    ///
+    pub fn instance(geometry_id: u32, instance_id: u32) -> Self {
+        Self {
+            geometry_id,
+            instance_id: Some(instance_id),
+        }
+    }
+}
+
+impl From<IntersectionResult> for Selection {
+    fn from(result: IntersectionResult) -> Self {
+        Self::instance(result.geometry_id, result.instance_id)
+    }
+}
+
+///
+/// An effect that draws an outline around a set of selected geometries, or selected instances of instanced geometries, on top of an already rendered image.
+/// The selected geometries are first rendered into a mask texture using [SelectionOutlineEffect::update],
+/// then the effect is applied to the screen, for example using [RenderTarget::apply_screen_effect] with no color or depth texture.
+/// The outline is visible even if the selected geometries are occluded by other geometries.
+///
+pub struct SelectionOutlineEffect {
+    context: Context,
+    mask: Texture2D,
+    /// The color of the outline.
+    pub color: Srgba,
+    /// The width of the outline in pixels.
+    pub width: f32,
+    /// The number of pulses per second. If `None`, the outline does not pulse.
+    pub pulse_frequency: Option<f32>,
+    /// The time in milliseconds used for the pulse animation.
+    pub time: f32,
+}
+
+impl SelectionOutlineEffect {
+    ///
+    /// Constructs a new selection outline effect with an empty selection.
+    ///
+    pub fn new(context: &Context) -> Self {
+        Self {
+            context: context.clone(),
+            mask: new_mask_texture(context, 1, 1),
+            color: Srgba::new(255, 165, 0, 255),
+            width: 3.0,
+            pulse_frequency: None,
+            time: 0.0,
+        }
+    }
+
+    ///
+    /// Renders the selected geometries into the selection mask as seen from the given viewer.
+    /// The geometry IDs of the selection refer to the index of a geometry in the given list of geometries, similar to [pick].
+    /// Must be called each time the selection, the geometries or the viewer changes.
+    ///
+    pub fn update(
+        &mut self,
+        viewer: impl Viewer,
+        geometries: impl IntoIterator<Item = impl Geometry>,
+        selection: &[Selection],
+    ) {
+        let viewport = viewer.viewport();
+        if self.mask.width() != viewport.width || self.mask.height() != viewport.height {
+            self.mask = new_mask_texture(&self.context, viewport.width, viewport.height);
+        }
+        let viewer = GeometryPassCamera(&viewer);
+        let mut material = SelectionMaskMaterial::default();
+        self.mask
+            .as_color_target(None)
+            .clear(ClearState::color(0.0, 0.0, 0.0, 0.0))
+            .write::<RendererError>(|| {
+                for (id, geometry) in geometries.into_iter().enumerate() {
+                    material.geometry_id = id as u32;
+                    for s in selection.iter().filter(|s| s.geometry_id == id as u32) {
+                        material.instance_id = s.instance_id;
+                        render_with_material(&self.context, &viewer, &geometry, &material, &[])?;
+                    }
+                }
+                Ok(())
+            })
+            .unwrap();
+    }
+
+    ///
+    /// Clears the selection mask, so that no outline is drawn.
+    ///
+    pub fn clear(&mut self) {
+        self.mask
+            .as_color_target(None)
+            .clear(ClearState::color(0.0, 0.0, 0.0, 0.0));
+    }
+
+    ///
+    /// Returns the selection mask.
+    /// Each pixel covered by a selected geometry contains `1.0` in the red channel, the geometry ID in the green channel and the instance ID in the blue channel,
+    /// encoded the same way as for the [IntersectionMaterial].
+    ///
+    pub fn mask(&self) -> &Texture2D {
+        &self.mask
+    }
+}
+
+fn new_mask_texture(context: &Context, width: u32, height: u32) -> Texture2D {
+    Texture2D::new_empty::<[f32; 4]>(
+        context,
+        width,
+        height,
+        Interpolation::Nearest,
+        Interpolation::Nearest,
+        None,
+        Wrapping::ClampToEdge,
+        Wrapping::ClampToEdge,
+    )
+}
+
+impl Effect for SelectionOutlineEffect {
+    fn fragment_shader_source(
+        &self,
+        _lights: &[&dyn Light],
+        _color_texture: Option<ColorTexture>,
+        _depth_texture: Option<DepthTexture>,
+    ) -> String {
+        format!(
+            "{}\n{}",
+            ColorMapping::fragment_shader_source(),
+            include_str!("shaders/selection_outline_effect.frag")
+        )
+    }
+
+    fn id(
+        &self,
+        _color_texture: Option<ColorTexture>,
+        _depth_texture: Option<DepthTexture>,
+    ) -> EffectMaterialId {
+        EffectMaterialId::SelectionOutlineEffect
+    }
+
+    fn use_uniforms(
+        &self,
+        program: &Program,
+        viewer: &dyn Viewer,
+        _lights: &[&dyn Light],
+        _color_texture: Option<ColorTexture>,
+        _depth_texture: Option<DepthTexture>,
+    ) {
+        viewer.color_mapping().use_uniforms(program);
+        let pulse = self
+            .pulse_frequency
+            .map(|f| 0.625 + 0.375 * (2.0 * std::f32::consts::PI * f * 0.001 * self.time).cos())
+            .unwrap_or(1.0);
+        program.use_texture("maskTexture", &self.mask);
+        program.use_uniform("outlineColor", self.color.to_linear_srgb());
+        program.use_uniform("outlineWidth", self.width.max(0.0));
+        program.use_uniform("pulse", pulse);
+    }
+
+    fn render_states(&self) -> RenderStates {
+        RenderStates {
+            write_mask: WriteMask::COLOR,
+            depth_test: DepthTest::Always,
+            blend: Blend::TRANSPARENCY,
+            cull: Cull::Back,
+        }
+    }
+}
+
+#[derive(Default)]
+struct SelectionMaskMaterial {
+    geometry_id: u32,
+    instance_id: Option<u32>,
+}
+
+impl Material for SelectionMaskMaterial {
+    fn id(&self) -> EffectMaterialId {
+        EffectMaterialId::SelectionMaskMaterial
+    }
+
+    fn fragment_shader_source(&self, _lights: &[&dyn Light]) -> String {
+        include_str!("shaders/selection_mask_material.frag").to_string()
+    }
+
+    fn use_uniforms(&self, program: &Program, _viewer: &dyn Viewer, _lights: &[&dyn Light]) {
+        program.use_uniform("geometryId", self.geometry_id);
+        program.use_uniform(
+            "selectedInstance",
+            self.instance_id.map(|i| i as i32).unwrap_or(-1),
+        );
+    }
+
+    fn render_states(&self) -> RenderStates {
+        RenderStates {
+            write_mask: WriteMask::COLOR,
+            depth_test: DepthTest::Always,
+            cull: Cull::None,
+            ..Default::default()
+        }
+    }
+
+    fn material_type(&self) -> MaterialType {
+        MaterialType::Opaque
+    }
+}
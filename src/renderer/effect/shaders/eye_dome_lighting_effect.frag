@@ -0,0 +1,40 @@
+uniform mat4 viewProjectionInverse;
+uniform vec3 eyePosition;
+uniform vec2 pixelSize;
+uniform float radius;
+uniform float strength;
+
+in vec2 uvs;
+
+layout (location = 0) out vec4 outColor;
+
+float log_distance(vec2 uv) {
+    float depth = sample_depth(uv);
+    if (depth > 0.99999) {
+        return -1.0;
+    }
+    return log2(max(distance(world_pos_from_depth(viewProjectionInverse, depth, uv), eyePosition), 1e-6));
+}
+
+void main()
+{
+    vec4 color = sample_color(uvs);
+    float center = log_distance(uvs);
+    if (center < -0.5) {
+        outColor = color;
+        return;
+    }
+
+    // Sum how much closer the neighbours are to the eye, so the edges of nearby surfaces cast a shadow on the surfaces behind
+    float response = 0.0;
+    for (int i = 0; i < 8; i++) {
+        float angle = 0.785398 * float(i);
+        vec2 offset = radius * pixelSize * vec2(cos(angle), sin(angle));
+        float neighbour = log_distance(uvs + offset);
+        if (neighbour > -0.5) {
+            response += max(0.0, center - neighbour);
+        }
+    }
+    float shade = exp(-100.0 * strength * response / 8.0);
+    outColor = vec4(color.rgb * shade, color.a);
+}
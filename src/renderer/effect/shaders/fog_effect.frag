@@ -4,6 +4,11 @@ uniform mat4 viewProjectionInverse;
 uniform float time;
 uniform float fogDensity;
 uniform vec4 fogColor;
+uniform int fogMode;
+uniform vec2 fogParameters;
+uniform vec3 sunDirection;
+uniform vec3 sunColor;
+uniform float sunExponent;
 uniform float animation;
 uniform vec3 eyePosition;
 
@@ -124,26 +129,53 @@ float snoise(vec3 v)
     return 42.0 * dot(m4, pdotx);
 }
 
+// The optical depth of the fog between the eye and the given position
+float optical_depth(vec3 pos, float dist)
+{
+    if (fogMode == 0) {
+        // Linear
+        return -log(1.0 - clamp((dist - fogParameters.x) / (fogParameters.y - fogParameters.x), 0.0, 0.9999));
+    } else if (fogMode == 1) {
+        // Exponential
+        return dist * fogDensity;
+    } else if (fogMode == 2) {
+        // Exponential squared
+        float x = dist * fogDensity;
+        return x * x;
+    }
+    // Exponential height, the density is integrated along the line from the eye to the position
+    float base_height = fogParameters.x;
+    float falloff = fogParameters.y;
+    float delta = falloff * (pos.y - eyePosition.y);
+    float integral = abs(delta) > 0.0001 ? (1.0 - exp(-delta)) / delta : 1.0;
+    return fogDensity * dist * exp(-falloff * (eyePosition.y - base_height)) * integral;
+}
+
 // factor: 1 == full fog, 0 == no fog
 void main()
 {
     vec4 color = sample_color(uvs);
     float depth = sample_depth(uvs);
     vec3 pos = world_pos_from_depth(viewProjectionInverse, depth, uvs);
+    vec3 view_direction = normalize(pos - eyePosition);
 
     // Distance
     float dist = depth < 0.999f ? distance(pos, eyePosition) : 100.f;
+    pos = eyePosition + dist * view_direction;
 
-    float x = dist * fogDensity;
-    float factor = 1. - 1. / exp(x * x);
+    float factor = 1. - exp(-optical_depth(pos, dist));
 
     // Noise
     float n = snoise(pos);
     factor *=  (1. + animation * n * cos(time));
     factor = clamp(factor, 0., 1.);
 
+    // In-scattering
+    float sun_amount = pow(max(dot(view_direction, sunDirection), 0.0), sunExponent);
+    vec4 fog_color = vec4(fogColor.rgb + sunColor * sun_amount, fogColor.a);
+
     // Output
-    outColor = mix(color, fogColor, factor);
+    outColor = mix(color, fog_color, factor);
     outColor.rgb = tone_mapping(outColor.rgb);
     outColor.rgb = color_mapping(outColor.rgb);
     gl_FragDepth = depth;
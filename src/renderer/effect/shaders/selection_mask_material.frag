@@ -0,0 +1,15 @@
+
+uniform uint geometryId;
+uniform int selectedInstance;
+
+flat in int instance_id;
+
+layout (location = 0) out vec4 outColor;
+
+void main()
+{
+    if (selectedInstance >= 0 && instance_id != selectedInstance) {
+        discard;
+    }
+    outColor = vec4(1.0, uintBitsToFloat(geometryId), intBitsToFloat(instance_id), 1.0);
+}
@@ -0,0 +1,38 @@
+
+uniform sampler2D maskTexture;
+uniform vec4 outlineColor;
+uniform float outlineWidth;
+uniform float pulse;
+
+in vec2 uvs;
+
+layout (location = 0) out vec4 outColor;
+
+void main()
+{
+    ivec2 size = textureSize(maskTexture, 0);
+    ivec2 center = clamp(ivec2(uvs * vec2(size)), ivec2(0), size - 1);
+    vec4 centerMask = texelFetch(maskTexture, center, 0);
+
+    // Find the distance to the closest pixel that belongs to another selected geometry or instance
+    int radius = int(ceil(outlineWidth));
+    float minDistance = outlineWidth + 1.0;
+    for (int y = -radius; y <= radius; y++) {
+        for (int x = -radius; x <= radius; x++) {
+            float d = length(vec2(x, y));
+            if (d >= minDistance) {
+                continue;
+            }
+            vec4 mask = texelFetch(maskTexture, clamp(center + ivec2(x, y), ivec2(0), size - 1), 0);
+            if (mask.a > 0.5 && (centerMask.a < 0.5 || floatBitsToUint(mask.g) != floatBitsToUint(centerMask.g) || floatBitsToInt(mask.b) != floatBitsToInt(centerMask.b))) {
+                minDistance = d;
+            }
+        }
+    }
+
+    float coverage = clamp(outlineWidth + 1.0 - minDistance, 0.0, 1.0);
+    if (coverage <= 0.0) {
+        discard;
+    }
+    outColor = vec4(color_mapping(outlineColor.rgb), outlineColor.a * coverage * pulse);
+}
@@ -0,0 +1,37 @@
+uniform sampler2D scatteringTexture;
+uniform mat4 viewProjectionInverse;
+uniform vec3 eyePosition;
+uniform float maxDistance;
+
+in vec2 uvs;
+
+layout (location = 0) out vec4 outColor;
+
+void main()
+{
+    float depth = sample_depth(uvs);
+    vec3 pos = world_pos_from_depth(viewProjectionInverse, depth, uvs);
+    float dist = min(distance(pos, eyePosition), maxDistance);
+
+    // Bilateral upsampling: Bilinear interpolation of the four nearest low resolution samples,
+    // where samples with a distance different from the distance of this pixel are given a lower weight
+    ivec2 size = textureSize(scatteringTexture, 0);
+    vec2 coord = uvs * vec2(size) - 0.5;
+    ivec2 base = ivec2(floor(coord));
+    vec2 f = fract(coord);
+    vec3 sum = vec3(0.0);
+    float weight_sum = 0.0;
+    for (int y = 0; y < 2; y++) {
+        for (int x = 0; x < 2; x++) {
+            vec4 s = texelFetch(scatteringTexture, clamp(base + ivec2(x, y), ivec2(0), size - 1), 0);
+            float bilinear = (x == 0 ? 1.0 - f.x : f.x) * (y == 0 ? 1.0 - f.y : f.y);
+            float depth_weight = max(exp(-abs(s.a - dist) / (0.05 * dist + 0.001)), 0.001);
+            float w = bilinear * depth_weight;
+            sum += w * s.rgb;
+            weight_sum += w;
+        }
+    }
+    vec3 color = sum / max(weight_sum, 0.000001);
+
+    outColor = vec4(color_mapping(tone_mapping(color)), 0.0);
+}
@@ -0,0 +1,86 @@
+uniform mat4 viewProjectionInverse;
+uniform vec3 eyePosition;
+uniform float maxDistance;
+uniform int steps;
+uniform float density;
+uniform float anisotropy;
+
+uniform vec3 lightColor;
+uniform vec3 lightDirection;
+#ifdef SPOT_LIGHT
+uniform vec3 lightPosition;
+uniform float lightCutoff;
+uniform vec3 lightAttenuation;
+#endif
+#ifdef USE_SHADOW_MAP
+uniform sampler2D shadowMap;
+uniform mat4 shadowMVP;
+#endif
+
+in vec2 uvs;
+
+layout (location = 0) out vec4 outColor;
+
+// Henyey-Greenstein phase function
+float phase(float cos_theta)
+{
+    float g = anisotropy;
+    float denom = 1.0 + g * g - 2.0 * g * cos_theta;
+    return (1.0 - g * g) / (4.0 * PI * denom * sqrt(denom));
+}
+
+float visibility(vec3 position)
+{
+#ifdef USE_SHADOW_MAP
+    vec4 shadow_coord = shadowMVP * vec4(position, 1.0);
+    shadow_coord.xyz /= shadow_coord.w;
+    if (shadow_coord.x < 0.0 || shadow_coord.x > 1.0 || shadow_coord.y < 0.0 || shadow_coord.y > 1.0 || shadow_coord.z > 1.0) {
+        return 1.0;
+    }
+    return texture(shadowMap, shadow_coord.xy).x < shadow_coord.z - 0.001 ? 0.0 : 1.0;
+#else
+    return 1.0;
+#endif
+}
+
+// The light scattered towards the viewer at the given position along a view ray with the given direction
+vec3 in_scattering(vec3 position, vec3 view_direction)
+{
+#ifdef SPOT_LIGHT
+    vec3 light_direction = lightPosition - position;
+    float distance = length(light_direction);
+    light_direction = light_direction / distance;
+    float angle = acos(dot(-light_direction, lightDirection));
+    if (angle >= lightCutoff) {
+        return vec3(0.0);
+    }
+    float attenuation = lightAttenuation.x + lightAttenuation.y * distance + lightAttenuation.z * distance * distance;
+    vec3 color = lightColor / max(attenuation, 0.0001) * (1.0 - smoothstep(0.75 * lightCutoff, lightCutoff, angle));
+    return color * phase(dot(view_direction, light_direction));
+#else
+    return lightColor * phase(dot(view_direction, -lightDirection));
+#endif
+}
+
+void main()
+{
+    float depth = sample_depth(uvs);
+    vec3 pos = world_pos_from_depth(viewProjectionInverse, depth, uvs);
+    vec3 ray = pos - eyePosition;
+    float dist = min(length(ray), maxDistance);
+    vec3 view_direction = normalize(ray);
+
+    // Offset the samples with a per-pixel noise to trade banding for noise, which is removed by the upsampling
+    float jitter = fract(sin(dot(gl_FragCoord.xy, vec2(12.9898, 78.233))) * 43758.5453);
+    float step_length = dist / float(steps);
+    float step_transmittance = exp(-density * step_length);
+
+    vec3 result = vec3(0.0);
+    float transmittance = 1.0;
+    for (int i = 0; i < steps; i++) {
+        vec3 p = eyePosition + view_direction * (float(i) + jitter) * step_length;
+        result += transmittance * density * step_length * visibility(p) * in_scattering(p, view_direction);
+        transmittance *= step_transmittance;
+    }
+    outColor = vec4(result, dist);
+}
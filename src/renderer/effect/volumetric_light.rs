@@ -0,0 +1,295 @@
+use crate::core::*;
+use crate::renderer::*;
+
+///
+/// A light which can produce volumetric light shafts using the [VolumetricLightEffect].
+/// The light shafts are only occluded by geometry if the shadow map of the light has been generated.
+///
+#[derive(Clone, Copy)]
+pub enum VolumetricLightSource<'a> {
+    /// A directional light, for example the sun.
+    Directional(&'a DirectionalLight),
+    /// A spot light.
+    Spot(&'a SpotLight),
+}
+
+impl<'a> From<&'a DirectionalLight> for VolumetricLightSource<'a> {
+    fn from(light: &'a DirectionalLight) -> Self {
+        Self::Directional(light)
+    }
+}
+
+impl<'a> From<&'a SpotLight> for VolumetricLightSource<'a> {
+    fn from(light: &'a SpotLight) -> Self {
+        Self::Spot(light)
+    }
+}
+
+impl VolumetricLightSource<'_> {
+    fn shadow_map(&self) -> Option<(&DepthTexture2D, Mat4)> {
+        match self {
+            Self::Directional(light) => light.shadow_map().map(|t| (t, light.shadow_matrix())),
+            Self::Spot(light) => light.shadow_map().map(|t| (t, light.shadow_matrix())),
+        }
+    }
+}
+
+///
+/// An effect that simulates light scattered in the air towards the viewer, which produces visible light shafts (god rays),
+/// for example from the sun shining through foliage or from a spot light shining through a window.
+///
+/// The view rays are ray marched at a reduced resolution using [VolumetricLightEffect::update],
+/// then the effect is applied to the screen, which upsamples the result using the depth and adds it to the already rendered image,
+/// for example using [RenderTarget::apply_screen_effect] with the same depth texture and no color texture.
+///
+pub struct VolumetricLightEffect {
+    context: Context,
+    scattering: Texture2D,
+    /// The amount of light scattered per unit of distance.
+    pub density: f32,
+    /// The anisotropy of the Henyey-Greenstein phase function in the range `]-1, 1[`.
+    /// Positive values scatter more light forward, so the light shafts are brighter when looking towards the light.
+    pub anisotropy: f32,
+    /// The number of samples along each view ray.
+    pub steps: u32,
+    /// The maximum distance from the viewer that is ray marched.
+    pub max_distance: f32,
+    /// The resolution used for ray marching relative to the resolution of the viewer, for example `0.5` for half resolution.
+    pub resolution_scale: f32,
+}
+
+impl VolumetricLightEffect {
+    ///
+    /// Constructs a new volumetric light effect with no light shafts.
+    ///
+    pub fn new(context: &Context) -> Self {
+        Self {
+            context: context.clone(),
+            scattering: new_scattering_texture(context, 1, 1),
+            density: 0.05,
+            anisotropy: 0.5,
+            steps: 32,
+            max_distance: 100.0,
+            resolution_scale: 0.5,
+        }
+    }
+
+    ///
+    /// Ray marches the view rays of the given viewer through the light from the given light source up to the depth in the given depth texture.
+    /// Must be called each time the viewer, the light or the scene changes.
+    ///
+    pub fn update<'a>(
+        &mut self,
+        viewer: impl Viewer,
+        depth_texture: DepthTexture,
+        light: impl Into<VolumetricLightSource<'a>>,
+    ) {
+        let viewport = viewer.viewport();
+        let scale = self.resolution_scale.clamp(0.05, 1.0);
+        let width = ((viewport.width as f32 * scale).ceil() as u32).max(1);
+        let height = ((viewport.height as f32 * scale).ceil() as u32).max(1);
+        if self.scattering.width() != width || self.scattering.height() != height {
+            self.scattering = new_scattering_texture(&self.context, width, height);
+        }
+        let effect = VolumetricLightMarchEffect {
+            effect: self,
+            light: light.into(),
+            view_projection_inverse: (viewer.projection() * viewer.view()).invert().unwrap(),
+            eye_position: viewer.position(),
+        };
+        self.scattering
+            .as_color_target(None)
+            .clear(ClearState::color(0.0, 0.0, 0.0, 0.0))
+            .apply_screen_effect(
+                &effect,
+                Camera::new_2d(Viewport::new_at_origo(width, height)),
+                &[],
+                None,
+                Some(depth_texture),
+            );
+    }
+
+    ///
+    /// Returns the texture containing the ray marched light in the rgb channels and the distance to the viewer in the alpha channel,
+    /// at the reduced resolution.
+    ///
+    pub fn scattering_texture(&self) -> &Texture2D {
+        &self.scattering
+    }
+}
+
+fn new_scattering_texture(context: &Context, width: u32, height: u32) -> Texture2D {
+    Texture2D::new_empty::<[f32; 4]>(
+        context,
+        width,
+        height,
+        Interpolation::Nearest,
+        Interpolation::Nearest,
+        None,
+        Wrapping::ClampToEdge,
+        Wrapping::ClampToEdge,
+    )
+}
+
+impl Effect for VolumetricLightEffect {
+    fn fragment_shader_source(
+        &self,
+        _lights: &[&dyn Light],
+        _color_texture: Option<ColorTexture>,
+        depth_texture: Option<DepthTexture>,
+    ) -> String {
+        format!(
+            "{}\n{}\n{}\n{}\n{}",
+            include_str!("../../core/shared.frag"),
+            depth_texture
+                .expect("Must supply a depth texture to apply a volumetric light effect")
+                .fragment_shader_source(),
+            ToneMapping::fragment_shader_source(),
+            ColorMapping::fragment_shader_source(),
+            include_str!("shaders/volumetric_light_effect.frag")
+        )
+    }
+
+    fn id(
+        &self,
+        _color_texture: Option<ColorTexture>,
+        depth_texture: Option<DepthTexture>,
+    ) -> EffectMaterialId {
+        EffectMaterialId::VolumetricLightEffect(
+            depth_texture.expect("Must supply a depth texture to apply a volumetric light effect"),
+        )
+    }
+
+    fn use_uniforms(
+        &self,
+        program: &Program,
+        viewer: &dyn Viewer,
+        _lights: &[&dyn Light],
+        _color_texture: Option<ColorTexture>,
+        depth_texture: Option<DepthTexture>,
+    ) {
+        viewer.tone_mapping().use_uniforms(program);
+        viewer.color_mapping().use_uniforms(program);
+        depth_texture
+            .expect("Must supply a depth texture to apply a volumetric light effect")
+            .use_uniforms(program);
+        program.use_texture("scatteringTexture", &self.scattering);
+        program.use_uniform(
+            "viewProjectionInverse",
+            (viewer.projection() * viewer.view()).invert().unwrap(),
+        );
+        program.use_uniform("eyePosition", viewer.position());
+        program.use_uniform("maxDistance", self.max_distance);
+    }
+
+    fn render_states(&self) -> RenderStates {
+        RenderStates {
+            write_mask: WriteMask::COLOR,
+            depth_test: DepthTest::Always,
+            blend: Blend::ADD,
+            cull: Cull::Back,
+        }
+    }
+}
+
+struct VolumetricLightMarchEffect<'a> {
+    effect: &'a VolumetricLightEffect,
+    light: VolumetricLightSource<'a>,
+    view_projection_inverse: Mat4,
+    eye_position: Vec3,
+}
+
+impl Effect for VolumetricLightMarchEffect<'_> {
+    fn fragment_shader_source(
+        &self,
+        _lights: &[&dyn Light],
+        _color_texture: Option<ColorTexture>,
+        depth_texture: Option<DepthTexture>,
+    ) -> String {
+        let mut output = String::new();
+        if let VolumetricLightSource::Spot(_) = self.light {
+            output.push_str("#define SPOT_LIGHT;\n");
+        }
+        if self.light.shadow_map().is_some() {
+            output.push_str("#define USE_SHADOW_MAP;\n");
+        }
+        output.push_str(include_str!("../../core/shared.frag"));
+        output.push_str(
+            &depth_texture
+                .expect("Must supply a depth texture to apply a volumetric light effect")
+                .fragment_shader_source(),
+        );
+        output.push_str(include_str!("shaders/volumetric_light_march.frag"));
+        output
+    }
+
+    fn id(
+        &self,
+        _color_texture: Option<ColorTexture>,
+        depth_texture: Option<DepthTexture>,
+    ) -> EffectMaterialId {
+        EffectMaterialId::VolumetricLightMarchEffect(
+            depth_texture.expect("Must supply a depth texture to apply a volumetric light effect"),
+            matches!(self.light, VolumetricLightSource::Spot(_)),
+            self.light.shadow_map().is_some(),
+        )
+    }
+
+    fn use_uniforms(
+        &self,
+        program: &Program,
+        _viewer: &dyn Viewer,
+        _lights: &[&dyn Light],
+        _color_texture: Option<ColorTexture>,
+        depth_texture: Option<DepthTexture>,
+    ) {
+        depth_texture
+            .expect("Must supply a depth texture to apply a volumetric light effect")
+            .use_uniforms(program);
+        program.use_uniform("viewProjectionInverse", self.view_projection_inverse);
+        program.use_uniform("eyePosition", self.eye_position);
+        program.use_uniform("maxDistance", self.effect.max_distance.max(0.0));
+        program.use_uniform("steps", self.effect.steps.max(1) as i32);
+        program.use_uniform("density", self.effect.density.max(0.0));
+        program.use_uniform("anisotropy", self.effect.anisotropy.clamp(-0.99, 0.99));
+        match self.light {
+            VolumetricLightSource::Directional(light) => {
+                program.use_uniform(
+                    "lightColor",
+                    light.color.to_linear_srgb().truncate() * light.intensity,
+                );
+                program.use_uniform("lightDirection", light.direction.normalize());
+            }
+            VolumetricLightSource::Spot(light) => {
+                program.use_uniform(
+                    "lightColor",
+                    light.color.to_linear_srgb().truncate() * light.intensity,
+                );
+                program.use_uniform("lightDirection", light.direction.normalize());
+                program.use_uniform("lightPosition", light.position);
+                program.use_uniform("lightCutoff", light.cutoff.0);
+                program.use_uniform(
+                    "lightAttenuation",
+                    vec3(
+                        light.attenuation.constant,
+                        light.attenuation.linear,
+                        light.attenuation.quadratic,
+                    ),
+                );
+            }
+        }
+        if let Some((shadow_map, shadow_matrix)) = self.light.shadow_map() {
+            program.use_depth_texture("shadowMap", shadow_map);
+            program.use_uniform("shadowMVP", shadow_matrix);
+        }
+    }
+
+    fn render_states(&self) -> RenderStates {
+        RenderStates {
+            write_mask: WriteMask::COLOR,
+            depth_test: DepthTest::Always,
+            cull: Cull::Back,
+            ..Default::default()
+        }
+    }
+}
@@ -62,6 +62,16 @@ mod particles;
 #[doc(inline)]
 pub use particles::*;
 
+mod point_cloud;
+#[doc(inline)]
+pub use point_cloud::*;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod point_cloud_octree;
+#[cfg(not(target_arch = "wasm32"))]
+#[doc(inline)]
+pub use point_cloud_octree::*;
+
 mod bounding_box;
 #[doc(inline)]
 pub use bounding_box::*;
@@ -70,6 +80,10 @@ mod line;
 #[doc(inline)]
 pub use line::*;
 
+mod polyline3d;
+#[doc(inline)]
+pub use polyline3d::*;
+
 mod rectangle;
 #[doc(inline)]
 pub use rectangle::*;
@@ -78,6 +92,16 @@ mod circle;
 #[doc(inline)]
 pub use circle::*;
 
+#[cfg(feature = "text")]
+mod path2d;
+#[cfg(feature = "text")]
+#[doc(inline)]
+pub use path2d::*;
+
+mod mesh_bvh;
+#[doc(inline)]
+pub use mesh_bvh::*;
+
 use crate::core::*;
 use crate::renderer::*;
 
@@ -118,8 +142,12 @@ pub trait Geometry {
     ///
     /// **Note:** The last bit is reserved to internally implemented geometries, so if implementing the `Geometry` trait
     /// outside of this crate, always return an id in the public use range as defined by [GeometryId].
+    /// The default implementation returns [GeometryId::FromSource] which means that the shader is identified by its source instead,
+    /// at the cost of generating the shader source for each draw call.
     ///
-    fn id(&self) -> GeometryId;
+    fn id(&self) -> GeometryId {
+        GeometryId::FromSource
+    }
 
     ///
     /// Render the geometry with the given [Material].
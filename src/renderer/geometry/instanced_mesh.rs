@@ -4,26 +4,58 @@ use std::sync::RwLock;
 
 use super::BaseMesh;
 
+///
+/// Defines when a level of detail is used, see for example [InstancedMesh::set_lods].
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LodThreshold {
+    /// The level of detail is used when the distance from the viewer to the center of the bounding box is larger than the given distance.
+    Distance(f32),
+    /// The level of detail is used when the [screen_coverage] of the bounding box is smaller than the given value.
+    ScreenCoverage(f32),
+}
+
+impl LodThreshold {
+    ///
+    /// Returns whether or not this threshold is exceeded for something with the given bounding box when seen from the given viewer,
+    /// ie. whether the level of detail with this threshold, or a lower level of detail, should be used.
+    ///
+    pub fn is_exceeded(&self, viewer: &dyn Viewer, aabb: &AxisAlignedBoundingBox) -> bool {
+        match self {
+            Self::Distance(distance) => aabb.center().distance(viewer.position()) > *distance,
+            Self::ScreenCoverage(coverage) => screen_coverage(viewer, aabb) < *coverage,
+        }
+    }
+
+    ///
+    /// Returns how far this threshold is exceeded, ie. a value larger than one if the threshold is exceeded and smaller than one if it is not.
+    ///
+    pub(crate) fn ratio(&self, viewer: &dyn Viewer, aabb: &AxisAlignedBoundingBox) -> f32 {
+        match self {
+            Self::Distance(distance) => aabb.center().distance(viewer.position()) / *distance,
+            Self::ScreenCoverage(coverage) => *coverage / screen_coverage(viewer, aabb),
+        }
+    }
+}
+
 ///
 /// Similar to [Mesh], except it is possible to render many instances of the same mesh efficiently.
 ///
+/// Optionally, each instance can be frustum culled on the CPU before rendering, see [InstancedMesh::set_instance_culling].
+/// It is also possible to render instances far away from the viewer with lower levels of detail, see [InstancedMesh::set_lods].
+///
 pub struct InstancedMesh {
     context: Context,
     base_mesh: BaseMesh,
-    transform: RwLock<(
-        InstanceBuffer<Vec4>,
-        InstanceBuffer<Vec4>,
-        InstanceBuffer<Vec4>,
-    )>,
-    indices: RwLock<Vec<usize>>,
-    tex_transform: RwLock<Option<(InstanceBuffer<Vec3>, InstanceBuffer<Vec3>)>>,
-    instance_color: RwLock<Option<InstanceBuffer<Vec4>>>,
-    last_camera_position: RwLock<Option<Vec3>>,
+    lods: Vec<(BaseMesh, LodThreshold, AxisAlignedBoundingBox)>,
+    // The instance buffers for the most recently used viewers, ordered from the least to the most recently used.
+    instance_buffers: RwLock<Vec<ViewInstanceBuffers>>,
     aabb: AxisAlignedBoundingBox, // The AABB for the base mesh without transformations applied
     transformation: Mat4,
     animation_transformation: Mat4,
     animation: Option<Box<dyn Fn(f32) -> Mat4 + Send + Sync>>,
     instances: Instances,
+    instance_culling: bool,
 }
 
 impl InstancedMesh {
@@ -37,26 +69,18 @@ impl InstancedMesh {
         instances.validate().expect("invalid instances");
 
         let aabb = cpu_mesh.compute_aabb();
-        let instanced_mesh = Self {
+        Self {
             context: context.clone(),
             base_mesh: BaseMesh::new(context, cpu_mesh),
-            transform: RwLock::new((
-                InstanceBuffer::<Vec4>::new(context),
-                InstanceBuffer::<Vec4>::new(context),
-                InstanceBuffer::<Vec4>::new(context),
-            )),
-            tex_transform: RwLock::new(None),
-            instance_color: RwLock::new(None),
-            last_camera_position: RwLock::new(None),
-            indices: RwLock::new((0..instances.transformations.len()).collect::<Vec<usize>>()),
+            lods: Vec::new(),
+            instance_buffers: RwLock::new(Vec::new()),
             aabb,
             transformation: Mat4::identity(),
             animation_transformation: Mat4::identity(),
             animation: None,
             instances: instances.clone(),
-        };
-        instanced_mesh.update_instance_buffers();
-        instanced_mesh
+            instance_culling: false,
+        }
     }
 
     ///
@@ -72,7 +96,7 @@ impl InstancedMesh {
     ///
     pub fn set_transformation(&mut self, transformation: Mat4) {
         self.transformation = transformation;
-        *self.last_camera_position.write().unwrap() = None;
+        self.invalidate_instance_buffers();
     }
 
     ///
@@ -97,65 +121,246 @@ impl InstancedMesh {
         #[cfg(debug_assertions)]
         instances.validate().expect("invalid instances");
         self.instances = instances.clone();
-        *self.indices.write().unwrap() =
-            (0..instances.transformations.len()).collect::<Vec<usize>>();
-        *self.last_camera_position.write().unwrap() = None;
+        self.invalidate_instance_buffers();
+    }
+
+    ///
+    /// Specifies whether or not each instance is tested against the frustum of the viewer before rendering, so that only the visible instances are drawn.
+    /// The culling is done on the CPU each time the viewer changes, which is usually much cheaper than drawing all the instances.
+    /// Disabled by default.
+    ///
+    pub fn set_instance_culling(&mut self, instance_culling: bool) {
+        self.instance_culling = instance_culling;
+        self.invalidate_instance_buffers();
+    }
+
+    ///
+    /// Sets the lower levels of detail of this mesh, ordered from the highest to the lowest level of detail.
+    /// Each instance is rendered using the lowest level of detail whose [LodThreshold], and the thresholds of all higher levels of detail, are exceeded,
+    /// or using the [CpuMesh] given at construction if no thresholds are exceeded.
+    /// The thresholds are evaluated using the bounding box of each instance.
+    ///
+    /// All levels of detail must have the same vertex attributes (normals, tangents, uv coordinates and colors) as the [CpuMesh] given at construction.
+    ///
+    pub fn set_lods(&mut self, lods: &[(&CpuMesh, LodThreshold)]) {
+        self.lods = lods
+            .iter()
+            .map(|(cpu_mesh, threshold)| {
+                let base_mesh = BaseMesh::new(&self.context, cpu_mesh);
+                assert!(
+                    base_mesh.normals.is_some() == self.base_mesh.normals.is_some()
+                        && base_mesh.tangents.is_some() == self.base_mesh.tangents.is_some()
+                        && base_mesh.uvs.is_some() == self.base_mesh.uvs.is_some()
+                        && base_mesh.colors.is_some() == self.base_mesh.colors.is_some(),
+                    "all levels of detail of an instanced mesh must have the same vertex attributes"
+                );
+                (base_mesh, *threshold, cpu_mesh.compute_aabb())
+            })
+            .collect();
+        self.invalidate_instance_buffers();
+    }
+
+    ///
+    /// Returns the bounding box of all levels of detail without any transformations applied.
+    ///
+    fn local_aabb(&self) -> AxisAlignedBoundingBox {
+        let mut aabb = self.aabb;
+        for (_, _, lod_aabb) in self.lods.iter() {
+            aabb.expand_with_aabb(*lod_aabb);
+        }
+        aabb
+    }
+
+    ///
+    /// Marks the instance buffers for all viewers as outdated, so they are updated the next time they are used.
+    ///
+    fn invalidate_instance_buffers(&mut self) {
+        for view_buffers in self.instance_buffers.get_mut().unwrap().iter_mut() {
+            view_buffers.valid = false;
+        }
+    }
+
+    ///
+    /// This function updates the given instance buffers with one entry for each level of detail.
+    /// If a viewer is given, the instances outside the frustum of the viewer are culled (if enabled), the level of detail is selected for each instance
+    /// and if specified, the instances are sorted back to front.
+    /// The existing buffers are refilled, so no new buffers are allocated unless the number of levels of detail has changed.
+    ///
+    fn update_instance_buffers(
+        &self,
+        buffers: &mut Vec<InstanceBuffers>,
+        viewer: Option<&dyn Viewer>,
+        sort: bool,
+    ) {
+        let local_aabb = self.local_aabb();
+        let frustum = viewer
+            .filter(|_| self.instance_culling)
+            .map(|viewer| Frustum::new(viewer.projection() * viewer.view()));
+        let mut levels = vec![Vec::new(); self.lods.len() + 1];
+        for (i, transformation) in self.instances.transformations.iter().enumerate() {
+            let aabb = local_aabb
+                .transformed(self.transformation * transformation * self.animation_transformation);
+            if let Some(frustum) = &frustum {
+                if !frustum.contains(aabb) {
+                    continue;
+                }
+            }
+            let level = viewer
+                .map(|viewer| {
+                    self.lods
+                        .iter()
+                        .take_while(|(_, threshold, _)| threshold.is_exceeded(viewer, &aabb))
+                        .count()
+                })
+                .unwrap_or(0);
+            levels[level].push(i);
+        }
+
+        if let (true, Some(viewer)) = (sort, viewer) {
+            let distances = self
+                .instances
+                .transformations
+                .iter()
+                .map(|m| {
+                    (self.transformation * m * self.animation_transformation)
+                        .w
+                        .truncate()
+                        .distance2(viewer.position())
+                })
+                .collect::<Vec<_>>();
+            for indices in levels.iter_mut() {
+                indices.sort_by(|a, b| {
+                    distances[*b]
+                        .partial_cmp(&distances[*a])
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                });
+            }
+        }
 
-        self.update_instance_buffers();
+        buffers.truncate(levels.len());
+        for (level, indices) in levels.iter().enumerate() {
+            if let Some(level_buffers) = buffers.get_mut(level) {
+                level_buffers.fill(&self.context, &self.instances, indices);
+            } else {
+                buffers.push(InstanceBuffers::new(
+                    &self.context,
+                    &self.instances,
+                    indices,
+                ));
+            }
+        }
     }
+}
+
+/// The maximum number of viewers an [InstancedMesh] keeps instance buffers for.
+const MAX_CACHED_VIEWS: usize = 8;
+
+///
+/// The instance buffers for each level of detail of an [InstancedMesh] when rendered from one viewer.
+///
+struct ViewInstanceBuffers {
+    // The view projection, position and whether the instances are sorted for the viewer,
+    // or `None` if the instance buffers are independent of the viewer.
+    view: Option<(Mat4, Vec3, bool)>,
+    valid: bool,
+    levels: Vec<InstanceBuffers>,
+}
 
+///
+/// The instance buffers for the instances rendered with one level of detail of an [InstancedMesh].
+///
+struct InstanceBuffers {
+    count: u32,
+    transform: (
+        InstanceBuffer<Vec4>,
+        InstanceBuffer<Vec4>,
+        InstanceBuffer<Vec4>,
+    ),
+    tex_transform: Option<(InstanceBuffer<Vec3>, InstanceBuffer<Vec3>)>,
+    instance_color: Option<InstanceBuffer<Vec4>>,
+    // The index of each instance in the list of instances, used as the instance id when picking.
+    instance_index: InstanceBuffer<i32>,
+}
+
+impl InstanceBuffers {
     ///
-    /// This function updates the instance buffers, so the instances are rendered in the order given by the indices
+    /// Creates instance buffers, so the instances with the given indices are rendered in the order given by the indices
     ///
-    fn update_instance_buffers(&self) {
-        let indices = self.indices.read().unwrap();
+    fn new(context: &Context, instances: &Instances, indices: &[usize]) -> Self {
+        let mut buffers = Self {
+            count: 0,
+            transform: (
+                InstanceBuffer::new(context),
+                InstanceBuffer::new(context),
+                InstanceBuffer::new(context),
+            ),
+            tex_transform: None,
+            instance_color: None,
+            instance_index: InstanceBuffer::new(context),
+        };
+        buffers.fill(context, instances, indices);
+        buffers
+    }
+
+    ///
+    /// Refills the instance buffers, so the instances with the given indices are rendered in the order given by the indices
+    ///
+    fn fill(&mut self, context: &Context, instances: &Instances, indices: &[usize]) {
+        self.count = indices.len() as u32;
+        if indices.is_empty() {
+            return;
+        }
+
         let mut row1 = Vec::new();
         let mut row2 = Vec::new();
         let mut row3 = Vec::new();
-        for transformation in indices.iter().map(|i| self.instances.transformations[*i]) {
+        for transformation in indices.iter().map(|i| instances.transformations[*i]) {
             row1.push(transformation.row(0));
             row2.push(transformation.row(1));
             row3.push(transformation.row(2));
         }
-
-        *self.transform.write().unwrap() = (
-            InstanceBuffer::new_with_data(&self.context, &row1),
-            InstanceBuffer::new_with_data(&self.context, &row2),
-            InstanceBuffer::new_with_data(&self.context, &row3),
-        );
-
-        *self.tex_transform.write().unwrap() =
-            self.instances
-                .texture_transformations
-                .as_ref()
-                .map(|texture_transforms| {
-                    let mut instance_tex_transform1 = Vec::new();
-                    let mut instance_tex_transform2 = Vec::new();
-                    for texture_transform in indices.iter().map(|i| texture_transforms[*i]) {
-                        instance_tex_transform1.push(vec3(
-                            texture_transform.x.x,
-                            texture_transform.y.x,
-                            texture_transform.z.x,
-                        ));
-                        instance_tex_transform2.push(vec3(
-                            texture_transform.x.y,
-                            texture_transform.y.y,
-                            texture_transform.z.y,
-                        ));
-                    }
-                    (
-                        InstanceBuffer::new_with_data(&self.context, &instance_tex_transform1),
-                        InstanceBuffer::new_with_data(&self.context, &instance_tex_transform2),
-                    )
-                });
-        *self.instance_color.write().unwrap() =
-            self.instances.colors.as_ref().map(|instance_colors| {
-                let ordered_instance_colors = indices
-                    .iter()
-                    .map(|i| instance_colors[*i].to_linear_srgb())
-                    .collect::<Vec<_>>();
-                InstanceBuffer::new_with_data(&self.context, &ordered_instance_colors)
+        self.transform.0.fill(&row1);
+        self.transform.1.fill(&row2);
+        self.transform.2.fill(&row3);
+
+        self.instance_index
+            .fill(&indices.iter().map(|i| *i as i32).collect::<Vec<_>>());
+
+        if let Some(texture_transforms) = &instances.texture_transformations {
+            let mut instance_tex_transform1 = Vec::new();
+            let mut instance_tex_transform2 = Vec::new();
+            for texture_transform in indices.iter().map(|i| texture_transforms[*i]) {
+                instance_tex_transform1.push(vec3(
+                    texture_transform.x.x,
+                    texture_transform.y.x,
+                    texture_transform.z.x,
+                ));
+                instance_tex_transform2.push(vec3(
+                    texture_transform.x.y,
+                    texture_transform.y.y,
+                    texture_transform.z.y,
+                ));
+            }
+            let (row1, row2) = self.tex_transform.get_or_insert_with(|| {
+                (InstanceBuffer::new(context), InstanceBuffer::new(context))
             });
+            row1.fill(&instance_tex_transform1);
+            row2.fill(&instance_tex_transform2);
+        } else {
+            self.tex_transform = None;
+        }
+
+        if let Some(instance_colors) = &instances.colors {
+            let ordered_instance_colors = indices
+                .iter()
+                .map(|i| instance_colors[*i].to_linear_srgb())
+                .collect::<Vec<_>>();
+            self.instance_color
+                .get_or_insert_with(|| InstanceBuffer::new(context))
+                .fill(&ordered_instance_colors);
+        } else {
+            self.instance_color = None;
+        }
     }
 }
 
@@ -170,70 +375,84 @@ impl<'a> IntoIterator for &'a InstancedMesh {
 
 impl Geometry for InstancedMesh {
     fn draw(&self, viewer: &dyn Viewer, program: &Program, render_states: RenderStates) {
-        // Check if we need a reorder the instance draw order. This only applies to transparent materials.
-        if render_states.blend != Blend::Disabled
-            && self
-                .last_camera_position
-                .read()
-                .unwrap()
-                .map(|p| p.distance2(viewer.position()) > 0.001)
-                .unwrap_or(true)
-        {
-            *self.last_camera_position.write().unwrap() = Some(viewer.position());
-            let distances = self
-                .instances
-                .transformations
-                .iter()
-                .map(|m| {
-                    (self.transformation * m * self.animation_transformation)
-                        .w
-                        .truncate()
-                        .distance2(viewer.position())
-                })
-                .collect::<Vec<_>>();
-            self.indices.write().unwrap().sort_by(|a, b| {
-                distances[*b]
-                    .partial_cmp(&distances[*a])
-                    .unwrap_or(std::cmp::Ordering::Equal)
-            });
-            self.update_instance_buffers();
+        // Check if we need to update the instance buffers. The instances are only sorted for transparent materials,
+        // while culling and selecting the level of detail applies to all materials.
+        let sort = render_states.blend != Blend::Disabled;
+        let view = if sort || self.instance_culling || !self.lods.is_empty() {
+            Some((viewer.projection() * viewer.view(), viewer.position(), sort))
+        } else {
+            None
+        };
+        let mut instance_buffers = self.instance_buffers.write().unwrap();
+        let mut view_buffers = match instance_buffers.iter().position(|b| b.view == view) {
+            Some(index) => instance_buffers.remove(index),
+            None if instance_buffers.len() >= MAX_CACHED_VIEWS => {
+                // Reuse the buffers of the least recently used viewer
+                let mut view_buffers = instance_buffers.remove(0);
+                view_buffers.view = view;
+                view_buffers.valid = false;
+                view_buffers
+            }
+            None => ViewInstanceBuffers {
+                view,
+                valid: false,
+                levels: Vec::new(),
+            },
+        };
+        if !view_buffers.valid {
+            self.update_instance_buffers(&mut view_buffers.levels, view.map(|_| viewer), sort);
+            view_buffers.valid = true;
         }
 
         program.use_uniform("viewProjection", viewer.projection() * viewer.view());
         program.use_uniform("animationTransform", self.animation_transformation);
         program.use_uniform("modelMatrix", self.transformation);
 
-        let (row1, row2, row3) = &*self.transform.read().unwrap();
-        program.use_instance_attribute("row1", row1);
-        program.use_instance_attribute("row2", row2);
-        program.use_instance_attribute("row3", row3);
+        for (level, buffers) in view_buffers.levels.iter().enumerate() {
+            if buffers.count == 0 {
+                continue;
+            }
+            let (row1, row2, row3) = &buffers.transform;
+            program.use_instance_attribute("row1", row1);
+            program.use_instance_attribute("row2", row2);
+            program.use_instance_attribute("row3", row3);
+
+            if program.requires_attribute("tex_transform_row1") {
+                if let Some((row1, row2)) = &buffers.tex_transform {
+                    program.use_instance_attribute("tex_transform_row1", row1);
+                    program.use_instance_attribute("tex_transform_row2", row2);
+                }
+            }
 
-        if program.requires_attribute("tex_transform_row1") {
-            if let Some((row1, row2)) = &*self.tex_transform.read().unwrap() {
-                program.use_instance_attribute("tex_transform_row1", row1);
-                program.use_instance_attribute("tex_transform_row2", row2);
+            if program.requires_attribute("instance_color") {
+                if let Some(color) = &buffers.instance_color {
+                    program.use_instance_attribute("instance_color", color);
+                }
             }
-        }
 
-        if program.requires_attribute("instance_color") {
-            if let Some(color) = &*self.instance_color.read().unwrap() {
-                program.use_instance_attribute("instance_color", color);
+            if program.requires_attribute("instance_index") {
+                program.use_instance_attribute("instance_index", &buffers.instance_index);
             }
-        }
 
-        self.base_mesh
-            .draw_instanced(program, render_states, viewer, self.instance_count());
+            let base_mesh = if level == 0 {
+                &self.base_mesh
+            } else {
+                &self.lods[level - 1].0
+            };
+            base_mesh.draw_instanced(program, render_states, viewer, buffers.count);
+        }
+        instance_buffers.push(view_buffers);
     }
 
     fn vertex_shader_source(&self) -> String {
         format!(
             "#define USE_INSTANCE_TRANSFORMS\n{}{}{}",
-            if self.instance_color.read().unwrap().is_some() {
+            if self.instances.colors.is_some() {
                 "#define USE_INSTANCE_COLORS\n"
             } else {
                 ""
             },
-            if self.tex_transform.read().unwrap().is_some() {
+            if self.instances.texture_transformations.is_some() {
                 "#define USE_INSTANCE_TEXTURE_TRANSFORMATION\n"
             } else {
                 ""
@@ -248,15 +467,16 @@ impl Geometry for InstancedMesh {
             self.base_mesh.tangents.is_some(),
             self.base_mesh.uvs.is_some(),
             self.base_mesh.colors.is_some(),
-            self.instance_color.read().unwrap().is_some(),
-            self.tex_transform.read().unwrap().is_some(),
+            self.instances.colors.is_some(),
+            self.instances.texture_transformations.is_some(),
         )
     }
 
     fn aabb(&self) -> AxisAlignedBoundingBox {
+        let local_aabb = self.local_aabb();
         let mut aabb = AxisAlignedBoundingBox::EMPTY;
         for instance_transformation in &self.instances.transformations {
-            aabb.expand_with_aabb(self.aabb.transformed(
+            aabb.expand_with_aabb(local_aabb.transformed(
                 self.transformation * instance_transformation * self.animation_transformation,
             ));
         }
@@ -266,7 +486,7 @@ impl Geometry for InstancedMesh {
     fn animate(&mut self, time: f32) {
         if let Some(animation) = &self.animation {
             self.animation_transformation = animation(time);
-            *self.last_camera_position.write().unwrap() = None;
+            self.invalidate_instance_buffers();
         }
     }
 
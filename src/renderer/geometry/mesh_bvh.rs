@@ -0,0 +1,630 @@
+use crate::core::*;
+use crate::renderer::*;
+use std::cell::Cell;
+
+///
+/// The result of a ray or sphere query against a [MeshBvh].
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeshHit {
+    /// The index of the instance that was hit, which is 0 if the [MeshBvh] is not instanced.
+    pub instance_index: u32,
+    /// The index of the triangle that was hit, ie. the triangle defined by the indices `3 * triangle_index`, `3 * triangle_index + 1` and `3 * triangle_index + 2`.
+    pub triangle_index: u32,
+    /// The barycentric coordinates of the hit position with respect to the three vertices of the triangle.
+    pub barycentrics: Vec3,
+    /// The position in world space of the hit. For sphere queries, this is the point on the triangle closest to the center of the sphere.
+    pub position: Vec3,
+    /// The normal in world space at the hit position.
+    /// This is the interpolated vertex normal if the mesh has normals, otherwise the normal of the triangle.
+    pub normal: Vec3,
+    /// The distance from the origin of the ray, or the center of the sphere, to the hit position.
+    pub distance: f32,
+}
+
+///
+/// Identifies a triangle returned by [MeshBvh::aabb_intersect].
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MeshTriangle {
+    /// The index of the instance, which is 0 if the [MeshBvh] is not instanced.
+    pub instance_index: u32,
+    /// The index of the triangle, see [MeshHit::triangle_index].
+    pub triangle_index: u32,
+}
+
+///
+/// A bounding volume hierarchy over the triangles of a [CpuMesh], optionally instanced using the transformations of [Instances],
+/// which is used to efficiently find intersections between rays, spheres or boxes and the triangles on the CPU.
+/// In contrast to [ray_intersect], this does not require a graphics context, so it can be used from any thread and for many queries per frame.
+///
+/// The triangles are in world space, so transform the [CpuMesh] before constructing the BVH if needed, or use [MeshBvh::new_instanced].
+///
+#[derive(Clone)]
+pub struct MeshBvh {
+    positions: Vec<Vec3>,
+    normals: Option<Vec<Vec3>>,
+    triangles: Vec<[u32; 3]>,
+    triangle_bvh: Bvh,
+    instances: Vec<BvhInstance>,
+    instance_bvh: Bvh,
+}
+
+#[derive(Clone)]
+struct BvhInstance {
+    transformation: Mat4,
+    inverse_transformation: Mat4,
+    normal_transformation: Mat3,
+}
+
+impl MeshBvh {
+    ///
+    /// Constructs a new bounding volume hierarchy over the triangles of the given mesh.
+    ///
+    pub fn new(cpu_mesh: &CpuMesh) -> Self {
+        Self::new_with_transformations(cpu_mesh, &[Mat4::identity()])
+    }
+
+    ///
+    /// Constructs a new bounding volume hierarchy over the triangles of the given mesh for each of the instance transformations,
+    /// using the same instance index as an [InstancedMesh] with the given instances.
+    ///
+    pub fn new_instanced(cpu_mesh: &CpuMesh, instances: &Instances) -> Self {
+        Self::new_with_transformations(cpu_mesh, &instances.transformations)
+    }
+
+    fn new_with_transformations(cpu_mesh: &CpuMesh, transformations: &[Mat4]) -> Self {
+        let positions = cpu_mesh.positions.to_f32();
+        let triangles = match &cpu_mesh.indices {
+            Indices::U8(ind) => ind.iter().map(|i| *i as u32).collect::<Vec<_>>(),
+            Indices::U16(ind) => ind.iter().map(|i| *i as u32).collect::<Vec<_>>(),
+            Indices::U32(ind) => ind.clone(),
+            Indices::None => (0..positions.len() as u32).collect::<Vec<_>>(),
+        }
+        .chunks_exact(3)
+        .map(|t| [t[0], t[1], t[2]])
+        .collect::<Vec<_>>();
+        let triangle_bvh = Bvh::new(
+            &triangles
+                .iter()
+                .map(|t| bounds(t.iter().map(|i| positions[*i as usize])))
+                .collect::<Vec<_>>(),
+        );
+        let instances = transformations
+            .iter()
+            .map(|transformation| {
+                let inverse_transformation = transformation.invert().unwrap_or(Mat4::identity());
+                let m = inverse_transformation.transpose();
+                BvhInstance {
+                    transformation: *transformation,
+                    inverse_transformation,
+                    normal_transformation: Mat3::from_cols(
+                        m.x.truncate(),
+                        m.y.truncate(),
+                        m.z.truncate(),
+                    ),
+                }
+            })
+            .collect::<Vec<_>>();
+        let local_bounds = triangle_bvh.bounds();
+        let instance_bvh = Bvh::new(
+            &instances
+                .iter()
+                .map(|instance| match local_bounds {
+                    Some((min, max)) => bounds(
+                        box_corners(min, max)
+                            .iter()
+                            .map(|c| transform_point(&instance.transformation, *c)),
+                    ),
+                    None => (
+                        vec3(f32::MAX, f32::MAX, f32::MAX),
+                        vec3(f32::MIN, f32::MIN, f32::MIN),
+                    ),
+                })
+                .collect::<Vec<_>>(),
+        );
+        Self {
+            positions,
+            normals: cpu_mesh.normals.clone(),
+            triangles,
+            triangle_bvh,
+            instances,
+            instance_bvh,
+        }
+    }
+
+    ///
+    /// Returns the number of triangles in the mesh.
+    ///
+    pub fn triangle_count(&self) -> u32 {
+        self.triangles.len() as u32
+    }
+
+    ///
+    /// Returns the number of instances.
+    ///
+    pub fn instance_count(&self) -> u32 {
+        self.instances.len() as u32
+    }
+
+    ///
+    /// Returns the bounding box in world space of all the triangles of all instances.
+    ///
+    pub fn aabb(&self) -> AxisAlignedBoundingBox {
+        match self.instance_bvh.bounds() {
+            Some((min, max)) => AxisAlignedBoundingBox::new_with_positions(&[min, max]),
+            None => AxisAlignedBoundingBox::EMPTY,
+        }
+    }
+
+    ///
+    /// Finds the closest intersection between the triangles and the ray starting at the given position in the given direction.
+    /// Both front and back facing triangles are hit.
+    /// Returns `None` if no triangle was hit before the given maximum distance.
+    ///
+    pub fn ray_intersect(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+    ) -> Option<MeshHit> {
+        let direction = direction.normalize();
+        let inverse_direction = vec3(1.0 / direction.x, 1.0 / direction.y, 1.0 / direction.z);
+        let closest = Cell::new(max_distance);
+        let result = Cell::new(None);
+        self.instance_bvh.traverse(
+            |min, max| ray_box_intersect(origin, inverse_direction, min, max, closest.get()),
+            |instance_index| {
+                let instance = &self.instances[instance_index as usize];
+                let local_origin = transform_point(&instance.inverse_transformation, origin);
+                let local_direction =
+                    (instance.inverse_transformation * direction.extend(0.0)).truncate();
+                let local_inverse_direction = vec3(
+                    1.0 / local_direction.x,
+                    1.0 / local_direction.y,
+                    1.0 / local_direction.z,
+                );
+                self.triangle_bvh.traverse(
+                    |min, max| {
+                        ray_box_intersect(
+                            local_origin,
+                            local_inverse_direction,
+                            min,
+                            max,
+                            closest.get(),
+                        )
+                    },
+                    |triangle_index| {
+                        let [a, b, c] = self.triangle_positions(triangle_index);
+                        if let Some((t, barycentrics)) =
+                            ray_triangle_intersect(local_origin, local_direction, a, b, c)
+                        {
+                            if t >= 0.0 && t < closest.get() {
+                                closest.set(t);
+                                result.set(Some((instance_index, triangle_index, barycentrics, t)));
+                            }
+                        }
+                    },
+                );
+            },
+        );
+        result
+            .get()
+            .map(|(instance_index, triangle_index, barycentrics, t)| {
+                self.hit(
+                    instance_index,
+                    triangle_index,
+                    barycentrics,
+                    origin + direction * t,
+                    t,
+                )
+            })
+    }
+
+    ///
+    /// Finds all triangles that intersect the sphere with the given center and radius.
+    /// For each triangle, the point on the triangle closest to the center of the sphere is returned.
+    /// The hits are sorted by distance to the center of the sphere.
+    ///
+    pub fn sphere_intersect(&self, center: Vec3, radius: f32) -> Vec<MeshHit> {
+        let world_min = center - vec3(radius, radius, radius);
+        let world_max = center + vec3(radius, radius, radius);
+        let mut hits = Vec::new();
+        self.instance_bvh.traverse(
+            |min, max| box_box_intersect(world_min, world_max, min, max),
+            |instance_index| {
+                let instance = &self.instances[instance_index as usize];
+                let (local_min, local_max) = bounds(
+                    box_corners(world_min, world_max)
+                        .iter()
+                        .map(|c| transform_point(&instance.inverse_transformation, *c)),
+                );
+                self.triangle_bvh.traverse(
+                    |min, max| box_box_intersect(local_min, local_max, min, max),
+                    |triangle_index| {
+                        let [a, b, c] = self
+                            .triangle_positions(triangle_index)
+                            .map(|p| transform_point(&instance.transformation, p));
+                        let barycentrics = closest_point_on_triangle(center, a, b, c);
+                        let position = a * barycentrics.x + b * barycentrics.y + c * barycentrics.z;
+                        let distance = position.distance(center);
+                        if distance <= radius {
+                            hits.push(self.hit(
+                                instance_index,
+                                triangle_index,
+                                barycentrics,
+                                position,
+                                distance,
+                            ));
+                        }
+                    },
+                );
+            },
+        );
+        hits.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        hits
+    }
+
+    ///
+    /// Finds all triangles that intersect the given axis aligned bounding box in world space.
+    ///
+    pub fn aabb_intersect(&self, aabb: &AxisAlignedBoundingBox) -> Vec<MeshTriangle> {
+        let mut triangles = Vec::new();
+        if aabb.is_empty() {
+            return triangles;
+        }
+        let (world_min, world_max) = (aabb.min(), aabb.max());
+        self.instance_bvh.traverse(
+            |min, max| box_box_intersect(world_min, world_max, min, max),
+            |instance_index| {
+                let instance = &self.instances[instance_index as usize];
+                let (local_min, local_max) = bounds(
+                    box_corners(world_min, world_max)
+                        .iter()
+                        .map(|c| transform_point(&instance.inverse_transformation, *c)),
+                );
+                self.triangle_bvh.traverse(
+                    |min, max| box_box_intersect(local_min, local_max, min, max),
+                    |triangle_index| {
+                        let triangle = self
+                            .triangle_positions(triangle_index)
+                            .map(|p| transform_point(&instance.transformation, p));
+                        if triangle_box_intersect(triangle, world_min, world_max) {
+                            triangles.push(MeshTriangle {
+                                instance_index,
+                                triangle_index,
+                            });
+                        }
+                    },
+                );
+            },
+        );
+        triangles
+    }
+
+    fn triangle_positions(&self, triangle_index: u32) -> [Vec3; 3] {
+        self.triangles[triangle_index as usize].map(|i| self.positions[i as usize])
+    }
+
+    fn hit(
+        &self,
+        instance_index: u32,
+        triangle_index: u32,
+        barycentrics: Vec3,
+        position: Vec3,
+        distance: f32,
+    ) -> MeshHit {
+        let [i0, i1, i2] = self.triangles[triangle_index as usize];
+        let local_normal = match &self.normals {
+            Some(normals) => {
+                normals[i0 as usize] * barycentrics.x
+                    + normals[i1 as usize] * barycentrics.y
+                    + normals[i2 as usize] * barycentrics.z
+            }
+            None => {
+                let [a, b, c] = self.triangle_positions(triangle_index);
+                (b - a).cross(c - a)
+            }
+        };
+        let normal = self.instances[instance_index as usize].normal_transformation * local_normal;
+        MeshHit {
+            instance_index,
+            triangle_index,
+            barycentrics,
+            position,
+            normal: if normal.magnitude2() > 0.0 {
+                normal.normalize()
+            } else {
+                normal
+            },
+            distance,
+        }
+    }
+}
+
+///
+/// A bounding volume hierarchy over a list of bounding boxes, split at the median along the longest axis.
+///
+#[derive(Clone, Default)]
+struct Bvh {
+    nodes: Vec<BvhNode>,
+    items: Vec<u32>,
+}
+
+#[derive(Clone, Copy)]
+struct BvhNode {
+    min: Vec3,
+    max: Vec3,
+    /// The index of the first item if this is a leaf, otherwise the index of the first of the two children.
+    start: u32,
+    /// The number of items if this is a leaf, otherwise zero.
+    count: u32,
+}
+
+const MAX_LEAF_SIZE: usize = 4;
+
+impl Bvh {
+    fn new(item_bounds: &[(Vec3, Vec3)]) -> Self {
+        let mut items = (0..item_bounds.len() as u32).collect::<Vec<_>>();
+        let mut nodes = Vec::new();
+        if items.is_empty() {
+            return Self { nodes, items };
+        }
+        let centroids = item_bounds
+            .iter()
+            .map(|(min, max)| 0.5 * (min + max))
+            .collect::<Vec<_>>();
+        nodes.push(BvhNode {
+            min: vec3(0.0, 0.0, 0.0),
+            max: vec3(0.0, 0.0, 0.0),
+            start: 0,
+            count: 0,
+        });
+        let mut stack = vec![(0, 0, items.len())];
+        while let Some((node_index, start, end)) = stack.pop() {
+            let (min, max) = bounds(items[start..end].iter().flat_map(|i| {
+                let (min, max) = item_bounds[*i as usize];
+                [min, max]
+            }));
+            let (centroid_min, centroid_max) =
+                bounds(items[start..end].iter().map(|i| centroids[*i as usize]));
+            let extent = centroid_max - centroid_min;
+            let axis = if extent.x >= extent.y && extent.x >= extent.z {
+                0
+            } else if extent.y >= extent.z {
+                1
+            } else {
+                2
+            };
+            if end - start <= MAX_LEAF_SIZE || extent[axis] <= 0.0 {
+                nodes[node_index] = BvhNode {
+                    min,
+                    max,
+                    start: start as u32,
+                    count: (end - start) as u32,
+                };
+                continue;
+            }
+            let mid = (start + end) / 2;
+            items[start..end].select_nth_unstable_by(mid - start, |a, b| {
+                centroids[*a as usize][axis].total_cmp(&centroids[*b as usize][axis])
+            });
+            let left = nodes.len();
+            nodes[node_index] = BvhNode {
+                min,
+                max,
+                start: left as u32,
+                count: 0,
+            };
+            nodes.push(nodes[node_index]);
+            nodes.push(nodes[node_index]);
+            stack.push((left, start, mid));
+            stack.push((left + 1, mid, end));
+        }
+        Self { nodes, items }
+    }
+
+    fn bounds(&self) -> Option<(Vec3, Vec3)> {
+        self.nodes.first().map(|n| (n.min, n.max))
+    }
+
+    ///
+    /// Calls `visit` for each item in the leaf nodes whose bounding box is accepted by `overlaps`.
+    ///
+    fn traverse(&self, overlaps: impl Fn(Vec3, Vec3) -> bool, mut visit: impl FnMut(u32)) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = vec![0u32];
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index as usize];
+            if !overlaps(node.min, node.max) {
+                continue;
+            }
+            if node.count > 0 {
+                for item in &self.items[node.start as usize..(node.start + node.count) as usize] {
+                    visit(*item);
+                }
+            } else {
+                stack.push(node.start + 1);
+                stack.push(node.start);
+            }
+        }
+    }
+}
+
+fn bounds(points: impl Iterator<Item = Vec3>) -> (Vec3, Vec3) {
+    points.fold(
+        (
+            vec3(f32::MAX, f32::MAX, f32::MAX),
+            vec3(f32::MIN, f32::MIN, f32::MIN),
+        ),
+        |(min, max), p| {
+            (
+                vec3(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+                vec3(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)),
+            )
+        },
+    )
+}
+
+fn box_corners(min: Vec3, max: Vec3) -> [Vec3; 8] {
+    [
+        vec3(min.x, min.y, min.z),
+        vec3(max.x, min.y, min.z),
+        vec3(min.x, max.y, min.z),
+        vec3(max.x, max.y, min.z),
+        vec3(min.x, min.y, max.z),
+        vec3(max.x, min.y, max.z),
+        vec3(min.x, max.y, max.z),
+        vec3(max.x, max.y, max.z),
+    ]
+}
+
+fn transform_point(transformation: &Mat4, point: Vec3) -> Vec3 {
+    let p = transformation * point.extend(1.0);
+    p.truncate() / p.w
+}
+
+fn box_box_intersect(min0: Vec3, max0: Vec3, min1: Vec3, max1: Vec3) -> bool {
+    min0.x <= max1.x
+        && max0.x >= min1.x
+        && min0.y <= max1.y
+        && max0.y >= min1.y
+        && min0.z <= max1.z
+        && max0.z >= min1.z
+}
+
+fn ray_box_intersect(
+    origin: Vec3,
+    inverse_direction: Vec3,
+    min: Vec3,
+    max: Vec3,
+    max_distance: f32,
+) -> bool {
+    let t0 = min - origin;
+    let t0 = vec3(
+        t0.x * inverse_direction.x,
+        t0.y * inverse_direction.y,
+        t0.z * inverse_direction.z,
+    );
+    let t1 = max - origin;
+    let t1 = vec3(
+        t1.x * inverse_direction.x,
+        t1.y * inverse_direction.y,
+        t1.z * inverse_direction.z,
+    );
+    let t_near = t0.x.min(t1.x).max(t0.y.min(t1.y)).max(t0.z.min(t1.z));
+    let t_far = t0.x.max(t1.x).min(t0.y.max(t1.y)).min(t0.z.max(t1.z));
+    t_near <= t_far && t_far >= 0.0 && t_near <= max_distance
+}
+
+///
+/// Möller-Trumbore ray-triangle intersection, returns the ray parameter and the barycentric coordinates of the intersection.
+///
+fn ray_triangle_intersect(
+    origin: Vec3,
+    direction: Vec3,
+    a: Vec3,
+    b: Vec3,
+    c: Vec3,
+) -> Option<(f32, Vec3)> {
+    let edge0 = b - a;
+    let edge1 = c - a;
+    let p = direction.cross(edge1);
+    let determinant = edge0.dot(p);
+    if determinant.abs() < f32::EPSILON * edge0.magnitude() * edge1.magnitude() {
+        return None;
+    }
+    let inverse_determinant = 1.0 / determinant;
+    let s = origin - a;
+    let u = s.dot(p) * inverse_determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(edge0);
+    let v = direction.dot(q) * inverse_determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = edge1.dot(q) * inverse_determinant;
+    Some((t, vec3(1.0 - u - v, u, v)))
+}
+
+///
+/// Returns the barycentric coordinates of the point on the triangle closest to the given point (Real-Time Collision Detection, Ericson).
+///
+fn closest_point_on_triangle(p: Vec3, a: Vec3, b: Vec3, c: Vec3) -> Vec3 {
+    let ab = b - a;
+    let ac = c - a;
+    let ap = p - a;
+    let d1 = ab.dot(ap);
+    let d2 = ac.dot(ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return vec3(1.0, 0.0, 0.0);
+    }
+    let bp = p - b;
+    let d3 = ab.dot(bp);
+    let d4 = ac.dot(bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return vec3(0.0, 1.0, 0.0);
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        let v = d1 / (d1 - d3);
+        return vec3(1.0 - v, v, 0.0);
+    }
+    let cp = p - c;
+    let d5 = ab.dot(cp);
+    let d6 = ac.dot(cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return vec3(0.0, 0.0, 1.0);
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        let w = d2 / (d2 - d6);
+        return vec3(1.0 - w, 0.0, w);
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        let w = (d4 - d3) / ((d4 - d3) + (d5 - d6));
+        return vec3(0.0, 1.0 - w, w);
+    }
+    let denominator = 1.0 / (va + vb + vc);
+    let v = vb * denominator;
+    let w = vc * denominator;
+    vec3(1.0 - v - w, v, w)
+}
+
+///
+/// Separating axis test between a triangle and an axis aligned box (Akenine-Möller).
+///
+fn triangle_box_intersect(triangle: [Vec3; 3], min: Vec3, max: Vec3) -> bool {
+    let center = 0.5 * (min + max);
+    let half_size = 0.5 * (max - min);
+    let v = triangle.map(|p| p - center);
+    let edges = [v[1] - v[0], v[2] - v[1], v[0] - v[2]];
+    let separated = |axis: Vec3| {
+        let p = v.map(|p| p.dot(axis));
+        let r =
+            half_size.x * axis.x.abs() + half_size.y * axis.y.abs() + half_size.z * axis.z.abs();
+        p[0].min(p[1]).min(p[2]) > r || p[0].max(p[1]).max(p[2]) < -r
+    };
+    let box_axes = [
+        vec3(1.0, 0.0, 0.0),
+        vec3(0.0, 1.0, 0.0),
+        vec3(0.0, 0.0, 1.0),
+    ];
+    for box_axis in box_axes.iter() {
+        for edge in edges.iter() {
+            let axis = box_axis.cross(*edge);
+            if axis.magnitude2() > 0.0 && separated(axis) {
+                return false;
+            }
+        }
+        if separated(*box_axis) {
+            return false;
+        }
+    }
+    !separated(edges[0].cross(edges[1]))
+}
@@ -0,0 +1,430 @@
+use crate::renderer::*;
+use lyon::math::{point, Point};
+use lyon::path::iterator::PathIterator;
+use lyon::path::{Path, PathEvent};
+use lyon::tessellation::{
+    BuffersBuilder, FillOptions, FillTessellator, FillVertex, StrokeOptions, StrokeTessellator,
+    StrokeVertex, VertexBuffers,
+};
+
+/// The maximum distance in physical pixels between the curves and the tessellated triangles.
+const TOLERANCE: f32 = 0.1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PathCommand {
+    MoveTo(Vec2),
+    LineTo(Vec2),
+    QuadraticTo(Vec2, Vec2),
+    CubicTo(Vec2, Vec2, Vec2),
+    Close,
+}
+
+///
+/// A 2D path consisting of straight lines and bezier curves in physical pixels, which can be filled or stroked using a [Path2DGeometry].
+/// The path can consist of several sub paths which are started by [Path2D::move_to].
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Path2D {
+    commands: Vec<PathCommand>,
+}
+
+impl Path2D {
+    ///
+    /// Creates a new empty path.
+    ///
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///
+    /// Starts a new sub path at the given point.
+    ///
+    pub fn move_to(&mut self, point: impl Into<PhysicalPoint>) -> &mut Self {
+        self.commands.push(PathCommand::MoveTo(to_vec2(point)));
+        self
+    }
+
+    ///
+    /// Adds a straight line from the current point to the given point.
+    ///
+    pub fn line_to(&mut self, point: impl Into<PhysicalPoint>) -> &mut Self {
+        self.commands.push(PathCommand::LineTo(to_vec2(point)));
+        self
+    }
+
+    ///
+    /// Adds a quadratic bezier curve from the current point to the given point using the given control point.
+    ///
+    pub fn quadratic_to(
+        &mut self,
+        control: impl Into<PhysicalPoint>,
+        point: impl Into<PhysicalPoint>,
+    ) -> &mut Self {
+        self.commands
+            .push(PathCommand::QuadraticTo(to_vec2(control), to_vec2(point)));
+        self
+    }
+
+    ///
+    /// Adds a cubic bezier curve from the current point to the given point using the two given control points.
+    ///
+    pub fn cubic_to(
+        &mut self,
+        control0: impl Into<PhysicalPoint>,
+        control1: impl Into<PhysicalPoint>,
+        point: impl Into<PhysicalPoint>,
+    ) -> &mut Self {
+        self.commands.push(PathCommand::CubicTo(
+            to_vec2(control0),
+            to_vec2(control1),
+            to_vec2(point),
+        ));
+        self
+    }
+
+    ///
+    /// Adds a circular arc with the given center and radius, starting at the start angle and sweeping the given angle counterclockwise, or clockwise if the sweep angle is negative.
+    /// A straight line is added from the current point to the start of the arc, unless the path is empty.
+    ///
+    pub fn arc(
+        &mut self,
+        center: impl Into<PhysicalPoint>,
+        radius: f32,
+        start_angle: impl Into<Radians>,
+        sweep_angle: impl Into<Radians>,
+    ) -> &mut Self {
+        let center = to_vec2(center);
+        let start_angle = start_angle.into().0;
+        let sweep_angle = sweep_angle.into().0;
+        let point_at = |angle: f32| center + radius * vec2(angle.cos(), angle.sin());
+        let tangent_at = |angle: f32| radius * vec2(-angle.sin(), angle.cos());
+
+        let start = point_at(start_angle);
+        if self.commands.is_empty() {
+            self.commands.push(PathCommand::MoveTo(start));
+        } else {
+            self.commands.push(PathCommand::LineTo(start));
+        }
+
+        // Approximate the arc by cubic bezier curves each spanning at most a quarter circle
+        let segment_count = (sweep_angle.abs() / std::f32::consts::FRAC_PI_2)
+            .ceil()
+            .max(1.0) as u32;
+        let segment_angle = sweep_angle / segment_count as f32;
+        let k = 4.0 / 3.0 * (0.25 * segment_angle).tan();
+        for i in 0..segment_count {
+            let angle0 = start_angle + i as f32 * segment_angle;
+            let angle1 = angle0 + segment_angle;
+            let p0 = point_at(angle0);
+            let p1 = point_at(angle1);
+            let c0 = p0 + k * tangent_at(angle0);
+            let c1 = p1 - k * tangent_at(angle1);
+            self.commands.push(PathCommand::CubicTo(c0, c1, p1));
+        }
+        self
+    }
+
+    ///
+    /// Closes the current sub path with a straight line back to its starting point.
+    ///
+    pub fn close(&mut self) -> &mut Self {
+        self.commands.push(PathCommand::Close);
+        self
+    }
+
+    fn to_lyon(&self) -> Path {
+        let mut builder = Path::builder();
+        let mut open = false;
+        let mut current = point(0.0, 0.0);
+        let mut start = current;
+        for command in self.commands.iter() {
+            if !open && !matches!(command, PathCommand::MoveTo(_) | PathCommand::Close) {
+                builder.begin(current);
+                start = current;
+                open = true;
+            }
+            match *command {
+                PathCommand::MoveTo(p) => {
+                    if open {
+                        builder.end(false);
+                    }
+                    current = to_point(p);
+                    start = current;
+                    builder.begin(current);
+                    open = true;
+                }
+                PathCommand::LineTo(p) => {
+                    current = to_point(p);
+                    builder.line_to(current);
+                }
+                PathCommand::QuadraticTo(c, p) => {
+                    current = to_point(p);
+                    builder.quadratic_bezier_to(to_point(c), current);
+                }
+                PathCommand::CubicTo(c0, c1, p) => {
+                    current = to_point(p);
+                    builder.cubic_bezier_to(to_point(c0), to_point(c1), current);
+                }
+                PathCommand::Close => {
+                    if open {
+                        builder.end(true);
+                        open = false;
+                    }
+                    current = start;
+                }
+            }
+        }
+        if open {
+            builder.end(false);
+        }
+        builder.build()
+    }
+}
+
+///
+/// The rule that determines which parts of a [Path2D] are inside the path when filled.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FillRule {
+    /// A point is inside if the path winds around it a non-zero number of times.
+    #[default]
+    NonZero,
+    /// A point is inside if a ray from the point crosses the path an odd number of times.
+    EvenOdd,
+}
+
+///
+/// The style used for stroking a [Path2D], see [Path2DGeometry::stroke].
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct StrokeStyle {
+    /// The width of the stroke in physical pixels.
+    pub width: f32,
+    /// The shape used where two segments meet.
+    pub join: LineJoin,
+    /// The shape used at the ends of open sub paths and at the ends of each dash.
+    pub cap: LineCap,
+    /// The miter limit as defined in [LineJoin::Miter], longer miters are replaced by bevel joins.
+    pub miter_limit: f32,
+    /// The lengths of alternating dashes and gaps in physical pixels, starting with a dash at the beginning of each sub path.
+    /// Negative lengths are treated as zero.
+    /// If the pattern contains an odd number of values, it is repeated to get an even number of values.
+    /// An empty pattern gives a solid stroke.
+    pub dash_pattern: Vec<f32>,
+}
+
+impl Default for StrokeStyle {
+    fn default() -> Self {
+        Self {
+            width: 1.0,
+            join: LineJoin::default(),
+            cap: LineCap::default(),
+            miter_limit: 4.0,
+            dash_pattern: Vec::new(),
+        }
+    }
+}
+
+///
+/// A 2D geometry created by filling or stroking a [Path2D] which can be rendered using a camera created by [Camera::new_2d].
+/// The positions are in physical pixels.
+///
+pub struct Path2DGeometry {
+    mesh: Mesh,
+}
+
+impl Path2DGeometry {
+    ///
+    /// Constructs a new geometry covering the inside of the given path, where the inside is determined by the fill rule.
+    /// Open sub paths are implicitly closed.
+    ///
+    pub fn fill(context: &Context, path: &Path2D, fill_rule: FillRule) -> Self {
+        let options = FillOptions::tolerance(TOLERANCE).with_fill_rule(match fill_rule {
+            FillRule::NonZero => lyon::tessellation::FillRule::NonZero,
+            FillRule::EvenOdd => lyon::tessellation::FillRule::EvenOdd,
+        });
+        let mut geometry: VertexBuffers<Vec3, u32> = VertexBuffers::new();
+        // The tessellation only fails for invalid input, in which case the geometry is left empty
+        FillTessellator::new()
+            .tessellate_path(
+                &path.to_lyon(),
+                &options,
+                &mut BuffersBuilder::new(&mut geometry, |vertex: FillVertex| {
+                    vec3(vertex.position().x, vertex.position().y, 0.0)
+                }),
+            )
+            .ok();
+        Self::new(context, geometry)
+    }
+
+    ///
+    /// Constructs a new geometry covering the outline of the given path using the given stroke style.
+    ///
+    pub fn stroke(context: &Context, path: &Path2D, style: &StrokeStyle) -> Self {
+        let mut path = path.to_lyon();
+        let mut pattern = style
+            .dash_pattern
+            .iter()
+            .map(|length| length.max(0.0))
+            .collect::<Vec<_>>();
+        if pattern.iter().sum::<f32>() > 0.0 {
+            if pattern.len() % 2 == 1 {
+                pattern.extend_from_within(..);
+            }
+            path = dash(&path, &pattern);
+        }
+        let stroke_options = StrokeOptions::tolerance(TOLERANCE)
+            .with_line_width(style.width)
+            .with_miter_limit(style.miter_limit.max(1.0))
+            .with_line_join(match style.join {
+                LineJoin::Miter => lyon::tessellation::LineJoin::Miter,
+                LineJoin::Round => lyon::tessellation::LineJoin::Round,
+            })
+            .with_line_cap(match style.cap {
+                LineCap::Butt => lyon::tessellation::LineCap::Butt,
+                LineCap::Square => lyon::tessellation::LineCap::Square,
+                LineCap::Round => lyon::tessellation::LineCap::Round,
+            });
+        let mut geometry: VertexBuffers<Vec3, u32> = VertexBuffers::new();
+        // The tessellation only fails for invalid input, in which case the geometry is left empty
+        StrokeTessellator::new()
+            .tessellate_path(
+                &path,
+                &stroke_options,
+                &mut BuffersBuilder::new(&mut geometry, |vertex: StrokeVertex| {
+                    vec3(vertex.position().x, vertex.position().y, 0.0)
+                }),
+            )
+            .ok();
+        Self::new(context, geometry)
+    }
+
+    fn new(context: &Context, geometry: VertexBuffers<Vec3, u32>) -> Self {
+        Self {
+            mesh: Mesh::new(
+                context,
+                &CpuMesh {
+                    positions: Positions::F32(geometry.vertices),
+                    indices: Indices::U32(geometry.indices),
+                    ..Default::default()
+                },
+            ),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a Path2DGeometry {
+    type Item = &'a dyn Geometry;
+    type IntoIter = std::iter::Once<&'a dyn Geometry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        std::iter::once(self)
+    }
+}
+
+use std::ops::Deref;
+impl Deref for Path2DGeometry {
+    type Target = Mesh;
+    fn deref(&self) -> &Self::Target {
+        &self.mesh
+    }
+}
+
+impl std::ops::DerefMut for Path2DGeometry {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.mesh
+    }
+}
+
+impl Geometry for Path2DGeometry {
+    impl_geometry_body!(deref);
+
+    fn animate(&mut self, time: f32) {
+        self.mesh.animate(time)
+    }
+}
+
+fn to_vec2(point: impl Into<PhysicalPoint>) -> Vec2 {
+    let point = point.into();
+    vec2(point.x, point.y)
+}
+
+fn to_point(p: Vec2) -> Point {
+    point(p.x, p.y)
+}
+
+///
+/// Splits the given path into open sub paths for each dash in the given pattern of alternating dash and gap lengths.
+///
+fn dash(path: &Path, pattern: &[f32]) -> Path {
+    struct Dasher<'a> {
+        builder: lyon::path::path::Builder,
+        pattern: &'a [f32],
+        index: usize,
+        remaining: f32,
+        open: bool,
+    }
+
+    impl Dasher<'_> {
+        fn begin(&mut self, at: Point) {
+            self.index = 0;
+            self.remaining = self.pattern[0];
+            self.builder.begin(at);
+            self.open = true;
+        }
+
+        fn end(&mut self) {
+            if self.open {
+                self.builder.end(false);
+                self.open = false;
+            }
+        }
+
+        fn segment(&mut self, from: Point, to: Point) {
+            let mut start = from;
+            let mut length = (to - from).length();
+            while length > 0.0 {
+                let step = self.remaining.min(length);
+                let end = start + (to - start) * (step / length);
+                if self.open {
+                    self.builder.line_to(end);
+                }
+                self.remaining -= step;
+                length -= step;
+                start = end;
+                if self.remaining <= 0.0 {
+                    self.index = (self.index + 1) % self.pattern.len();
+                    self.remaining = self.pattern[self.index];
+                    if self.index % 2 == 0 {
+                        self.builder.begin(start);
+                        self.open = true;
+                    } else {
+                        self.end();
+                    }
+                }
+            }
+        }
+    }
+
+    let mut dasher = Dasher {
+        builder: Path::builder(),
+        pattern,
+        index: 0,
+        remaining: 0.0,
+        open: false,
+    };
+    for event in path.iter().flattened(TOLERANCE) {
+        match event {
+            PathEvent::Begin { at } => dasher.begin(at),
+            PathEvent::Line { from, to } => dasher.segment(from, to),
+            PathEvent::End { last, first, close } => {
+                if close {
+                    dasher.segment(last, first);
+                }
+                dasher.end();
+            }
+            _ => {}
+        }
+    }
+    dasher.builder.build()
+}
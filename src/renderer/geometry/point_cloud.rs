@@ -0,0 +1,282 @@
+use crate::core::*;
+use crate::renderer::*;
+use std::f32::consts::PI;
+
+///
+/// The size of each point in a [PointCloudGeometry].
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PointSize {
+    /// The diameter of each point in physical pixels, ie. the points have the same size on the screen independent of the distance to the viewer.
+    Pixels(f32),
+    /// The diameter of each point in world space units, ie. the points get smaller when further away from the viewer.
+    World(f32),
+}
+
+impl Default for PointSize {
+    fn default() -> Self {
+        Self::Pixels(2.0)
+    }
+}
+
+///
+/// The shape of each point in a [PointCloudGeometry].
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PointShape {
+    /// Each point is a square facing the viewer.
+    #[default]
+    Square,
+    /// Each point is a disc facing the viewer.
+    Round,
+}
+
+///
+/// A point cloud geometry where each point is rendered as a small square or disc facing the viewer, see [PointShape],
+/// with a size in either pixels or world space units, see [PointSize].
+/// This is much faster than rendering each point as an instanced mesh, so it can be used for point clouds with millions of points, for example scanned data.
+///
+/// The geometry provides the world position, a normal facing the viewer, the color and uv coordinates across each point to the material.
+/// The color of each point is the color given in the [PointCloud] multiplied by the intensity, if specified using [PointCloudGeometry::set_intensities].
+/// Unlit point clouds can be made easier to read by applying the [EyeDomeLightingEffect].
+///
+pub struct PointCloudGeometry {
+    context: Context,
+    square_buffer: VertexBuffer<Vec2>,
+    disc_buffer: VertexBuffer<Vec2>,
+    center_buffer: InstanceBuffer<Vec3>,
+    color_buffer: Option<InstanceBuffer<Vec4>>,
+    intensity_buffer: Option<InstanceBuffer<f32>>,
+    point_size: PointSize,
+    point_shape: PointShape,
+    transformation: Mat4,
+    aabb: AxisAlignedBoundingBox,
+}
+
+impl PointCloudGeometry {
+    ///
+    /// Creates a new point cloud geometry from the positions and colors in the given [PointCloud].
+    ///
+    pub fn new(context: &Context, point_cloud: &PointCloud) -> Self {
+        let square_buffer = VertexBuffer::new_with_data(
+            context,
+            &[
+                vec2(-1.0, -1.0),
+                vec2(1.0, -1.0),
+                vec2(1.0, 1.0),
+                vec2(1.0, 1.0),
+                vec2(-1.0, 1.0),
+                vec2(-1.0, -1.0),
+            ],
+        );
+        const DISC_TRIANGLES: u32 = 12;
+        let disc_corner = |i: u32| {
+            let angle = 2.0 * PI * i as f32 / DISC_TRIANGLES as f32;
+            vec2(angle.cos(), angle.sin())
+        };
+        let disc_buffer = VertexBuffer::new_with_data(
+            context,
+            &(0..DISC_TRIANGLES)
+                .flat_map(|i| [vec2(0.0, 0.0), disc_corner(i), disc_corner(i + 1)])
+                .collect::<Vec<_>>(),
+        );
+        let mut geometry = Self {
+            context: context.clone(),
+            square_buffer,
+            disc_buffer,
+            center_buffer: InstanceBuffer::new(context),
+            color_buffer: None,
+            intensity_buffer: None,
+            point_size: PointSize::default(),
+            point_shape: PointShape::default(),
+            transformation: Mat4::identity(),
+            aabb: AxisAlignedBoundingBox::EMPTY,
+        };
+        geometry.set_point_cloud(point_cloud);
+        geometry
+    }
+
+    ///
+    /// Sets the positions and colors of the points from the given [PointCloud].
+    /// The intensities are removed if the number of points changes.
+    ///
+    pub fn set_point_cloud(&mut self, point_cloud: &PointCloud) {
+        let positions = point_cloud.positions.to_f32();
+        if positions.len() as u32 != self.center_buffer.instance_count() {
+            self.intensity_buffer = None;
+        }
+        self.aabb = AxisAlignedBoundingBox::new_with_positions(&positions);
+        self.center_buffer.fill(&positions);
+        self.color_buffer = point_cloud.colors.as_ref().map(|colors| {
+            InstanceBuffer::new_with_data(
+                &self.context,
+                &colors
+                    .iter()
+                    .map(|c| c.to_linear_srgb())
+                    .collect::<Vec<_>>(),
+            )
+        });
+    }
+
+    ///
+    /// Sets the intensity of each point which is multiplied with the color of the point, for example the intensity returned by a laser scanner.
+    /// The number of intensities must be the same as the number of points. Use `None` to remove the intensities.
+    ///
+    pub fn set_intensities(&mut self, intensities: Option<&[f32]>) {
+        self.intensity_buffer = intensities
+            .map(|intensities| InstanceBuffer::new_with_data(&self.context, intensities));
+    }
+
+    ///
+    /// Returns the size of the points.
+    ///
+    pub fn point_size(&self) -> PointSize {
+        self.point_size
+    }
+
+    ///
+    /// Sets the size of the points.
+    ///
+    pub fn set_point_size(&mut self, point_size: PointSize) {
+        self.point_size = point_size;
+    }
+
+    ///
+    /// Returns the shape of the points.
+    ///
+    pub fn point_shape(&self) -> PointShape {
+        self.point_shape
+    }
+
+    ///
+    /// Sets the shape of the points.
+    ///
+    pub fn set_point_shape(&mut self, point_shape: PointShape) {
+        self.point_shape = point_shape;
+    }
+
+    ///
+    /// Returns the local to world transformation applied to all points.
+    ///
+    pub fn transformation(&self) -> Mat4 {
+        self.transformation
+    }
+
+    ///
+    /// Set the local to world transformation applied to all points.
+    ///
+    pub fn set_transformation(&mut self, transformation: Mat4) {
+        self.transformation = transformation;
+    }
+
+    fn draw(&self, program: &Program, render_states: RenderStates, viewer: &dyn Viewer) {
+        let viewport = viewer.viewport();
+        program.use_uniform("viewProjection", viewer.projection() * viewer.view());
+        program.use_uniform("view", viewer.view());
+        program.use_uniform("transformation", self.transformation);
+        program.use_uniform(
+            "viewportSize",
+            vec2(viewport.width as f32, viewport.height as f32),
+        );
+        let (point_size, world_size) = match self.point_size {
+            PointSize::Pixels(size) => (size, 0),
+            PointSize::World(size) => (size, 1),
+        };
+        program.use_uniform("pointSize", point_size);
+        program.use_uniform("worldSize", world_size);
+        let corner_buffer = match self.point_shape {
+            PointShape::Square => &self.square_buffer,
+            PointShape::Round => &self.disc_buffer,
+        };
+        program.use_vertex_attribute("corner", corner_buffer);
+        program.use_instance_attribute("center", &self.center_buffer);
+        if let Some(color_buffer) = &self.color_buffer {
+            if program.requires_attribute("point_color") {
+                program.use_instance_attribute("point_color", color_buffer);
+            }
+        }
+        if let Some(intensity_buffer) = &self.intensity_buffer {
+            if program.requires_attribute("intensity") {
+                program.use_instance_attribute("intensity", intensity_buffer);
+            }
+        }
+        program.draw_arrays_instanced(
+            render_states,
+            viewport,
+            corner_buffer.vertex_count(),
+            self.center_buffer.instance_count(),
+        )
+    }
+}
+
+impl<'a> IntoIterator for &'a PointCloudGeometry {
+    type Item = &'a dyn Geometry;
+    type IntoIter = std::iter::Once<&'a dyn Geometry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        std::iter::once(self)
+    }
+}
+
+impl Geometry for PointCloudGeometry {
+    fn draw(&self, viewer: &dyn Viewer, program: &Program, render_states: RenderStates) {
+        self.draw(program, render_states, viewer);
+    }
+
+    fn vertex_shader_source(&self) -> String {
+        format!(
+            "{}{}{}",
+            if self.color_buffer.is_some() {
+                "#define USE_COLORS\n"
+            } else {
+                ""
+            },
+            if self.intensity_buffer.is_some() {
+                "#define USE_INTENSITIES\n"
+            } else {
+                ""
+            },
+            include_str!("shaders/point_cloud.vert"),
+        )
+    }
+
+    fn id(&self) -> GeometryId {
+        GeometryId::PointCloudGeometry(self.color_buffer.is_some(), self.intensity_buffer.is_some())
+    }
+
+    fn render_with_material(
+        &self,
+        material: &dyn Material,
+        viewer: &dyn Viewer,
+        lights: &[&dyn Light],
+    ) {
+        if let Err(e) = render_with_material(&self.context, viewer, &self, material, lights) {
+            panic!("{}", e.to_string());
+        }
+    }
+
+    fn render_with_effect(
+        &self,
+        material: &dyn Effect,
+        viewer: &dyn Viewer,
+        lights: &[&dyn Light],
+        color_texture: Option<ColorTexture>,
+        depth_texture: Option<DepthTexture>,
+    ) {
+        if let Err(e) = render_with_effect(
+            &self.context,
+            viewer,
+            self,
+            material,
+            lights,
+            color_texture,
+            depth_texture,
+        ) {
+            panic!("{}", e.to_string());
+        }
+    }
+
+    fn aabb(&self) -> AxisAlignedBoundingBox {
+        self.aabb.transformed(self.transformation)
+    }
+}
@@ -0,0 +1,607 @@
+use crate::core::*;
+use crate::renderer::*;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 4] = b"3DPC";
+const VERSION: u32 = 1;
+const NO_CHILD: u32 = u32::MAX;
+const HEADER_SIZE: u64 = 16;
+const NODE_SIZE: u64 = 4 * 7 + 4 * 8 + 4 + 8;
+const GRID_RESOLUTION: f32 = 128.0;
+const MAX_DEPTH: u32 = 20;
+
+///
+/// A node in a [PointCloudOctree].
+///
+#[derive(Clone, Debug)]
+pub struct PointCloudOctreeNode {
+    /// The bounding box of the node, which is a cube containing the bounding boxes of all the children.
+    pub aabb: AxisAlignedBoundingBox,
+    /// The approximate distance between the points in this node, or zero if this node contains all the remaining points in its part of the point cloud.
+    pub spacing: f32,
+    /// The indices of the children of this node in [PointCloudOctree::nodes].
+    pub children: Vec<usize>,
+    /// The number of points stored in this node.
+    pub point_count: u32,
+    offset: u64,
+}
+
+///
+/// An octree which divides a point cloud into nodes stored in a chunked file, so only the nodes needed for rendering have to be loaded into memory.
+/// The root node contains a uniform subsample of the entire point cloud and each child node adds more points to its part of the parent node,
+/// so the point cloud is rendered in increasing level of detail by rendering a node together with its ancestors.
+///
+/// Use [PointCloudOctree::build] to build the octree from a [PointCloud] and write it to a file,
+/// [PointCloudOctree::open] to open an existing file and [StreamedPointCloud] to render it.
+///
+pub struct PointCloudOctree {
+    path: PathBuf,
+    nodes: Vec<PointCloudOctreeNode>,
+    has_colors: bool,
+}
+
+impl PointCloudOctree {
+    ///
+    /// Builds an octree from the given point cloud and writes it to a file at the given path.
+    /// A node is divided into eight children when it contains more than the given maximum number of points.
+    ///
+    pub fn build(
+        point_cloud: &PointCloud,
+        path: impl AsRef<Path>,
+        max_points_per_node: u32,
+    ) -> Result<Self, RendererError> {
+        let positions = point_cloud.positions.to_f32();
+        let colors = point_cloud.colors.as_ref();
+        if let Some(colors) = colors {
+            if colors.len() != positions.len() {
+                return Err(RendererError::InvalidBufferLength(
+                    "Point cloud colors".to_string(),
+                    positions.len(),
+                    colors.len(),
+                ));
+            }
+        }
+        let aabb = AxisAlignedBoundingBox::new_with_positions(&positions);
+        let size = aabb.size();
+        let cube_size = size.x.max(size.y).max(size.z).max(f32::EPSILON);
+        let root_aabb = AxisAlignedBoundingBox::new_with_positions(&[
+            aabb.min(),
+            aabb.min() + vec3(cube_size, cube_size, cube_size),
+        ]);
+
+        // Build the nodes and the point indices for each node
+        let mut nodes: Vec<PointCloudOctreeNode> = Vec::new();
+        let mut node_indices = Vec::new();
+        let mut stack = vec![(
+            None,
+            root_aabb,
+            (0..positions.len() as u32).collect::<Vec<_>>(),
+            0,
+        )];
+        while let Some((parent, aabb, indices, depth)) = stack.pop() {
+            let node_index = nodes.len();
+            if let Some(parent) = parent {
+                nodes[parent].children.push(node_index);
+            }
+            let (kept, remaining, spacing) =
+                if indices.len() as u32 <= max_points_per_node.max(1) || depth >= MAX_DEPTH {
+                    (indices, Vec::new(), 0.0)
+                } else {
+                    subsample(&positions, &aabb, indices)
+                };
+            nodes.push(PointCloudOctreeNode {
+                aabb,
+                spacing: if remaining.is_empty() { 0.0 } else { spacing },
+                children: Vec::new(),
+                point_count: kept.len() as u32,
+                offset: 0,
+            });
+            node_indices.push(kept);
+            if !remaining.is_empty() {
+                let center = aabb.center();
+                let half_size = 0.5 * aabb.size();
+                let mut octants = vec![Vec::new(); 8];
+                for i in remaining {
+                    octants[octant(center, positions[i as usize])].push(i);
+                }
+                for (o, indices) in octants.into_iter().enumerate().rev() {
+                    if !indices.is_empty() {
+                        let min = aabb.min()
+                            + vec3(
+                                if o & 1 == 1 { half_size.x } else { 0.0 },
+                                if o & 2 == 2 { half_size.y } else { 0.0 },
+                                if o & 4 == 4 { half_size.z } else { 0.0 },
+                            );
+                        stack.push((
+                            Some(node_index),
+                            AxisAlignedBoundingBox::new_with_positions(&[min, min + half_size]),
+                            indices,
+                            depth + 1,
+                        ));
+                    }
+                }
+            }
+        }
+
+        // Write the header, the node table and then the points of each node
+        let mut offset = HEADER_SIZE + NODE_SIZE * nodes.len() as u64;
+        for node in nodes.iter_mut() {
+            node.offset = offset;
+            offset += node.point_count as u64 * if colors.is_some() { 16 } else { 12 };
+        }
+        let write = || -> std::io::Result<()> {
+            let mut writer = BufWriter::new(File::create(path.as_ref())?);
+            writer.write_all(MAGIC)?;
+            writer.write_all(&VERSION.to_le_bytes())?;
+            writer.write_all(&(nodes.len() as u32).to_le_bytes())?;
+            writer.write_all(&(colors.is_some() as u32).to_le_bytes())?;
+            for node in nodes.iter() {
+                let (min, max) = (node.aabb.min(), node.aabb.max());
+                for value in [min.x, min.y, min.z, max.x, max.y, max.z, node.spacing] {
+                    writer.write_all(&value.to_le_bytes())?;
+                }
+                for i in 0..8 {
+                    let child = node.children.get(i).map(|c| *c as u32).unwrap_or(NO_CHILD);
+                    writer.write_all(&child.to_le_bytes())?;
+                }
+                writer.write_all(&node.point_count.to_le_bytes())?;
+                writer.write_all(&node.offset.to_le_bytes())?;
+            }
+            for indices in node_indices.iter() {
+                for i in indices {
+                    let p = positions[*i as usize];
+                    for value in [p.x, p.y, p.z] {
+                        writer.write_all(&value.to_le_bytes())?;
+                    }
+                }
+                if let Some(colors) = colors {
+                    for i in indices {
+                        let c = colors[*i as usize];
+                        writer.write_all(&[c.r, c.g, c.b, c.a])?;
+                    }
+                }
+            }
+            writer.flush()
+        };
+        write().map_err(io_error(path.as_ref()))?;
+        Ok(Self {
+            path: path.as_ref().to_path_buf(),
+            nodes,
+            has_colors: colors.is_some(),
+        })
+    }
+
+    ///
+    /// Opens an octree written by [PointCloudOctree::build]. Only the node hierarchy is read, the points are read when needed using [PointCloudOctree::read_node].
+    ///
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, RendererError> {
+        let mut file = File::open(path.as_ref()).map_err(io_error(path.as_ref()))?;
+        let mut header = [0u8; HEADER_SIZE as usize];
+        file.read_exact(&mut header)
+            .map_err(io_error(path.as_ref()))?;
+        if &header[0..4] != MAGIC || read_u32(&header[4..8]) != VERSION {
+            return Err(RendererError::InvalidPointCloudOctree(
+                path.as_ref().to_string_lossy().to_string(),
+            ));
+        }
+        let node_count = read_u32(&header[8..12]) as usize;
+        let has_colors = read_u32(&header[12..16]) != 0;
+        let mut table = vec![0u8; NODE_SIZE as usize * node_count];
+        file.read_exact(&mut table)
+            .map_err(io_error(path.as_ref()))?;
+        let nodes = table
+            .chunks_exact(NODE_SIZE as usize)
+            .map(|bytes| {
+                let f = |i: usize| f32::from_le_bytes(bytes[4 * i..4 * i + 4].try_into().unwrap());
+                PointCloudOctreeNode {
+                    aabb: AxisAlignedBoundingBox::new_with_positions(&[
+                        vec3(f(0), f(1), f(2)),
+                        vec3(f(3), f(4), f(5)),
+                    ]),
+                    spacing: f(6),
+                    children: (0..8)
+                        .map(|i| read_u32(&bytes[28 + 4 * i..32 + 4 * i]))
+                        .filter(|c| *c != NO_CHILD)
+                        .map(|c| c as usize)
+                        .collect(),
+                    point_count: read_u32(&bytes[60..64]),
+                    offset: u64::from_le_bytes(bytes[64..72].try_into().unwrap()),
+                }
+            })
+            .collect();
+        Ok(Self {
+            path: path.as_ref().to_path_buf(),
+            nodes,
+            has_colors,
+        })
+    }
+
+    ///
+    /// Returns the nodes of the octree, where the first node is the root.
+    ///
+    pub fn nodes(&self) -> &[PointCloudOctreeNode] {
+        &self.nodes
+    }
+
+    ///
+    /// Returns the total number of points in the octree.
+    ///
+    pub fn point_count(&self) -> u64 {
+        self.nodes.iter().map(|n| n.point_count as u64).sum()
+    }
+
+    ///
+    /// Returns whether or not the points have colors.
+    ///
+    pub fn has_colors(&self) -> bool {
+        self.has_colors
+    }
+
+    ///
+    /// Reads the points stored in the node with the given index from the file.
+    ///
+    pub fn read_node(&self, index: usize) -> Result<PointCloud, RendererError> {
+        let node = &self.nodes[index];
+        let count = node.point_count as usize;
+        let mut file = File::open(&self.path).map_err(io_error(&self.path))?;
+        file.seek(SeekFrom::Start(node.offset))
+            .map_err(io_error(&self.path))?;
+        let mut bytes = vec![0u8; count * if self.has_colors { 16 } else { 12 }];
+        file.read_exact(&mut bytes).map_err(io_error(&self.path))?;
+        let f = |i: usize| f32::from_le_bytes(bytes[4 * i..4 * i + 4].try_into().unwrap());
+        let positions = (0..count)
+            .map(|i| vec3(f(3 * i), f(3 * i + 1), f(3 * i + 2)))
+            .collect();
+        let colors = self.has_colors.then(|| {
+            bytes[12 * count..]
+                .chunks_exact(4)
+                .map(|c| Srgba::new(c[0], c[1], c[2], c[3]))
+                .collect()
+        });
+        Ok(PointCloud {
+            positions: Positions::F32(positions),
+            colors,
+        })
+    }
+}
+
+fn io_error(path: &Path) -> impl Fn(std::io::Error) -> RendererError + '_ {
+    move |e| RendererError::PointCloudOctreeIo(path.to_string_lossy().to_string(), e)
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes[0..4].try_into().unwrap())
+}
+
+fn octant(center: Vec3, position: Vec3) -> usize {
+    (position.x >= center.x) as usize
+        | ((position.y >= center.y) as usize) << 1
+        | ((position.z >= center.z) as usize) << 2
+}
+
+///
+/// Keeps the first point in each cell of a uniform grid inside the node and returns the kept points, the remaining points and the size of the grid cells.
+///
+fn subsample(
+    positions: &[Vec3],
+    aabb: &AxisAlignedBoundingBox,
+    indices: Vec<u32>,
+) -> (Vec<u32>, Vec<u32>, f32) {
+    let cell_size = aabb.size().x / GRID_RESOLUTION;
+    let min = aabb.min();
+    let mut cells = HashMap::new();
+    let mut kept = Vec::new();
+    let mut remaining = Vec::new();
+    for i in indices {
+        let p = (positions[i as usize] - min) / cell_size;
+        let cell = (p.x as u32, p.y as u32, p.z as u32);
+        if cells.insert(cell, ()).is_none() {
+            kept.push(i);
+        } else {
+            remaining.push(i);
+        }
+    }
+    (kept, remaining, cell_size)
+}
+
+///
+/// Statistics about the nodes of a [StreamedPointCloud], see [StreamedPointCloud::stats].
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StreamedPointCloudStats {
+    /// The number of nodes selected for rendering in the last [StreamedPointCloud::update].
+    pub selected_nodes: u32,
+    /// The number of selected nodes which are loaded and therefore rendered.
+    pub rendered_nodes: u32,
+    /// The number of points in the rendered nodes.
+    pub rendered_points: u64,
+    /// The number of nodes loaded into GPU memory.
+    pub resident_nodes: u32,
+    /// The number of points loaded into GPU memory.
+    pub resident_points: u64,
+    /// The number of points uploaded to the GPU in the last [StreamedPointCloud::update].
+    pub uploaded_points: u64,
+}
+
+///
+/// A point cloud stored in a [PointCloudOctree] which is streamed from the file to the GPU as needed.
+///
+/// Call [StreamedPointCloud::update] each frame to select the nodes to render based on the screen space error seen from the viewer,
+/// load the missing nodes within the [StreamedPointCloud::upload_budget] and evict the least recently used nodes
+/// when more than [StreamedPointCloud::max_resident_points] points are loaded.
+/// Until a node is loaded, the part of the point cloud it covers is rendered using the points of its ancestors.
+///
+pub struct StreamedPointCloud {
+    context: Context,
+    octree: PointCloudOctree,
+    resident: HashMap<usize, (PointCloudGeometry, u64)>,
+    selected: Vec<usize>,
+    frame: u64,
+    stats: StreamedPointCloudStats,
+    point_size: PointSize,
+    point_shape: PointShape,
+    transformation: Mat4,
+    /// The maximum distance in physical pixels between the rendered points before a node is refined, ie. the children of the node are rendered.
+    pub max_screen_space_error: f32,
+    /// The maximum number of points uploaded to the GPU in each [StreamedPointCloud::update]. At least one node is uploaded if any is missing.
+    pub upload_budget: u64,
+    /// The maximum number of points kept in GPU memory.
+    pub max_resident_points: u64,
+}
+
+impl StreamedPointCloud {
+    ///
+    /// Creates a new streamed point cloud from the given octree. No points are loaded until [StreamedPointCloud::update] is called.
+    ///
+    pub fn new(context: &Context, octree: PointCloudOctree) -> Self {
+        Self {
+            context: context.clone(),
+            octree,
+            resident: HashMap::new(),
+            selected: Vec::new(),
+            frame: 0,
+            stats: StreamedPointCloudStats::default(),
+            point_size: PointSize::default(),
+            point_shape: PointShape::default(),
+            transformation: Mat4::identity(),
+            max_screen_space_error: 2.0,
+            upload_budget: 1_000_000,
+            max_resident_points: 20_000_000,
+        }
+    }
+
+    ///
+    /// Returns the octree.
+    ///
+    pub fn octree(&self) -> &PointCloudOctree {
+        &self.octree
+    }
+
+    ///
+    /// Selects the nodes to render when seen from the given viewer, loads missing nodes and evicts the least recently used nodes.
+    ///
+    pub fn update(&mut self, viewer: &dyn Viewer) -> Result<(), RendererError> {
+        self.frame += 1;
+        self.select(viewer);
+
+        // Upload the missing nodes in the order of priority
+        let mut uploaded_points = 0;
+        for index in self.selected.iter() {
+            if let Some((_, last_used)) = self.resident.get_mut(index) {
+                *last_used = self.frame;
+            } else if uploaded_points == 0 || uploaded_points < self.upload_budget {
+                let mut geometry =
+                    PointCloudGeometry::new(&self.context, &self.octree.read_node(*index)?);
+                geometry.set_point_size(self.point_size);
+                geometry.set_point_shape(self.point_shape);
+                geometry.set_transformation(self.transformation);
+                uploaded_points += self.octree.nodes[*index].point_count as u64;
+                self.resident.insert(*index, (geometry, self.frame));
+            }
+        }
+
+        // Evict the least recently used nodes which are not used in this frame
+        let mut resident_points = self.resident_points();
+        if resident_points > self.max_resident_points {
+            let mut candidates = self
+                .resident
+                .iter()
+                .filter(|(_, (_, last_used))| *last_used < self.frame)
+                .map(|(index, (_, last_used))| (*last_used, *index))
+                .collect::<Vec<_>>();
+            candidates.sort();
+            for (_, index) in candidates {
+                if resident_points <= self.max_resident_points {
+                    break;
+                }
+                self.resident.remove(&index);
+                resident_points -= self.octree.nodes[index].point_count as u64;
+            }
+        }
+
+        let rendered = self
+            .selected
+            .iter()
+            .filter(|i| self.resident.contains_key(i))
+            .collect::<Vec<_>>();
+        self.stats = StreamedPointCloudStats {
+            selected_nodes: self.selected.len() as u32,
+            rendered_nodes: rendered.len() as u32,
+            rendered_points: rendered
+                .iter()
+                .map(|i| self.octree.nodes[**i].point_count as u64)
+                .sum(),
+            resident_nodes: self.resident.len() as u32,
+            resident_points,
+            uploaded_points,
+        };
+        Ok(())
+    }
+
+    ///
+    /// Selects the visible nodes, where the children of a node are only selected if the screen space error of the node is too large.
+    /// The nodes are ordered by decreasing screen space error, so the most important nodes are loaded first.
+    ///
+    fn select(&mut self, viewer: &dyn Viewer) {
+        let frustum = Frustum::new(viewer.projection() * viewer.view());
+        let height = viewer.viewport().height as f32;
+        let mut candidates = vec![(f32::INFINITY, 0)];
+        let mut selected = Vec::new();
+        while let Some((_, index)) = candidates.pop() {
+            let node = &self.octree.nodes[index];
+            let aabb = node.aabb.transformed(self.transformation);
+            if node.point_count == 0 || !frustum.contains(aabb) {
+                continue;
+            }
+            selected.push(index);
+            let radius = 0.5 * aabb.size().magnitude();
+            let scale = (self.transformation * vec4(1.0, 0.0, 0.0, 0.0)).magnitude();
+            let error = node.spacing * scale * height * screen_coverage(viewer, &aabb)
+                / (2.0 * radius).max(f32::EPSILON);
+            if error > self.max_screen_space_error {
+                for child in node.children.iter() {
+                    let child_aabb = self.octree.nodes[*child]
+                        .aabb
+                        .transformed(self.transformation);
+                    candidates.push((screen_coverage(viewer, &child_aabb), *child));
+                }
+                candidates
+                    .sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+            }
+        }
+        self.selected = selected;
+    }
+
+    fn resident_points(&self) -> u64 {
+        self.resident
+            .keys()
+            .map(|i| self.octree.nodes[*i].point_count as u64)
+            .sum()
+    }
+
+    ///
+    /// Returns statistics about the nodes selected, rendered and loaded in the last [StreamedPointCloud::update].
+    ///
+    pub fn stats(&self) -> StreamedPointCloudStats {
+        self.stats
+    }
+
+    ///
+    /// Sets the size of the points.
+    ///
+    pub fn set_point_size(&mut self, point_size: PointSize) {
+        self.point_size = point_size;
+        for (geometry, _) in self.resident.values_mut() {
+            geometry.set_point_size(point_size);
+        }
+    }
+
+    ///
+    /// Sets the shape of the points.
+    ///
+    pub fn set_point_shape(&mut self, point_shape: PointShape) {
+        self.point_shape = point_shape;
+        for (geometry, _) in self.resident.values_mut() {
+            geometry.set_point_shape(point_shape);
+        }
+    }
+
+    ///
+    /// Returns the local to world transformation applied to all points.
+    ///
+    pub fn transformation(&self) -> Mat4 {
+        self.transformation
+    }
+
+    ///
+    /// Set the local to world transformation applied to all points.
+    ///
+    pub fn set_transformation(&mut self, transformation: Mat4) {
+        self.transformation = transformation;
+        for (geometry, _) in self.resident.values_mut() {
+            geometry.set_transformation(transformation);
+        }
+    }
+
+    fn rendered_nodes(&self) -> impl Iterator<Item = &PointCloudGeometry> {
+        self.selected
+            .iter()
+            .filter_map(|i| self.resident.get(i).map(|(geometry, _)| geometry))
+    }
+}
+
+impl<'a> IntoIterator for &'a StreamedPointCloud {
+    type Item = &'a dyn Geometry;
+    type IntoIter = std::iter::Once<&'a dyn Geometry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        std::iter::once(self)
+    }
+}
+
+impl Geometry for StreamedPointCloud {
+    fn draw(&self, viewer: &dyn Viewer, program: &Program, render_states: RenderStates) {
+        for geometry in self.rendered_nodes() {
+            Geometry::draw(geometry, viewer, program, render_states);
+        }
+    }
+
+    fn vertex_shader_source(&self) -> String {
+        match self.rendered_nodes().next() {
+            Some(geometry) => geometry.vertex_shader_source(),
+            None => String::new(),
+        }
+    }
+
+    fn id(&self) -> GeometryId {
+        GeometryId::PointCloudGeometry(self.octree.has_colors, false)
+    }
+
+    fn render_with_material(
+        &self,
+        material: &dyn Material,
+        viewer: &dyn Viewer,
+        lights: &[&dyn Light],
+    ) {
+        if self.rendered_nodes().next().is_none() {
+            return;
+        }
+        if let Err(e) = render_with_material(&self.context, viewer, &self, material, lights) {
+            panic!("{}", e.to_string());
+        }
+    }
+
+    fn render_with_effect(
+        &self,
+        material: &dyn Effect,
+        viewer: &dyn Viewer,
+        lights: &[&dyn Light],
+        color_texture: Option<ColorTexture>,
+        depth_texture: Option<DepthTexture>,
+    ) {
+        if self.rendered_nodes().next().is_none() {
+            return;
+        }
+        if let Err(e) = render_with_effect(
+            &self.context,
+            viewer,
+            self,
+            material,
+            lights,
+            color_texture,
+            depth_texture,
+        ) {
+            panic!("{}", e.to_string());
+        }
+    }
+
+    fn aabb(&self) -> AxisAlignedBoundingBox {
+        match self.octree.nodes.first() {
+            Some(root) => root.aabb.transformed(self.transformation),
+            None => AxisAlignedBoundingBox::EMPTY,
+        }
+    }
+}
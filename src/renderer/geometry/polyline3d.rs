@@ -0,0 +1,483 @@
+use crate::core::*;
+use crate::renderer::*;
+use std::f32::consts::{FRAC_PI_2, PI};
+
+///
+/// The shape used where two segments of a [Polyline3D] or a stroked 2D path meet.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineJoin {
+    /// The outer edges of the two segments are extended until they meet.
+    /// The miter limit is the maximum allowed ratio between the length of the miter and the width of the line, as in SVG,
+    /// where the ratio is `1 / sin(angle / 2)` and `angle` is the angle between the two segments.
+    /// If the miter is longer than the miter limit, a round join is used instead for a [Polyline3D], see [Polyline3D::set_miter_limit],
+    /// and a bevel join is used instead for a stroked 2D path.
+    #[default]
+    Miter,
+    /// The two segments are joined by a circular arc.
+    Round,
+}
+
+///
+/// The shape used at the two ends of a [Polyline3D] or a stroked 2D path and at the ends of each dash.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineCap {
+    /// The line ends exactly at the end point.
+    #[default]
+    Butt,
+    /// The line is extended by half the width beyond the end point.
+    Square,
+    /// The line ends with a half circle centered at the end point.
+    Round,
+}
+
+///
+/// A polyline defined by a list of points in world space which can be given to a [Polyline3D].
+///
+#[derive(Clone, Debug, Default)]
+pub struct CpuPolyline {
+    /// The points of the polyline in world space.
+    pub points: Vec<Vec3>,
+    /// The color at each point, if not specified, the color is white. If specified, the length must be the same as the number of points.
+    pub colors: Option<Vec<Srgba>>,
+    /// The width in physical pixels at each point, if not specified, the width given to the [Polyline3D] is used. If specified, the length must be the same as the number of points.
+    pub widths: Option<Vec<f32>>,
+}
+
+///
+/// A set of polylines in 3D with a width in physical pixels, ie. the lines have the same width on the screen independent of the distance to the viewer.
+/// Each segment of the polylines is expanded to a screen aligned quad in the vertex shader and the segments are connected using the [LineJoin].
+/// The ends of the polylines, and of each dash if a dash pattern is specified, are shaped by the [LineCap].
+///
+/// All the segments of all the polylines are rendered in one draw call, so this is suitable for rendering many lines, for example roads, edges of a graph or a wireframe.
+/// The geometry provides the world position, the color and uv coordinates to the material,
+/// where the u coordinate is the distance along the polyline and the v coordinate is the position across the line in the range `[0..1]`.
+///
+pub struct Polyline3D {
+    context: Context,
+    corner_buffer: VertexBuffer<Vec4>,
+    segment_buffers: SegmentBuffers,
+    polylines: Vec<CpuPolyline>,
+    width: f32,
+    dash_pattern: Vec<f32>,
+    join: LineJoin,
+    cap: LineCap,
+    miter_limit: f32,
+    transformation: Mat4,
+    aabb: AxisAlignedBoundingBox,
+}
+
+impl Polyline3D {
+    ///
+    /// Creates a new set of polylines, where the given width in physical pixels is used for the polylines without widths specified.
+    ///
+    pub fn new(context: &Context, polylines: &[CpuPolyline], width: f32) -> Self {
+        let mut corners = Vec::new();
+        // The body of the segment
+        for (end, side) in [
+            (0.0, -1.0),
+            (1.0, -1.0),
+            (1.0, 1.0),
+            (1.0, 1.0),
+            (0.0, 1.0),
+            (0.0, -1.0),
+        ] {
+            corners.push(vec4(end, 0.0, side, 0.0));
+        }
+        // A half circle fan at each end of the segment
+        const FAN_TRIANGLES: u32 = 8;
+        let angle = |i: u32| -FRAC_PI_2 + PI * i as f32 / FAN_TRIANGLES as f32;
+        for end in [0.0, 1.0] {
+            for i in 0..FAN_TRIANGLES {
+                corners.push(vec4(end, 1.0, 0.0, 0.0));
+                corners.push(vec4(end, 1.0, angle(i), 1.0));
+                corners.push(vec4(end, 1.0, angle(i + 1), 1.0));
+            }
+        }
+        let mut polyline = Self {
+            context: context.clone(),
+            corner_buffer: VertexBuffer::new_with_data(context, &corners),
+            segment_buffers: SegmentBuffers::new(context, &[]),
+            polylines: polylines.to_vec(),
+            width,
+            dash_pattern: Vec::new(),
+            join: LineJoin::default(),
+            cap: LineCap::default(),
+            miter_limit: 4.0,
+            transformation: Mat4::identity(),
+            aabb: AxisAlignedBoundingBox::EMPTY,
+        };
+        polyline.update();
+        polyline
+    }
+
+    ///
+    /// Sets the polylines.
+    ///
+    pub fn set_polylines(&mut self, polylines: &[CpuPolyline]) {
+        self.polylines = polylines.to_vec();
+        self.update();
+    }
+
+    ///
+    /// Sets the width in physical pixels used for the polylines without widths specified.
+    ///
+    pub fn set_width(&mut self, width: f32) {
+        self.width = width;
+        self.update();
+    }
+
+    ///
+    /// Sets the dash pattern given as alternating lengths of dashes and gaps in world space units along the polylines, starting with a dash.
+    /// If the pattern has an odd number of lengths, it is repeated to get an even number of lengths.
+    /// An empty pattern results in solid lines.
+    ///
+    pub fn set_dash_pattern(&mut self, dash_pattern: &[f32]) {
+        self.dash_pattern = dash_pattern.to_vec();
+        if self.dash_pattern.len() % 2 == 1 {
+            self.dash_pattern.extend_from_slice(dash_pattern);
+        }
+        self.update();
+    }
+
+    ///
+    /// Sets the shape used where two segments meet.
+    ///
+    pub fn set_join(&mut self, join: LineJoin) {
+        self.join = join;
+    }
+
+    ///
+    /// Sets the shape used at the ends of the polylines and the ends of each dash.
+    ///
+    pub fn set_cap(&mut self, cap: LineCap) {
+        self.cap = cap;
+    }
+
+    ///
+    /// Sets the miter limit as defined in [LineJoin::Miter], longer miters are replaced by round joins.
+    /// The default is `4.0`.
+    ///
+    pub fn set_miter_limit(&mut self, miter_limit: f32) {
+        self.miter_limit = miter_limit;
+    }
+
+    ///
+    /// Returns the local to world transformation applied to all polylines.
+    ///
+    pub fn transformation(&self) -> Mat4 {
+        self.transformation
+    }
+
+    ///
+    /// Set the local to world transformation applied to all polylines.
+    ///
+    pub fn set_transformation(&mut self, transformation: Mat4) {
+        self.transformation = transformation;
+    }
+
+    fn update(&mut self) {
+        let pieces = self
+            .polylines
+            .iter()
+            .flat_map(|polyline| {
+                dashes(&polyline_vertices(polyline, self.width), &self.dash_pattern)
+            })
+            .collect::<Vec<_>>();
+        let mut segments = Vec::new();
+        for piece in pieces.iter() {
+            for i in 0..piece.len() - 1 {
+                segments.push(Segment {
+                    start: &piece[i],
+                    end: &piece[i + 1],
+                    previous: if i > 0 { Some(&piece[i - 1]) } else { None },
+                    next: piece.get(i + 2),
+                });
+            }
+        }
+        self.segment_buffers = SegmentBuffers::new(&self.context, &segments);
+        let positions = self
+            .polylines
+            .iter()
+            .flat_map(|p| p.points.iter().copied())
+            .collect::<Vec<_>>();
+        self.aabb = AxisAlignedBoundingBox::new_with_positions(&positions);
+    }
+
+    fn draw(&self, program: &Program, render_states: RenderStates, viewer: &dyn Viewer) {
+        let buffers = &self.segment_buffers;
+        if buffers.count == 0 {
+            return;
+        }
+        let viewport = viewer.viewport();
+        program.use_uniform("viewProjection", viewer.projection() * viewer.view());
+        program.use_uniform("transformation", self.transformation);
+        program.use_uniform(
+            "viewportSize",
+            vec2(viewport.width as f32, viewport.height as f32),
+        );
+        program.use_uniform(
+            "join",
+            match self.join {
+                LineJoin::Miter => 0,
+                LineJoin::Round => 1,
+            },
+        );
+        program.use_uniform(
+            "cap",
+            match self.cap {
+                LineCap::Butt => 0,
+                LineCap::Square => 1,
+                LineCap::Round => 2,
+            },
+        );
+        program.use_uniform("miterLimit", self.miter_limit);
+        program.use_vertex_attribute("corner", &self.corner_buffer);
+        program.use_instance_attribute("start", &buffers.start);
+        program.use_instance_attribute("end", &buffers.end);
+        program.use_instance_attribute("previous", &buffers.previous);
+        program.use_instance_attribute("next", &buffers.next);
+        program.use_instance_attribute("widths", &buffers.widths);
+        if program.requires_attribute("start_color") {
+            program.use_instance_attribute("start_color", &buffers.start_color);
+            program.use_instance_attribute("end_color", &buffers.end_color);
+        }
+        if program.requires_attribute("distances") {
+            program.use_instance_attribute("distances", &buffers.distances);
+        }
+        program.draw_arrays_instanced(
+            render_states,
+            viewport,
+            self.corner_buffer.vertex_count(),
+            buffers.count,
+        )
+    }
+}
+
+impl<'a> IntoIterator for &'a Polyline3D {
+    type Item = &'a dyn Geometry;
+    type IntoIter = std::iter::Once<&'a dyn Geometry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        std::iter::once(self)
+    }
+}
+
+impl Geometry for Polyline3D {
+    fn draw(&self, viewer: &dyn Viewer, program: &Program, render_states: RenderStates) {
+        self.draw(program, render_states, viewer);
+    }
+
+    fn vertex_shader_source(&self) -> String {
+        include_str!("shaders/polyline3d.vert").to_owned()
+    }
+
+    fn id(&self) -> GeometryId {
+        GeometryId::Polyline3D
+    }
+
+    fn render_with_material(
+        &self,
+        material: &dyn Material,
+        viewer: &dyn Viewer,
+        lights: &[&dyn Light],
+    ) {
+        if let Err(e) = render_with_material(&self.context, viewer, &self, material, lights) {
+            panic!("{}", e.to_string());
+        }
+    }
+
+    fn render_with_effect(
+        &self,
+        material: &dyn Effect,
+        viewer: &dyn Viewer,
+        lights: &[&dyn Light],
+        color_texture: Option<ColorTexture>,
+        depth_texture: Option<DepthTexture>,
+    ) {
+        if let Err(e) = render_with_effect(
+            &self.context,
+            viewer,
+            self,
+            material,
+            lights,
+            color_texture,
+            depth_texture,
+        ) {
+            panic!("{}", e.to_string());
+        }
+    }
+
+    fn aabb(&self) -> AxisAlignedBoundingBox {
+        self.aabb.transformed(self.transformation)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct PolylineVertex {
+    position: Vec3,
+    color: Vec4,
+    width: f32,
+    distance: f32,
+}
+
+impl PolylineVertex {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            position: self.position + (other.position - self.position) * t,
+            color: self.color + (other.color - self.color) * t,
+            width: self.width + (other.width - self.width) * t,
+            distance: self.distance + (other.distance - self.distance) * t,
+        }
+    }
+}
+
+///
+/// Returns the vertices of the polyline with the distance along the polyline, where consecutive duplicate points are removed.
+///
+fn polyline_vertices(polyline: &CpuPolyline, width: f32) -> Vec<PolylineVertex> {
+    let mut vertices: Vec<PolylineVertex> = Vec::with_capacity(polyline.points.len());
+    for (i, position) in polyline.points.iter().enumerate() {
+        let distance = match vertices.last() {
+            Some(last) if last.position == *position => continue,
+            Some(last) => last.distance + last.position.distance(*position),
+            None => 0.0,
+        };
+        vertices.push(PolylineVertex {
+            position: *position,
+            color: polyline
+                .colors
+                .as_ref()
+                .and_then(|c| c.get(i))
+                .map(|c| c.to_linear_srgb())
+                .unwrap_or(vec4(1.0, 1.0, 1.0, 1.0)),
+            width: polyline
+                .widths
+                .as_ref()
+                .and_then(|w| w.get(i))
+                .copied()
+                .unwrap_or(width),
+            distance,
+        });
+    }
+    vertices
+}
+
+///
+/// Splits the vertices into the pieces of the polyline defined by the dash pattern, ie. one piece for each dash.
+///
+fn dashes(vertices: &[PolylineVertex], dash_pattern: &[f32]) -> Vec<Vec<PolylineVertex>> {
+    if vertices.len() < 2 {
+        return Vec::new();
+    }
+    if dash_pattern.len() < 2 || dash_pattern.iter().sum::<f32>() <= 0.0 {
+        return vec![vertices.to_vec()];
+    }
+    let mut pieces = Vec::new();
+    let mut piece = vec![vertices[0]];
+    let mut dash_index = 0;
+    let mut dash_end = dash_pattern[0].max(0.0);
+    for i in 1..vertices.len() {
+        let (a, b) = (&vertices[i - 1], &vertices[i]);
+        while dash_end < b.distance {
+            let vertex = a.lerp(b, (dash_end - a.distance) / (b.distance - a.distance));
+            if dash_index % 2 == 0 {
+                piece.push(vertex);
+                if piece.len() > 1 {
+                    pieces.push(piece);
+                }
+                piece = Vec::new();
+            } else {
+                piece.push(vertex);
+            }
+            dash_index = (dash_index + 1) % dash_pattern.len();
+            dash_end += dash_pattern[dash_index].max(0.0);
+        }
+        if dash_index % 2 == 0 {
+            piece.push(*b);
+        }
+    }
+    if piece.len() > 1 {
+        pieces.push(piece);
+    }
+    pieces
+}
+
+struct Segment<'a> {
+    start: &'a PolylineVertex,
+    end: &'a PolylineVertex,
+    previous: Option<&'a PolylineVertex>,
+    next: Option<&'a PolylineVertex>,
+}
+
+struct SegmentBuffers {
+    count: u32,
+    start: InstanceBuffer<Vec3>,
+    end: InstanceBuffer<Vec3>,
+    previous: InstanceBuffer<Vec4>,
+    next: InstanceBuffer<Vec4>,
+    start_color: InstanceBuffer<Vec4>,
+    end_color: InstanceBuffer<Vec4>,
+    widths: InstanceBuffer<Vec2>,
+    distances: InstanceBuffer<Vec2>,
+}
+
+impl SegmentBuffers {
+    fn new(context: &Context, segments: &[Segment]) -> Self {
+        let neighbour = |v: Option<&PolylineVertex>| {
+            v.map(|v| v.position.extend(1.0))
+                .unwrap_or(vec4(0.0, 0.0, 0.0, 0.0))
+        };
+        Self {
+            count: segments.len() as u32,
+            start: InstanceBuffer::new_with_data(
+                context,
+                &segments
+                    .iter()
+                    .map(|s| s.start.position)
+                    .collect::<Vec<_>>(),
+            ),
+            end: InstanceBuffer::new_with_data(
+                context,
+                &segments.iter().map(|s| s.end.position).collect::<Vec<_>>(),
+            ),
+            previous: InstanceBuffer::new_with_data(
+                context,
+                &segments
+                    .iter()
+                    .map(|s| neighbour(s.previous))
+                    .collect::<Vec<_>>(),
+            ),
+            next: InstanceBuffer::new_with_data(
+                context,
+                &segments
+                    .iter()
+                    .map(|s| neighbour(s.next))
+                    .collect::<Vec<_>>(),
+            ),
+            start_color: InstanceBuffer::new_with_data(
+                context,
+                &segments.iter().map(|s| s.start.color).collect::<Vec<_>>(),
+            ),
+            end_color: InstanceBuffer::new_with_data(
+                context,
+                &segments.iter().map(|s| s.end.color).collect::<Vec<_>>(),
+            ),
+            widths: InstanceBuffer::new_with_data(
+                context,
+                &segments
+                    .iter()
+                    .map(|s| vec2(s.start.width, s.end.width))
+                    .collect::<Vec<_>>(),
+            ),
+            distances: InstanceBuffer::new_with_data(
+                context,
+                &segments
+                    .iter()
+                    .map(|s| vec2(s.start.distance, s.end.distance))
+                    .collect::<Vec<_>>(),
+            ),
+        }
+    }
+}
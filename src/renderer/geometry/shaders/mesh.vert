@@ -15,6 +15,7 @@ uniform mat4 animationTransform;
 in vec4 row1;
 in vec4 row2;
 in vec4 row3;
+in int instance_index;
 #endif
 
 out vec3 pos;
@@ -113,5 +114,9 @@ void main()
 #ifdef USE_INSTANCE_COLORS
     col *= instance_color;
 #endif
+#ifdef USE_INSTANCE_TRANSFORMS
+    instance_id = instance_index;
+#else
     instance_id = gl_InstanceID;
+#endif
 }
\ No newline at end of file
@@ -0,0 +1,50 @@
+uniform mat4 viewProjection;
+uniform mat4 view;
+uniform mat4 transformation;
+uniform vec2 viewportSize;
+uniform float pointSize;
+uniform int worldSize;
+
+in vec2 corner;
+in vec3 center;
+
+#ifdef USE_COLORS
+in vec4 point_color;
+#endif
+
+#ifdef USE_INTENSITIES
+in float intensity;
+#endif
+
+out vec3 pos;
+out vec3 nor;
+out vec2 uvs;
+out vec4 col;
+flat out int instance_id;
+
+void main()
+{
+    vec3 right = vec3(view[0][0], view[1][0], view[2][0]);
+    vec3 up = vec3(view[0][1], view[1][1], view[2][1]);
+    vec4 world_center = transformation * vec4(center, 1.0);
+    if (worldSize == 1) {
+        vec3 p = world_center.xyz / world_center.w + 0.5 * pointSize * (corner.x * right + corner.y * up);
+        pos = p;
+        gl_Position = viewProjection * vec4(p, 1.0);
+    } else {
+        pos = world_center.xyz / world_center.w;
+        gl_Position = viewProjection * world_center;
+        gl_Position.xy += pointSize * corner / viewportSize * gl_Position.w;
+    }
+    nor = vec3(view[0][2], view[1][2], view[2][2]);
+    uvs = 0.5 + 0.5 * corner;
+
+    col = vec4(1.0);
+#ifdef USE_COLORS
+    col = point_color;
+#endif
+#ifdef USE_INTENSITIES
+    col.rgb *= intensity;
+#endif
+    instance_id = gl_InstanceID;
+}
@@ -0,0 +1,117 @@
+uniform mat4 viewProjection;
+uniform mat4 transformation;
+uniform vec2 viewportSize;
+uniform int join;
+uniform int cap;
+uniform float miterLimit;
+
+// x: 0 at the start and 1 at the end of the segment
+// y: 0 for the body of the segment and 1 for the fan used for round joins and caps
+// z: the side of the body or the angle of the fan relative to the outward direction
+// w: the radius of the fan
+in vec4 corner;
+
+in vec3 start;
+in vec3 end;
+in vec4 previous;
+in vec4 next;
+in vec4 start_color;
+in vec4 end_color;
+in vec2 widths;
+in vec2 distances;
+
+out vec3 pos;
+out vec4 col;
+out vec2 uvs;
+flat out int instance_id;
+
+vec2 to_screen(vec4 clip_position) {
+    return 0.5 * (clip_position.xy / clip_position.w + 1.0) * viewportSize;
+}
+
+// Returns true and the offset of the miter joining this segment with the neighbouring segment if it is shorter than the miter limit.
+bool miter_offset(vec2 screen_position, vec4 neighbour, bool is_end, vec2 normal, float half_width, out vec2 offset) {
+    offset = vec2(0.0);
+    vec4 neighbour_clip = viewProjection * transformation * vec4(neighbour.xyz, 1.0);
+    if (neighbour_clip.w <= 0.0) {
+        return false;
+    }
+    vec2 neighbour_direction = is_end ? to_screen(neighbour_clip) - screen_position : screen_position - to_screen(neighbour_clip);
+    if (dot(neighbour_direction, neighbour_direction) < 1e-8) {
+        return false;
+    }
+    neighbour_direction = normalize(neighbour_direction);
+    vec2 miter = normal + vec2(-neighbour_direction.y, neighbour_direction.x);
+    if (dot(miter, miter) < 1e-8) {
+        return false;
+    }
+    miter = normalize(miter);
+    float miter_length = half_width / max(dot(miter, normal), 1e-4);
+    if (miter_length > miterLimit * half_width) {
+        return false;
+    }
+    offset = miter * miter_length;
+    return true;
+}
+
+void main()
+{
+    vec4 p0 = transformation * vec4(start, 1.0);
+    vec4 p1 = transformation * vec4(end, 1.0);
+    vec4 c0 = viewProjection * p0;
+    vec4 c1 = viewProjection * p1;
+
+    // Clip the segment against the plane through the eye, so the screen space direction is well defined
+    const float min_w = 1e-4;
+    if (c0.w < min_w && c1.w < min_w) {
+        gl_Position = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+    if (c0.w < min_w) {
+        c0 = mix(c0, c1, (min_w - c0.w) / (c1.w - c0.w));
+    } else if (c1.w < min_w) {
+        c1 = mix(c1, c0, (min_w - c1.w) / (c0.w - c1.w));
+    }
+
+    vec2 s0 = to_screen(c0);
+    vec2 s1 = to_screen(c1);
+    vec2 direction = s1 - s0;
+    direction = dot(direction, direction) > 1e-8 ? normalize(direction) : vec2(1.0, 0.0);
+    vec2 normal = vec2(-direction.y, direction.x);
+
+    bool is_end = corner.x > 0.5;
+    vec4 clip_position = is_end ? c1 : c0;
+    vec2 screen_position = is_end ? s1 : s0;
+    float half_width = 0.5 * (is_end ? widths.y : widths.x);
+    vec4 neighbour = is_end ? next : previous;
+    bool has_neighbour = neighbour.w > 0.5;
+    vec2 outward = is_end ? direction : -direction;
+
+    vec2 miter;
+    bool is_mitered = has_neighbour && join == 0 && miter_offset(screen_position, neighbour, is_end, normal, half_width, miter);
+
+    vec2 offset = vec2(0.0);
+    float side = 0.0;
+    if (corner.y < 0.5) {
+        side = corner.z;
+        if (is_mitered) {
+            offset = side * miter;
+        } else {
+            offset = side * half_width * normal;
+            if (!has_neighbour && cap == 1) {
+                offset += half_width * outward;
+            }
+        }
+    } else if (has_neighbour ? !is_mitered : cap == 2) {
+        vec2 d = cos(corner.z) * outward + sin(corner.z) * (is_end ? normal : -normal);
+        offset = corner.w * half_width * d;
+        side = corner.w * dot(d, normal);
+    }
+
+    gl_Position = clip_position + vec4(2.0 * offset / viewportSize * clip_position.w, 0.0, 0.0);
+
+    pos = is_end ? p1.xyz : p0.xyz;
+    col = is_end ? end_color : start_color;
+    uvs = vec2(is_end ? distances.y : distances.x, 0.5 + 0.5 * side);
+    instance_id = gl_InstanceID;
+}
@@ -40,6 +40,14 @@ mod environment;
 #[doc(inline)]
 pub use environment::*;
 
+mod reflection_probe;
+#[doc(inline)]
+pub use reflection_probe::*;
+
+mod spherical_harmonics;
+#[doc(inline)]
+pub use spherical_harmonics::*;
+
 use crate::core::*;
 use crate::renderer::viewer::*;
 use crate::renderer::LightId;
@@ -85,8 +93,12 @@ pub trait Light {
     ///
     /// **Note:** The last bit is reserved to internally implemented materials, so if implementing the `Light` trait
     /// outside of this crate, always return an id in the public use range as defined by [LightId].
+    /// The default implementation returns [LightId::FromSource] which means that the shader is identified by its source instead,
+    /// at the cost of generating the shader source for each draw call.
     ///
-    fn id(&self) -> LightId;
+    fn id(&self) -> LightId {
+        LightId::FromSource
+    }
 }
 
 impl<T: Light + ?Sized> Light for &T {
@@ -4,6 +4,7 @@ use crate::renderer::*;
 ///
 /// A light which shines on all surfaces.
 /// Can be uniform (a light that shines equally on any surface) or calculated from an environment map using the [Environment] struct.
+/// Local [ReflectionProbe]s can be added to replace the lighting from the environment inside the influence box of each probe.
 ///
 pub struct AmbientLight {
     /// The intensity of the light. This allows for higher intensity than 1 which can be used to simulate high intensity light sources like the sun.
@@ -12,6 +13,13 @@ pub struct AmbientLight {
     pub color: Srgba,
     /// The light shining from the environment. This is calculated based on an environment map.
     pub environment: Option<Environment>,
+    /// If specified, the diffuse light from the environment is evaluated from these spherical harmonics instead of the [Environment::irradiance_map].
+    /// This can also be used without an environment, in which case only the diffuse light is calculated.
+    pub spherical_harmonics: Option<SphericalHarmonics>,
+    /// Local reflection probes which replaces the light from the environment inside their influence box.
+    /// Only the first [MAX_REFLECTION_PROBES] probes are used, use [AmbientLight::sort_reflection_probes] to use the probes nearest to a position.
+    /// If the influence boxes overlap, the probes earlier in the list take precedence.
+    pub reflection_probes: Vec<ReflectionProbe>,
 }
 
 impl AmbientLight {
@@ -21,6 +29,8 @@ impl AmbientLight {
             intensity,
             color,
             environment: None,
+            spherical_harmonics: None,
+            reflection_probes: Vec::new(),
         }
     }
 
@@ -35,15 +45,147 @@ impl AmbientLight {
             intensity,
             color,
             environment: Some(Environment::new(context, environment_map)),
+            spherical_harmonics: None,
+            reflection_probes: Vec::new(),
         }
     }
+
+    ///
+    /// Constructs an ambient light where the diffuse light is calculated from the given spherical harmonics.
+    ///
+    pub fn new_with_spherical_harmonics(
+        intensity: f32,
+        color: Srgba,
+        spherical_harmonics: SphericalHarmonics,
+    ) -> Self {
+        Self {
+            intensity,
+            color,
+            environment: None,
+            spherical_harmonics: Some(spherical_harmonics),
+            reflection_probes: Vec::new(),
+        }
+    }
+
+    ///
+    /// Sorts the reflection probes by the distance from the given position (usually the camera position) to their influence box,
+    /// so that the [MAX_REFLECTION_PROBES] nearest probes are used.
+    ///
+    pub fn sort_reflection_probes(&mut self, position: Vec3) {
+        self.reflection_probes.sort_by(|a, b| {
+            a.distance(position)
+                .partial_cmp(&b.distance(position))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+    }
+
+    fn reflection_probe_count(&self) -> usize {
+        self.reflection_probes.len().min(MAX_REFLECTION_PROBES)
+    }
 }
 
 impl Light for AmbientLight {
     fn shader_source(&self, i: u32) -> String {
-        if self.environment.is_some() {
+        let probe_count = self.reflection_probe_count();
+        if probe_count > 0 {
+            let mut probes = String::new();
+            for j in 0..probe_count {
+                probes.push_str(&format!(
+                    "
+                    {{
+                        float weight = remaining * reflection_probe_weight(position, probeBoxMin{i}_{j}, probeBoxMax{i}_{j}, probeBlendDistance{i}_{j});
+                        vec3 probeR = reflection_probe_box_project(R, position, probeBoxMin{i}_{j}, probeBoxMax{i}_{j}, probePosition{i}_{j});
+                        irradiance += weight * texture(probeIrradianceMap{i}_{j}, N).rgb;
+                        prefilteredColor += weight * textureLod(probePrefilterMap{i}_{j}, probeR, roughness * MAX_REFLECTION_LOD).rgb;
+                        remaining -= weight;
+                    }}
+                    "
+                ));
+            }
+            format!(
+                "
+                {}
+                uniform sampler2D brdfLUT;
+                uniform vec3 ambientColor;
+                {}
+
+                #ifndef REFLECTION_PROBE_FUNCTIONS
+                #define REFLECTION_PROBE_FUNCTIONS
+                float reflection_probe_weight(vec3 position, vec3 boxMin, vec3 boxMax, float blendDistance)
+                {{
+                    vec3 d = min(position - boxMin, boxMax - position);
+                    return clamp(min(min(d.x, d.y), d.z) / blendDistance, 0.0, 1.0);
+                }}
+
+                vec3 reflection_probe_box_project(vec3 R, vec3 position, vec3 boxMin, vec3 boxMax, vec3 probePosition)
+                {{
+                    vec3 first = (boxMax - position) / R;
+                    vec3 second = (boxMin - position) / R;
+                    vec3 furthest = max(first, second);
+                    float dist = min(min(furthest.x, furthest.y), furthest.z);
+                    return position + R * dist - probePosition;
+                }}
+                #endif
+
+                vec3 calculate_lighting{}(vec3 surface_color, vec3 position, vec3 normal, vec3 view_direction, float metallic, float roughness, float occlusion)
+                {{
+                    vec3 N = normal;
+                    vec3 V = view_direction;
+                    vec3 R = reflect(-V, N);
+                    float NdV = max(0.001, dot(N, V));
+
+                    vec3 F0 = mix(vec3(0.04), surface_color, metallic);
+                    vec3 specular_fresnel = fresnel_schlick_roughness(F0, NdV, roughness);
+                    vec3 diffuse_fresnel = 1.0 - specular_fresnel;
+
+                    // Blend the lighting from the reflection probes, the remaining weight is given to the environment
+                    const float MAX_REFLECTION_LOD = 4.0;
+                    vec3 irradiance = vec3(0.0);
+                    vec3 prefilteredColor = vec3(0.0);
+                    float remaining = 1.0;
+                    {}
+                    {}
+
+                    vec3 diffuse = diffuse_fresnel * mix(surface_color, vec3(0.0), metallic) * irradiance;
+                    vec2 brdf  = texture(brdfLUT, vec2(NdV, roughness)).rg;
+                    vec3 specular = prefilteredColor * (specular_fresnel * brdf.x + brdf.y);
+
+                    return (diffuse + specular) * occlusion * ambientColor;
+                }}
+                ",
+                if self.environment.is_some() {
+                    "uniform samplerCube irradianceMap;\nuniform samplerCube prefilterMap;"
+                } else {
+                    ""
+                }
+                .to_string()
+                    + &spherical_harmonics_source(i, self.spherical_harmonics.is_some()),
+                (0..probe_count)
+                    .map(|j| format!(
+                        "uniform samplerCube probeIrradianceMap{i}_{j};
+                        uniform samplerCube probePrefilterMap{i}_{j};
+                        uniform vec3 probePosition{i}_{j};
+                        uniform vec3 probeBoxMin{i}_{j};
+                        uniform vec3 probeBoxMax{i}_{j};
+                        uniform float probeBlendDistance{i}_{j};
+                        "
+                    ))
+                    .collect::<String>(),
+                i,
+                probes,
+                match (self.environment.is_some(), self.spherical_harmonics.is_some()) {
+                    (true, false) => format!("irradiance += remaining * texture(irradianceMap, N).rgb;
+                    prefilteredColor += remaining * textureLod(prefilterMap, R, roughness * MAX_REFLECTION_LOD).rgb;"),
+                    (true, true) => format!("irradiance += remaining * spherical_harmonics_irradiance(N, sphericalHarmonics{i});
+                    prefilteredColor += remaining * textureLod(prefilterMap, R, roughness * MAX_REFLECTION_LOD).rgb;"),
+                    (false, true) => format!("irradiance += remaining * spherical_harmonics_irradiance(N, sphericalHarmonics{i});"),
+                    (false, false) => "irradiance += remaining;".to_string(),
+                },
+            )
+        } else if self.environment.is_some() {
             format!(
             "
+                {}
                 uniform samplerCube irradianceMap;
                 uniform samplerCube prefilterMap;
                 uniform sampler2D brdfLUT;
@@ -63,7 +205,7 @@ impl Light for AmbientLight {
                     vec3 diffuse_fresnel = 1.0 - specular_fresnel;
 
                     // Diffuse
-                    vec3 irradiance = texture(irradianceMap, N).rgb;
+                    vec3 irradiance = {};
                     vec3 diffuse = diffuse_fresnel * mix(surface_color, vec3(0.0), metallic) * irradiance;
                     
                     // sample both the pre-filter map and the BRDF lut and combine them together as per the Split-Sum approximation to get the IBL specular part.
@@ -75,7 +217,23 @@ impl Light for AmbientLight {
                     return (diffuse + specular) * occlusion * ambientColor;
                 }}
             
-            ", i)
+            ", spherical_harmonics_source(i, self.spherical_harmonics.is_some()), i,
+            if self.spherical_harmonics.is_some() {
+                format!("spherical_harmonics_irradiance(N, sphericalHarmonics{})", i)
+            } else {
+                "texture(irradianceMap, N).rgb".to_string()
+            })
+        } else if self.spherical_harmonics.is_some() {
+            format!(
+                "
+                    {}
+                    uniform vec3 ambientColor;
+                    vec3 calculate_lighting{}(vec3 surface_color, vec3 position, vec3 normal, vec3 view_direction, float metallic, float roughness, float occlusion)
+                    {{
+                        return occlusion * ambientColor * mix(surface_color, vec3(0.0), metallic) * spherical_harmonics_irradiance(normal, sphericalHarmonics{});
+                    }}
+                
+                ", spherical_harmonics_source(i, true), i, i)
         } else {
             format!(
                 "
@@ -88,11 +246,26 @@ impl Light for AmbientLight {
                 ", i)
         }
     }
-    fn use_uniforms(&self, program: &Program, _i: u32) {
+    fn use_uniforms(&self, program: &Program, i: u32) {
         if let Some(ref environment) = self.environment {
-            program.use_texture_cube("irradianceMap", &environment.irradiance_map);
+            if self.spherical_harmonics.is_none() {
+                program.use_texture_cube("irradianceMap", &environment.irradiance_map);
+            }
             program.use_texture_cube("prefilterMap", &environment.prefilter_map);
             program.use_texture("brdfLUT", &environment.brdf_map);
+        } else if let Some(probe) = self.reflection_probes.first() {
+            program.use_texture("brdfLUT", &probe.environment().brdf_map);
+        }
+        if let Some(ref spherical_harmonics) = self.spherical_harmonics {
+            spherical_harmonics.use_uniforms(program, &format!("sphericalHarmonics{}", i));
+        }
+        for (j, probe) in self
+            .reflection_probes
+            .iter()
+            .take(MAX_REFLECTION_PROBES)
+            .enumerate()
+        {
+            probe.use_uniforms(program, i, j);
         }
         program.use_uniform(
             "ambientColor",
@@ -101,7 +274,29 @@ impl Light for AmbientLight {
     }
 
     fn id(&self) -> LightId {
-        LightId::AmbientLight(self.environment.is_some())
+        let probe_count = self.reflection_probe_count();
+        if self.spherical_harmonics.is_some() {
+            LightId::AmbientLightWithSphericalHarmonics(
+                self.environment.is_some(),
+                probe_count as u8,
+            )
+        } else if probe_count > 0 {
+            LightId::AmbientLightWithReflectionProbes(self.environment.is_some(), probe_count as u8)
+        } else {
+            LightId::AmbientLight(self.environment.is_some())
+        }
+    }
+}
+
+fn spherical_harmonics_source(i: u32, use_spherical_harmonics: bool) -> String {
+    if use_spherical_harmonics {
+        format!(
+            "uniform vec3 sphericalHarmonics{}[9];\n{}",
+            i,
+            include_str!("shaders/spherical_harmonics.frag")
+        )
+    } else {
+        String::new()
     }
 }
 
@@ -111,6 +306,8 @@ impl Default for AmbientLight {
             color: Srgba::WHITE,
             intensity: 1.0,
             environment: None,
+            spherical_harmonics: None,
+            reflection_probes: Vec::new(),
         }
     }
 }
@@ -0,0 +1,189 @@
+use crate::core::*;
+use crate::renderer::*;
+
+///
+/// The maximum number of reflection probes that can be used by an [AmbientLight] at the same time.
+///
+pub const MAX_REFLECTION_PROBES: usize = 4;
+
+///
+/// A local light probe which captures the scene as seen from a position into a cube map and precalculates the image based lighting from it (see [Environment]).
+/// Add it to an [AmbientLight] to replace the lighting from the global environment with the local lighting inside the influence box of the probe.
+/// The reflections are box-projected onto the influence box, so that reflections of for example the walls of a room appear at the right position.
+///
+pub struct ReflectionProbe {
+    context: Context,
+    /// The position from where the scene is captured.
+    pub position: Vec3,
+    /// The box in which this probe affects the lighting. It should usually match the geometry it captures, for example the walls of a room.
+    pub influence: AxisAlignedBoundingBox,
+    /// The distance from the border of the influence box over which the lighting from this probe is faded out.
+    pub blend_distance: f32,
+    /// The resolution of each side of the captured cube map.
+    pub resolution: u32,
+    /// The near plane of the cameras used when capturing the scene.
+    pub z_near: f32,
+    /// The far plane of the cameras used when capturing the scene.
+    pub z_far: f32,
+    /// The color used where nothing is captured.
+    pub clear_color: Srgba,
+    environment: Environment,
+}
+
+impl ReflectionProbe {
+    ///
+    /// Creates a new reflection probe at the given position with the given influence box and captures the given objects with the given lights.
+    ///
+    pub fn new(
+        context: &Context,
+        position: Vec3,
+        influence: AxisAlignedBoundingBox,
+        resolution: u32,
+        objects: impl IntoIterator<Item = impl Object> + Clone,
+        lights: &[&dyn Light],
+    ) -> Self {
+        let environment = Environment::new(
+            context,
+            &capture_environment_map(
+                context,
+                position,
+                resolution,
+                0.01,
+                1000.0,
+                Srgba::BLACK,
+                objects,
+                lights,
+            ),
+        );
+        Self {
+            context: context.clone(),
+            position,
+            influence,
+            blend_distance: 0.0,
+            resolution,
+            z_near: 0.01,
+            z_far: 1000.0,
+            clear_color: Srgba::BLACK,
+            environment,
+        }
+    }
+
+    ///
+    /// Captures the given objects with the given lights again and updates the precalculated lighting.
+    /// Use this if the scene inside the influence box has changed or if any of the capture parameters has changed.
+    ///
+    pub fn capture(
+        &mut self,
+        objects: impl IntoIterator<Item = impl Object> + Clone,
+        lights: &[&dyn Light],
+    ) {
+        let environment_map = capture_environment_map(
+            &self.context,
+            self.position,
+            self.resolution,
+            self.z_near,
+            self.z_far,
+            self.clear_color,
+            objects,
+            lights,
+        );
+        self.environment = Environment::new(&self.context, &environment_map);
+    }
+
+    ///
+    /// Returns the precalculated lighting from the captured scene.
+    ///
+    pub fn environment(&self) -> &Environment {
+        &self.environment
+    }
+
+    ///
+    /// Returns the distance from the given position to the influence box of this probe, which is zero if the position is inside the box.
+    ///
+    pub fn distance(&self, position: Vec3) -> f32 {
+        let min = self.influence.min();
+        let max = self.influence.max();
+        let d = vec3(
+            (min.x - position.x).max(position.x - max.x).max(0.0),
+            (min.y - position.y).max(position.y - max.y).max(0.0),
+            (min.z - position.z).max(position.z - max.z).max(0.0),
+        );
+        d.magnitude()
+    }
+
+    pub(super) fn use_uniforms(&self, program: &Program, i: u32, j: usize) {
+        program.use_texture_cube(
+            &format!("probeIrradianceMap{}_{}", i, j),
+            &self.environment.irradiance_map,
+        );
+        program.use_texture_cube(
+            &format!("probePrefilterMap{}_{}", i, j),
+            &self.environment.prefilter_map,
+        );
+        program.use_uniform(&format!("probePosition{}_{}", i, j), self.position);
+        program.use_uniform(&format!("probeBoxMin{}_{}", i, j), self.influence.min());
+        program.use_uniform(&format!("probeBoxMax{}_{}", i, j), self.influence.max());
+        program.use_uniform(
+            &format!("probeBlendDistance{}_{}", i, j),
+            self.blend_distance.max(0.0001),
+        );
+    }
+}
+
+fn capture_environment_map(
+    context: &Context,
+    position: Vec3,
+    resolution: u32,
+    z_near: f32,
+    z_far: f32,
+    clear_color: Srgba,
+    objects: impl IntoIterator<Item = impl Object> + Clone,
+    lights: &[&dyn Light],
+) -> TextureCubeMap {
+    let environment_map = TextureCubeMap::new_empty::<[f16; 4]>(
+        context,
+        resolution,
+        resolution,
+        Interpolation::Linear,
+        Interpolation::Linear,
+        Some(Mipmap::default()),
+        Wrapping::ClampToEdge,
+        Wrapping::ClampToEdge,
+        Wrapping::ClampToEdge,
+    );
+    let depth_texture = DepthTexture2D::new::<f32>(
+        context,
+        resolution,
+        resolution,
+        Wrapping::ClampToEdge,
+        Wrapping::ClampToEdge,
+    );
+    let clear_color = clear_color.to_linear_srgb();
+    let viewport = Viewport::new_at_origo(resolution, resolution);
+    for side in CubeMapSide::iter() {
+        let mut camera = Camera::new_perspective(
+            viewport,
+            position,
+            position + side.direction(),
+            side.up(),
+            degrees(90.0),
+            z_near,
+            z_far,
+        );
+        camera.disable_tone_and_color_mapping();
+        let sides = [side];
+        RenderTarget::new(
+            environment_map.as_color_target(&sides, None),
+            depth_texture.as_depth_target(),
+        )
+        .clear(ClearState::color_and_depth(
+            clear_color.x,
+            clear_color.y,
+            clear_color.z,
+            1.0,
+            1.0,
+        ))
+        .render(&camera, objects.clone(), lights);
+    }
+    environment_map
+}
@@ -0,0 +1,18 @@
+
+#ifndef SPHERICAL_HARMONICS_FUNCTIONS
+#define SPHERICAL_HARMONICS_FUNCTIONS
+// Evaluates the irradiance divided by PI from the SH9 coefficients which are already convolved with the clamped cosine lobe
+vec3 spherical_harmonics_irradiance(vec3 n, vec3 sh[9])
+{
+    return max(vec3(0.0),
+        sh[0] * 0.282095
+        + sh[1] * 0.488603 * n.y
+        + sh[2] * 0.488603 * n.z
+        + sh[3] * 0.488603 * n.x
+        + sh[4] * 1.092548 * n.x * n.y
+        + sh[5] * 1.092548 * n.y * n.z
+        + sh[6] * 0.315392 * (3.0 * n.z * n.z - 1.0)
+        + sh[7] * 1.092548 * n.x * n.z
+        + sh[8] * 0.546274 * (n.x * n.x - n.y * n.y));
+}
+#endif
@@ -0,0 +1,257 @@
+use crate::core::*;
+use crate::renderer::*;
+
+///
+/// A compact representation of the light coming from all directions, for example from an environment map, using the first nine spherical harmonics basis functions (SH9).
+/// This is sufficient to accurately represent the diffuse irradiance, see [AmbientLight::spherical_harmonics], and only requires nine RGB coefficients
+/// compared to a cube map.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SphericalHarmonics {
+    /// The nine RGB coefficients of the radiance in the order `L00, L1-1, L10, L11, L2-2, L2-1, L20, L21, L22`.
+    pub coefficients: [[f32; 3]; 9],
+}
+
+impl SphericalHarmonics {
+    ///
+    /// Projects the given cube map onto the spherical harmonics basis functions.
+    /// The cube map is first resampled on the GPU into a cube map with the given resolution and then read back and projected on the CPU,
+    /// so a low resolution, for example 32, is usually sufficient.
+    /// The colors in the cube map are assumed to be in linear color space.
+    ///
+    pub fn from_cube_map(context: &Context, cube_map: &TextureCubeMap, resolution: u32) -> Self {
+        let resampled = TextureCubeMap::new_empty::<[f32; 4]>(
+            context,
+            resolution,
+            resolution,
+            Interpolation::Nearest,
+            Interpolation::Nearest,
+            None,
+            Wrapping::ClampToEdge,
+            Wrapping::ClampToEdge,
+            Wrapping::ClampToEdge,
+        );
+        let viewport = Viewport::new_at_origo(resolution, resolution);
+        let sides = CubeMapSide::iter()
+            .map(|side| {
+                let sides = [side];
+                let target = resampled.as_color_target(&sides, None);
+                target.clear(ClearState::default()).apply_screen_material(
+                    &CubeMapResampleMaterial {
+                        environment_map: cube_map,
+                        side,
+                    },
+                    Camera::new_2d(viewport),
+                    &[],
+                );
+                (side, target.read::<[f32; 4]>())
+            })
+            .collect::<Vec<_>>();
+        Self::from_sides(resolution, &sides)
+    }
+
+    ///
+    /// Projects the texel data of each side of a cube map with the given resolution, as read back from the GPU, onto the spherical harmonics basis functions.
+    ///
+    fn from_sides(resolution: u32, sides: &[(CubeMapSide, Vec<[f32; 4]>)]) -> Self {
+        let mut sh = SphericalHarmonics::default();
+        let mut total_weight = 0.0;
+        for (side, data) in sides.iter() {
+            for_each_texel(*side, resolution, |index, direction, weight| {
+                let c = data[index];
+                sh.add(direction, vec3(c[0], c[1], c[2]), weight);
+                total_weight += weight;
+            });
+        }
+        sh.scale(4.0 * std::f32::consts::PI / total_weight);
+        sh
+    }
+
+    ///
+    /// Projects the radiance given by the function, which returns the linear color for a given normalized direction,
+    /// onto the spherical harmonics basis functions.
+    /// The function is evaluated in the center of each texel of a cube map with the given resolution.
+    ///
+    pub fn from_fn(resolution: u32, radiance: impl Fn(Vec3) -> Vec3) -> Self {
+        let mut sh = SphericalHarmonics::default();
+        let mut total_weight = 0.0;
+        for side in CubeMapSide::iter() {
+            for_each_texel(side, resolution, |_, direction, weight| {
+                sh.add(direction, radiance(direction), weight);
+                total_weight += weight;
+            });
+        }
+        sh.scale(4.0 * std::f32::consts::PI / total_weight);
+        sh
+    }
+
+    ///
+    /// Evaluates the radiance in the given direction.
+    ///
+    pub fn radiance(&self, direction: Vec3) -> Vec3 {
+        let basis = basis(direction.normalize());
+        (0..9).fold(vec3(0.0, 0.0, 0.0), |acc, i| {
+            acc + Vec3::from(self.coefficients[i]) * basis[i]
+        })
+    }
+
+    ///
+    /// Evaluates the diffuse irradiance divided by PI for a surface with the given normal,
+    /// which is the same as the value stored in the [Environment::irradiance_map].
+    ///
+    pub fn irradiance(&self, normal: Vec3) -> Vec3 {
+        let basis = basis(normal.normalize());
+        (0..9).fold(vec3(0.0, 0.0, 0.0), |acc, i| {
+            acc + Vec3::from(self.coefficients[i]) * basis[i] * BAND_FACTORS[i]
+        })
+    }
+
+    fn add(&mut self, direction: Vec3, color: Vec3, weight: f32) {
+        let basis = basis(direction);
+        for i in 0..9 {
+            for c in 0..3 {
+                self.coefficients[i][c] += color[c] * basis[i] * weight;
+            }
+        }
+    }
+
+    fn scale(&mut self, factor: f32) {
+        for coefficient in self.coefficients.iter_mut() {
+            for c in coefficient.iter_mut() {
+                *c *= factor;
+            }
+        }
+    }
+
+    pub(super) fn use_uniforms(&self, program: &Program, name: &str) {
+        program.use_uniform_array(
+            name,
+            &self
+                .coefficients
+                .iter()
+                .enumerate()
+                .map(|(i, c)| Vec3::from(*c) * BAND_FACTORS[i])
+                .collect::<Vec<_>>(),
+        );
+    }
+}
+
+///
+/// The convolution with the clamped cosine lobe divided by PI for each coefficient (Ramamoorthi and Hanrahan).
+///
+const BAND_FACTORS: [f32; 9] = [
+    1.0,
+    2.0 / 3.0,
+    2.0 / 3.0,
+    2.0 / 3.0,
+    0.25,
+    0.25,
+    0.25,
+    0.25,
+    0.25,
+];
+
+fn basis(d: Vec3) -> [f32; 9] {
+    [
+        0.282095,
+        0.488603 * d.y,
+        0.488603 * d.z,
+        0.488603 * d.x,
+        1.092548 * d.x * d.y,
+        1.092548 * d.y * d.z,
+        0.315392 * (3.0 * d.z * d.z - 1.0),
+        1.092548 * d.x * d.z,
+        0.546274 * (d.x * d.x - d.y * d.y),
+    ]
+}
+
+///
+/// Calls the callback with the index, the direction and the solid angle weight of each texel of the given cube map side.
+/// The direction is the same as when rendering into the side using the `direction` and `up` of the [CubeMapSide],
+/// where the first row is in the negative `up` direction, while the index refers to the texel data read back from the side,
+/// where the first row is the top row, ie. in the positive `up` direction.
+///
+fn for_each_texel(side: CubeMapSide, resolution: u32, mut callback: impl FnMut(usize, Vec3, f32)) {
+    let direction = side.direction();
+    let up = side.up();
+    let right = direction.cross(up);
+    for y in 0..resolution {
+        for x in 0..resolution {
+            let u = ((x as f32 + 0.5) / resolution as f32) * 2.0 - 1.0;
+            let v = ((y as f32 + 0.5) / resolution as f32) * 2.0 - 1.0;
+            let d = direction + right * u + up * v;
+            let weight = 1.0 / (1.0 + u * u + v * v).powf(1.5);
+            callback(
+                ((resolution - 1 - y) * resolution + x) as usize,
+                d.normalize(),
+                weight,
+            );
+        }
+    }
+}
+
+struct CubeMapResampleMaterial<'a> {
+    environment_map: &'a TextureCubeMap,
+    side: CubeMapSide,
+}
+
+impl Material for CubeMapResampleMaterial<'_> {
+    fn fragment_shader_source(&self, _lights: &[&dyn Light]) -> String {
+        include_str!("shaders/cube_map_resample.frag").to_string()
+    }
+
+    fn id(&self) -> EffectMaterialId {
+        EffectMaterialId::CubeMapResampleMaterial
+    }
+
+    fn use_uniforms(&self, program: &Program, _viewer: &dyn Viewer, _lights: &[&dyn Light]) {
+        program.use_texture_cube("environmentMap", self.environment_map);
+        program.use_uniform("direction", self.side.direction());
+        program.use_uniform("up", self.side.up());
+    }
+
+    fn render_states(&self) -> RenderStates {
+        RenderStates::default()
+    }
+
+    fn material_type(&self) -> MaterialType {
+        MaterialType::Opaque
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_sides_matches_from_fn() {
+        // Bright sky and dark ground, so the radiance is not symmetric top to bottom
+        let radiance = |d: Vec3| vec3(0.1, 0.2, 0.3) + vec3(1.0, 0.8, 0.5) * d.y.max(0.0);
+        let resolution = 8;
+        let sides = CubeMapSide::iter()
+            .map(|side| {
+                // Render the side like the resample material and read it back with the first row at the top
+                let right = side.direction().cross(side.up());
+                let mut data = Vec::new();
+                for y in (0..resolution).rev() {
+                    for x in 0..resolution {
+                        let u = ((x as f32 + 0.5) / resolution as f32) * 2.0 - 1.0;
+                        let v = ((y as f32 + 0.5) / resolution as f32) * 2.0 - 1.0;
+                        let c =
+                            radiance((side.direction() + right * u + side.up() * v).normalize());
+                        data.push([c.x, c.y, c.z, 1.0]);
+                    }
+                }
+                (side, data)
+            })
+            .collect::<Vec<_>>();
+        let expected = SphericalHarmonics::from_fn(resolution, radiance);
+        let actual = SphericalHarmonics::from_sides(resolution, &sides);
+        for (a, e) in actual.coefficients.iter().zip(expected.coefficients.iter()) {
+            for c in 0..3 {
+                assert!((a[c] - e[c]).abs() < 1e-4, "{:?} != {:?}", a, e);
+            }
+        }
+    }
+}
@@ -137,6 +137,14 @@ impl SpotLight {
     pub fn shadow_map(&self) -> Option<&DepthTexture2D> {
         self.shadow_texture.as_ref()
     }
+
+    ///
+    /// Returns the matrix which transforms a position in world space into texture coordinates (xy) and depth (z) in the shadow map.
+    /// Only valid if the shadow map has been generated.
+    ///
+    pub fn shadow_matrix(&self) -> Mat4 {
+        self.shadow_matrix
+    }
 }
 
 impl Light for SpotLight {
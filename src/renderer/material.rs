@@ -75,6 +75,14 @@ mod isosurface_material;
 #[doc(inline)]
 pub use isosurface_material::*;
 
+mod volume_material;
+#[doc(inline)]
+pub use volume_material::*;
+
+mod shader_material;
+#[doc(inline)]
+pub use shader_material::*;
+
 use std::{ops::Deref, sync::Arc};
 
 ///
@@ -168,8 +176,12 @@ pub trait Material {
     ///
     /// **Note:** The last bit is reserved to internally implemented materials, so if implementing the [Material] trait
     /// outside of this crate, always return an id in the public use range as defined by [EffectMaterialId].
+    /// The default implementation returns [EffectMaterialId::FromSource] which means that the shader is identified by its source instead,
+    /// at the cost of generating the shader source for each draw call.
     ///
-    fn id(&self) -> EffectMaterialId;
+    fn id(&self) -> EffectMaterialId {
+        EffectMaterialId::FromSource
+    }
 
     ///
     /// Sends the uniform data needed for this material to the fragment shader.
@@ -58,3 +58,39 @@ impl Material for IntersectionMaterial {
         MaterialType::Opaque
     }
 }
+
+///
+/// Used by [pick_region] to render the distance to the viewer, the geometry ID and the instance ID, similar to the [IntersectionMaterial],
+/// but with the alpha channel marking the pixels that are hit and optionally discarding the fragments at or in front of the depth in the previous layer (depth peeling).
+///
+pub(crate) struct RegionPickMaterial<'a> {
+    pub render_states: RenderStates,
+    pub geometry_id: u32,
+    pub previous_layer: &'a Texture2D,
+    pub peel: bool,
+}
+
+impl Material for RegionPickMaterial<'_> {
+    fn id(&self) -> EffectMaterialId {
+        EffectMaterialId::RegionPickMaterial
+    }
+
+    fn fragment_shader_source(&self, _lights: &[&dyn Light]) -> String {
+        include_str!("shaders/region_pick_material.frag").to_string()
+    }
+
+    fn use_uniforms(&self, program: &Program, viewer: &dyn Viewer, _lights: &[&dyn Light]) {
+        program.use_uniform("eye", viewer.position());
+        program.use_uniform("geometryId", self.geometry_id);
+        program.use_uniform("peel", if self.peel { 1 } else { 0 });
+        program.use_texture("previousLayer", self.previous_layer);
+    }
+
+    fn render_states(&self) -> RenderStates {
+        self.render_states
+    }
+
+    fn material_type(&self) -> MaterialType {
+        MaterialType::Opaque
+    }
+}
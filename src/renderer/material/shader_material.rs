@@ -0,0 +1,297 @@
+use crate::core::*;
+use crate::renderer::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+///
+/// A value that can be sent to a [ShaderMaterial] as a uniform variable.
+/// The GLSL declaration of the uniform is generated from the type of the value, see [UniformValue::glsl_type].
+///
+#[derive(Clone)]
+pub enum UniformValue {
+    /// Declared as `uniform float`.
+    Float(f32),
+    /// Declared as `uniform vec2`.
+    Vec2(Vec2),
+    /// Declared as `uniform vec3`.
+    Vec3(Vec3),
+    /// Declared as `uniform vec4`.
+    Vec4(Vec4),
+    /// Declared as `uniform int`.
+    Int(i32),
+    /// Declared as `uniform uint`.
+    UInt(u32),
+    /// Declared as `uniform mat2`.
+    Mat2(Mat2),
+    /// Declared as `uniform mat3`.
+    Mat3(Mat3),
+    /// Declared as `uniform mat4`.
+    Mat4(Mat4),
+    /// Declared as `uniform vec4` and sent to the shader in linear sRGB.
+    Color(Srgba),
+    /// Declared as `uniform float name[N]` where `N` is the length of the array.
+    FloatArray(Vec<f32>),
+    /// Declared as `uniform vec4 name[N]` where `N` is the length of the array.
+    Vec4Array(Vec<Vec4>),
+    /// Declared as `uniform sampler2D`.
+    Texture2D(Texture2DRef),
+    /// Declared as `uniform sampler2D`.
+    DepthTexture2D(Arc<DepthTexture2D>),
+    /// Declared as `uniform sampler2DArray`.
+    Texture2DArray(Arc<Texture2DArray>),
+    /// Declared as `uniform sampler3D`.
+    Texture3D(Arc<Texture3D>),
+    /// Declared as `uniform samplerCube`.
+    TextureCubeMap(Arc<TextureCubeMap>),
+}
+
+impl UniformValue {
+    ///
+    /// Returns the GLSL type used when declaring a uniform with this value.
+    ///
+    pub fn glsl_type(&self) -> &'static str {
+        match self {
+            Self::Float(_) | Self::FloatArray(_) => "float",
+            Self::Vec2(_) => "vec2",
+            Self::Vec3(_) => "vec3",
+            Self::Vec4(_) | Self::Color(_) | Self::Vec4Array(_) => "vec4",
+            Self::Int(_) => "int",
+            Self::UInt(_) => "uint",
+            Self::Mat2(_) => "mat2",
+            Self::Mat3(_) => "mat3",
+            Self::Mat4(_) => "mat4",
+            Self::Texture2D(_) | Self::DepthTexture2D(_) => "sampler2D",
+            Self::Texture2DArray(_) => "sampler2DArray",
+            Self::Texture3D(_) => "sampler3D",
+            Self::TextureCubeMap(_) => "samplerCube",
+        }
+    }
+
+    fn declaration(&self, name: &str) -> String {
+        match self {
+            Self::FloatArray(values) => format!("uniform float {}[{}];\n", name, values.len()),
+            Self::Vec4Array(values) => format!("uniform vec4 {}[{}];\n", name, values.len()),
+            _ => format!("uniform {} {};\n", self.glsl_type(), name),
+        }
+    }
+
+    fn use_uniform(&self, program: &Program, name: &str) {
+        if !program.requires_uniform(name) {
+            return;
+        }
+        match self {
+            Self::Float(v) => program.use_uniform(name, v),
+            Self::Vec2(v) => program.use_uniform(name, v),
+            Self::Vec3(v) => program.use_uniform(name, v),
+            Self::Vec4(v) => program.use_uniform(name, v),
+            Self::Int(v) => program.use_uniform(name, v),
+            Self::UInt(v) => program.use_uniform(name, v),
+            Self::Mat2(v) => program.use_uniform(name, v),
+            Self::Mat3(v) => program.use_uniform(name, v),
+            Self::Mat4(v) => program.use_uniform(name, v),
+            Self::Color(v) => program.use_uniform(name, v.to_linear_srgb()),
+            Self::FloatArray(v) => program.use_uniform_array(name, v),
+            Self::Vec4Array(v) => program.use_uniform_array(name, v),
+            Self::Texture2D(texture) => program.use_texture(name, texture),
+            Self::DepthTexture2D(texture) => program.use_depth_texture(name, texture),
+            Self::Texture2DArray(texture) => program.use_texture_array(name, texture),
+            Self::Texture3D(texture) => program.use_texture_3d(name, texture),
+            Self::TextureCubeMap(texture) => program.use_texture_cube(name, texture),
+        }
+    }
+}
+
+impl From<f32> for UniformValue {
+    fn from(value: f32) -> Self {
+        Self::Float(value)
+    }
+}
+
+impl From<Vec2> for UniformValue {
+    fn from(value: Vec2) -> Self {
+        Self::Vec2(value)
+    }
+}
+
+impl From<Vec3> for UniformValue {
+    fn from(value: Vec3) -> Self {
+        Self::Vec3(value)
+    }
+}
+
+impl From<Vec4> for UniformValue {
+    fn from(value: Vec4) -> Self {
+        Self::Vec4(value)
+    }
+}
+
+impl From<i32> for UniformValue {
+    fn from(value: i32) -> Self {
+        Self::Int(value)
+    }
+}
+
+impl From<u32> for UniformValue {
+    fn from(value: u32) -> Self {
+        Self::UInt(value)
+    }
+}
+
+impl From<Mat2> for UniformValue {
+    fn from(value: Mat2) -> Self {
+        Self::Mat2(value)
+    }
+}
+
+impl From<Mat3> for UniformValue {
+    fn from(value: Mat3) -> Self {
+        Self::Mat3(value)
+    }
+}
+
+impl From<Mat4> for UniformValue {
+    fn from(value: Mat4) -> Self {
+        Self::Mat4(value)
+    }
+}
+
+impl From<Srgba> for UniformValue {
+    fn from(value: Srgba) -> Self {
+        Self::Color(value)
+    }
+}
+
+impl From<Texture2DRef> for UniformValue {
+    fn from(value: Texture2DRef) -> Self {
+        Self::Texture2D(value)
+    }
+}
+
+impl From<Arc<Texture2DArray>> for UniformValue {
+    fn from(value: Arc<Texture2DArray>) -> Self {
+        Self::Texture2DArray(value)
+    }
+}
+
+impl From<Arc<Texture3D>> for UniformValue {
+    fn from(value: Arc<Texture3D>) -> Self {
+        Self::Texture3D(value)
+    }
+}
+
+impl From<Arc<TextureCubeMap>> for UniformValue {
+    fn from(value: Arc<TextureCubeMap>) -> Self {
+        Self::TextureCubeMap(value)
+    }
+}
+
+///
+/// A material defined by a custom GLSL fragment shader body and a set of named uniform values.
+///
+/// The fragment shader body is prepended with a standard prelude, so it only needs to contain the `main` function and any helper functions.
+/// The prelude contains:
+/// - the functionality in `shared.frag`, for example `saturate` and `world_pos_from_depth`,
+/// - `vec3 calculate_lighting(vec3 camera_position, vec3 surface_color, vec3 position, vec3 normal, float metallic, float roughness, float occlusion)` which calculates the lighting from the lights given in the render call,
+/// - `vec3 tone_mapping(vec3 color)` and `vec3 color_mapping(vec3 color)`,
+/// - `uniform vec3 cameraPosition;` containing the position of the viewer,
+/// - a uniform declaration for each of the [ShaderMaterial::uniforms],
+/// - `layout (location = 0) out vec4 outColor;` which is the output color.
+///
+/// The vertex attributes needed from the [Geometry] must be declared in the fragment shader body, for example `in vec3 pos;`, see [Material] for the available attributes.
+/// The uniforms are automatically sent to the shader, and the shader is identified by its source (see [EffectMaterialId::FromSource]), so there is no need to allocate an [EffectMaterialId].
+///
+#[derive(Clone, Default)]
+pub struct ShaderMaterial {
+    /// The fragment shader body, not including the prelude.
+    pub fragment_source: String,
+    /// The uniform values sent to the shader, each declared in the prelude using the name in the map.
+    pub uniforms: HashMap<String, UniformValue>,
+    /// Render states.
+    pub render_states: RenderStates,
+    /// Whether this material should be treated as a transparent material (An object needs to be rendered differently depending on whether it is transparent or opaque).
+    pub is_transparent: bool,
+}
+
+impl ShaderMaterial {
+    ///
+    /// Constructs a new opaque shader material from the given fragment shader body and uniforms.
+    ///
+    pub fn new(
+        fragment_source: impl Into<String>,
+        uniforms: HashMap<String, UniformValue>,
+    ) -> Self {
+        Self {
+            fragment_source: fragment_source.into(),
+            uniforms,
+            ..Default::default()
+        }
+    }
+
+    ///
+    /// Sets the uniform with the given name to the given value, replacing any existing value.
+    ///
+    pub fn set_uniform(&mut self, name: impl Into<String>, value: impl Into<UniformValue>) {
+        self.uniforms.insert(name.into(), value.into());
+    }
+
+    fn sorted_uniforms(&self) -> Vec<(&String, &UniformValue)> {
+        let mut uniforms = self.uniforms.iter().collect::<Vec<_>>();
+        uniforms.sort_by(|a, b| a.0.cmp(b.0));
+        uniforms
+    }
+
+    fn uniform_declarations(&self) -> String {
+        self.sorted_uniforms()
+            .into_iter()
+            .map(|(name, value)| value.declaration(name))
+            .collect()
+    }
+}
+
+impl Material for ShaderMaterial {
+    fn id(&self) -> EffectMaterialId {
+        // The fragment shader is user defined, so the program is identified by its source
+        EffectMaterialId::FromSource
+    }
+
+    fn fragment_shader_source(&self, lights: &[&dyn Light]) -> String {
+        let mut output = lights_shader_source(lights);
+        output.push_str(ToneMapping::fragment_shader_source());
+        output.push_str(ColorMapping::fragment_shader_source());
+        output.push_str("uniform vec3 cameraPosition;\n");
+        output.push_str(&self.uniform_declarations());
+        output.push_str("layout (location = 0) out vec4 outColor;\n");
+        output.push_str(&self.fragment_source);
+        output
+    }
+
+    fn use_uniforms(&self, program: &Program, viewer: &dyn Viewer, lights: &[&dyn Light]) {
+        if program.requires_uniform("toneMappingType") {
+            viewer.tone_mapping().use_uniforms(program);
+        }
+        if program.requires_uniform("ColorMappingType") {
+            viewer.color_mapping().use_uniforms(program);
+        }
+        program.use_uniform_if_required("cameraPosition", viewer.position());
+        if self.fragment_source.contains("calculate_lighting") {
+            for (i, light) in lights.iter().enumerate() {
+                light.use_uniforms(program, i as u32);
+            }
+        }
+        for (name, value) in self.sorted_uniforms() {
+            value.use_uniform(program, name);
+        }
+    }
+
+    fn render_states(&self) -> RenderStates {
+        self.render_states
+    }
+
+    fn material_type(&self) -> MaterialType {
+        if self.is_transparent {
+            MaterialType::Transparent
+        } else {
+            MaterialType::Opaque
+        }
+    }
+}
@@ -0,0 +1,19 @@
+uniform vec3 eye;
+uniform uint geometryId;
+uniform int peel;
+uniform sampler2D previousLayer;
+
+in vec3 pos;
+flat in int instance_id;
+
+layout (location = 0) out vec4 outColor;
+
+void main()
+{
+    float dist = distance(pos, eye);
+    if (peel == 1 && dist <= texelFetch(previousLayer, ivec2(gl_FragCoord.xy), 0).x * 1.00001) {
+        // Already found in a previous layer
+        discard;
+    }
+    outColor = vec4(dist, uintBitsToFloat(geometryId), intBitsToFloat(instance_id), 1.0);
+}
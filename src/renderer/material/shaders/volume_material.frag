@@ -0,0 +1,107 @@
+uniform vec3 cameraPosition;
+uniform sampler3D tex;
+uniform sampler2D transferFunction;
+uniform vec3 size;
+uniform int steps;
+uniform float opacityScale;
+uniform int renderMode;
+#ifdef USE_LIGHTING
+uniform float metallic;
+uniform float roughness;
+uniform vec3 h;
+#endif
+
+in vec3 pos;
+
+layout (location = 0) out vec4 outColor;
+
+#ifdef USE_LIGHTING
+vec3 estimate_normal(vec3 uvw) {
+    float x = texture(tex, uvw + vec3(h.x, 0.0, 0.0)).r - texture(tex, uvw - vec3(h.x, 0.0, 0.0)).r;
+    float y = texture(tex, uvw + vec3(0.0, h.y, 0.0)).r - texture(tex, uvw - vec3(0.0, h.y, 0.0)).r;
+    float z = texture(tex, uvw + vec3(0.0, 0.0, h.z)).r - texture(tex, uvw - vec3(0.0, 0.0, h.z)).r;
+    vec3 gradient = vec3(x, y, z) / (2.0 * h);
+    float l = length(gradient);
+    return l > 0.0001 ? -gradient / l : vec3(0.0);
+}
+#endif
+
+vec3 shade_sample(vec3 position, vec3 uvw, vec3 color, vec3 view_direction) {
+#ifdef USE_LIGHTING
+    vec3 normal = estimate_normal(uvw);
+    if (dot(normal, normal) < 0.5) {
+        // No gradient, ie. a homogeneous region, so the sample is lit as if facing the viewer
+        normal = -view_direction;
+    }
+    // Light both sides of the implicit surface
+    normal = faceforward(normal, view_direction, normal);
+    return calculate_lighting(cameraPosition, color, position, normal, metallic, roughness, 1.0);
+#else
+    return color;
+#endif
+}
+
+// Maps the value to the transfer function, such that zero and one are the centers of the first and last texel
+vec4 transfer_function(float value) {
+    float n = float(textureSize(transferFunction, 0).x);
+    return texture(transferFunction, vec2((value * (n - 1.0) + 0.5) / n, 0.5));
+}
+
+void main() {
+    vec3 ray_direction = normalize(pos - cameraPosition);
+
+    // Intersect the ray with the box
+    vec3 inv_direction = 1.0 / ray_direction;
+    vec3 t0 = (-0.5 * size - cameraPosition) * inv_direction;
+    vec3 t1 = (0.5 * size - cameraPosition) * inv_direction;
+    vec3 t_min = min(t0, t1);
+    vec3 t_max = max(t0, t1);
+    float t_near = max(max(max(t_min.x, t_min.y), t_min.z), 0.0);
+    float t_far = min(min(t_max.x, t_max.y), t_max.z);
+    if (t_far <= t_near) {
+        discard;
+    }
+
+    float step_size = length(size) / float(steps);
+    vec4 result = vec4(0.0);
+    float max_density = 0.0;
+    for (int i = 0; i < steps; i++) {
+        float t = t_near + (float(i) + 0.5) * step_size;
+        if (t > t_far) {
+            break;
+        }
+        vec3 position = cameraPosition + t * ray_direction;
+        vec3 uvw = position / size + 0.5;
+        float density = texture(tex, uvw).r;
+        if (renderMode == 1) {
+            // Maximum intensity projection
+            max_density = max(max_density, density);
+            continue;
+        }
+
+        // Front-to-back compositing, where the opacity is corrected for the step size
+        vec4 s = transfer_function(density);
+        float alpha = 1.0 - pow(1.0 - clamp(s.a, 0.0, 0.9999), step_size * opacityScale);
+        if (alpha > 0.001) {
+            vec3 color = shade_sample(position, uvw, s.rgb, ray_direction);
+            result.rgb += (1.0 - result.a) * alpha * color;
+            result.a += (1.0 - result.a) * alpha;
+            // Early ray termination
+            if (result.a > 0.99) {
+                break;
+            }
+        }
+    }
+    if (renderMode == 1) {
+        result = transfer_function(max_density);
+        result.rgb *= result.a;
+    }
+    if (result.a < 0.001) {
+        discard;
+    }
+
+    outColor.rgb = result.rgb / result.a;
+    outColor.rgb = tone_mapping(outColor.rgb);
+    outColor.rgb = color_mapping(outColor.rgb);
+    outColor.a = result.a;
+}
@@ -0,0 +1,177 @@
+use crate::core::*;
+use crate::renderer::*;
+use std::sync::Arc;
+
+///
+/// Determines how the voxel data is combined along each view ray in a [VolumeMaterial].
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum VolumeRenderMode {
+    /// The color and opacity of each sample along the ray is found using the transfer function and composited front-to-back.
+    #[default]
+    Composite,
+    /// The maximum value along the ray is found and mapped to a color and opacity using the transfer function.
+    MaximumIntensityProjection,
+}
+
+///
+/// A material that renders the voxel data in the [VolumeMaterial::voxels] using direct volume rendering,
+/// ie. the volume is ray marched and the value in the red channel of the voxel data is mapped to a color and opacity using the [VolumeMaterial::transfer_function].
+/// This material should be applied to a cube with center in origo, for example [CpuMesh::cube].
+///
+#[derive(Clone)]
+pub struct VolumeMaterial {
+    /// The voxel data.
+    pub voxels: Arc<Texture3D>,
+    /// A texture with a height of one pixel which maps a value in the voxel data in the range `[0..1]` (horizontal texture coordinate) to a color (rgb) and opacity (alpha).
+    /// The colors should be in linear color space, see [VolumeMaterial::new_transfer_function].
+    pub transfer_function: Arc<Texture2D>,
+    /// Determines how the values along each view ray are combined.
+    pub mode: VolumeRenderMode,
+    /// The size of the cube that is used to render the voxel data. The texture is scaled to fill the entire cube.
+    pub size: Vec3,
+    /// The number of samples along the diagonal of the cube.
+    pub steps: u32,
+    /// The opacity in the transfer function is the opacity of a layer of the volume with a thickness of one divided by this value.
+    pub opacity_scale: f32,
+    /// Whether or not to light the samples using the gradient of the voxel data as the normal.
+    /// Only used for [VolumeRenderMode::Composite].
+    pub lighting: bool,
+    /// A value in the range `[0..1]` specifying how metallic the samples are when [VolumeMaterial::lighting] is enabled.
+    pub metallic: f32,
+    /// A value in the range `[0..1]` specifying how rough the samples are when [VolumeMaterial::lighting] is enabled.
+    pub roughness: f32,
+    /// The lighting model used when [VolumeMaterial::lighting] is enabled.
+    pub lighting_model: LightingModel,
+}
+
+impl VolumeMaterial {
+    ///
+    /// Creates a transfer function texture which can be used as the [VolumeMaterial::transfer_function].
+    /// The given colors are evenly distributed in the range `[0..1]` and linearly interpolated,
+    /// so the first color is used for the value zero and the last color for the value one.
+    /// The alpha channel of each color is the opacity.
+    ///
+    pub fn new_transfer_function(context: &Context, colors: &[Srgba]) -> Arc<Texture2D> {
+        let data = colors
+            .iter()
+            .map(|c| {
+                let c = c.to_linear_srgb();
+                [
+                    f16::from_f32(c.x),
+                    f16::from_f32(c.y),
+                    f16::from_f32(c.z),
+                    f16::from_f32(c.w),
+                ]
+            })
+            .collect::<Vec<_>>();
+        let texture = Texture2D::new_empty::<[f16; 4]>(
+            context,
+            data.len().max(1) as u32,
+            1,
+            Interpolation::Linear,
+            Interpolation::Linear,
+            None,
+            Wrapping::ClampToEdge,
+            Wrapping::ClampToEdge,
+        );
+        if !data.is_empty() {
+            texture.fill(&data);
+        }
+        Arc::new(texture)
+    }
+
+    fn uses_lighting(&self) -> bool {
+        self.lighting && self.mode == VolumeRenderMode::Composite
+    }
+}
+
+impl Material for VolumeMaterial {
+    fn id(&self) -> EffectMaterialId {
+        EffectMaterialId::VolumeMaterial(self.uses_lighting())
+    }
+
+    fn fragment_shader_source(&self, lights: &[&dyn Light]) -> String {
+        let mut source = if self.uses_lighting() {
+            let mut source = "#define USE_LIGHTING;\n".to_string();
+            source.push_str(&lights_shader_source(lights));
+            source
+        } else {
+            include_str!("../../core/shared.frag").to_string()
+        };
+        source.push_str(ToneMapping::fragment_shader_source());
+        source.push_str(ColorMapping::fragment_shader_source());
+        source.push_str(include_str!("shaders/volume_material.frag"));
+        source
+    }
+
+    fn use_uniforms(&self, program: &Program, viewer: &dyn Viewer, lights: &[&dyn Light]) {
+        viewer.tone_mapping().use_uniforms(program);
+        viewer.color_mapping().use_uniforms(program);
+        if self.uses_lighting() {
+            program.use_uniform_if_required(
+                "lightingModel",
+                lighting_model_to_id(self.lighting_model),
+            );
+            for (i, light) in lights.iter().enumerate() {
+                light.use_uniforms(program, i as u32);
+            }
+            program.use_uniform("metallic", self.metallic);
+            program.use_uniform_if_required("roughness", self.roughness);
+            program.use_uniform(
+                "h",
+                vec3(
+                    1.0 / self.voxels.width() as f32,
+                    1.0 / self.voxels.height() as f32,
+                    1.0 / self.voxels.depth() as f32,
+                ),
+            );
+        }
+        program.use_uniform("cameraPosition", viewer.position());
+        program.use_uniform("size", self.size);
+        program.use_uniform("steps", self.steps.max(1) as i32);
+        program.use_uniform("opacityScale", self.opacity_scale.max(0.0));
+        program.use_uniform(
+            "renderMode",
+            match self.mode {
+                VolumeRenderMode::Composite => 0,
+                VolumeRenderMode::MaximumIntensityProjection => 1,
+            },
+        );
+        program.use_texture_3d("tex", &self.voxels);
+        program.use_texture("transferFunction", &self.transfer_function);
+    }
+
+    fn render_states(&self) -> RenderStates {
+        RenderStates {
+            write_mask: WriteMask::COLOR,
+            blend: Blend::TRANSPARENCY,
+            cull: Cull::Front,
+            ..Default::default()
+        }
+    }
+
+    fn material_type(&self) -> MaterialType {
+        MaterialType::Transparent
+    }
+}
+
+impl FromCpuVoxelGrid for VolumeMaterial {
+    fn from_cpu_voxel_grid(context: &Context, cpu_voxel_grid: &CpuVoxelGrid) -> Self {
+        Self {
+            voxels: Arc::new(Texture3D::new(context, &cpu_voxel_grid.voxels)),
+            transfer_function: Self::new_transfer_function(
+                context,
+                &[Srgba::new(0, 0, 0, 0), Srgba::new(255, 255, 255, 255)],
+            ),
+            mode: VolumeRenderMode::Composite,
+            size: cpu_voxel_grid.size,
+            steps: 256,
+            opacity_scale: 1.0,
+            lighting: false,
+            metallic: 0.0,
+            roughness: 1.0,
+            lighting_model: LightingModel::Blinn,
+        }
+    }
+}
@@ -30,6 +30,10 @@ mod instanced_model;
 #[doc(inline)]
 pub use instanced_model::*;
 
+mod static_batch;
+#[doc(inline)]
+pub use static_batch::*;
+
 mod voxel_grid;
 #[doc(inline)]
 pub use voxel_grid::*;
@@ -38,10 +42,26 @@ mod skybox;
 #[doc(inline)]
 pub use skybox::*;
 
+mod procedural_sky;
+#[doc(inline)]
+pub use procedural_sky::*;
+
 mod imposters;
 #[doc(inline)]
 pub use imposters::*;
 
+mod lod_group;
+#[doc(inline)]
+pub use lod_group::*;
+
+mod gaussian_splats;
+#[doc(inline)]
+pub use gaussian_splats::*;
+
+mod decal;
+#[doc(inline)]
+pub use decal::*;
+
 mod terrain;
 #[doc(inline)]
 pub use terrain::*;
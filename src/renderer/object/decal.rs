@@ -0,0 +1,430 @@
+use crate::core::*;
+use crate::renderer::*;
+use std::sync::Arc;
+
+///
+/// A decal, for example a bullet hole, a sign or a road marking, which is projected onto the surfaces inside an oriented box.
+/// The decal is projected along the negative z-axis of the box and the [Decal::albedo_texture] covers the box in the x and y directions.
+///
+/// The decals are applied when given to [RenderTarget::render_with_decals].
+/// Alternatively, a decal can be rendered as an [Object] together with the transparent objects, in which case it is projected onto the surfaces with the depth given by [Decal::set_scene_depth].
+/// For objects with a [MaterialType::Deferred] material, the decal modifies the albedo, normal, metallic and roughness of the surface before the lighting is calculated.
+/// For objects with a [MaterialType::Opaque] material, the position of the surface is reconstructed from the depth and the lit decal is blended on top of the surface.
+/// Which surfaces are affected is specified by [Decal::material_types].
+///
+pub struct Decal {
+    context: Context,
+    mesh: Mesh,
+    /// The albedo color which is multiplied with the color from the [Decal::albedo_texture]. The alpha channel specifies the opacity.
+    pub albedo: Srgba,
+    /// A texture with the albedo color and the opacity in the alpha channel.
+    pub albedo_texture: Option<Texture2DRef>,
+    /// A tangent space normal map which is applied to the surface, where the tangent is the x-axis of the box.
+    pub normal_texture: Option<Texture2DRef>,
+    /// A scalar multiplier applied to each normal vector of the [Decal::normal_texture].
+    pub normal_scale: f32,
+    /// A value in the range `[0..1]` specifying how metallic the decal is.
+    pub metallic: f32,
+    /// A value in the range `[0..1]` specifying how rough the decal is.
+    pub roughness: f32,
+    /// The angle between the surface normal and the projection direction where the decal starts to fade out.
+    pub fade_start_angle: Radians,
+    /// The angle between the surface normal and the projection direction where the decal is completely faded out.
+    pub fade_end_angle: Radians,
+    /// The types of materials that are affected by this decal, only [MaterialType::Opaque] and [MaterialType::Deferred] are supported.
+    pub material_types: Vec<MaterialType>,
+    scene_depth: Option<Arc<DepthTexture2D>>,
+}
+
+impl Decal {
+    ///
+    /// Creates a new decal with the given transformation from the cube with corners in `(-1, -1, -1)` and `(1, 1, 1)` to the box in world space
+    /// and with the given texture as albedo texture.
+    ///
+    pub fn new(
+        context: &Context,
+        transformation: Mat4,
+        albedo_texture: Option<Texture2DRef>,
+    ) -> Self {
+        let mut cube = CpuMesh::cube();
+        cube.normals = None;
+        cube.tangents = None;
+        cube.uvs = None;
+        let mut mesh = Mesh::new(context, &cube);
+        mesh.set_transformation(transformation);
+        Self {
+            context: context.clone(),
+            mesh,
+            albedo: Srgba::WHITE,
+            albedo_texture,
+            normal_texture: None,
+            normal_scale: 1.0,
+            metallic: 0.0,
+            roughness: 1.0,
+            fade_start_angle: degrees(60.0).into(),
+            fade_end_angle: degrees(80.0).into(),
+            material_types: vec![MaterialType::Opaque, MaterialType::Deferred],
+            scene_depth: None,
+        }
+    }
+
+    ///
+    /// Returns the transformation from the cube with corners in `(-1, -1, -1)` and `(1, 1, 1)` to the box in world space.
+    ///
+    pub fn transformation(&self) -> Mat4 {
+        self.mesh.transformation()
+    }
+
+    ///
+    /// Sets the transformation from the cube with corners in `(-1, -1, -1)` and `(1, 1, 1)` to the box in world space.
+    ///
+    pub fn set_transformation(&mut self, transformation: Mat4) {
+        self.mesh.set_transformation(transformation);
+    }
+
+    ///
+    /// Sets the depth texture of the surfaces the decal is projected onto when it is rendered as an [Object].
+    /// The depth texture must be rendered with the same viewer as the decal, for example using a [DepthMaterial] with [WriteMask::DEPTH].
+    /// If no depth texture is set, nothing is rendered when the decal is rendered as an [Object].
+    /// This is not used by [RenderTarget::render_with_decals].
+    ///
+    pub fn set_scene_depth(&mut self, depth_texture: Option<Arc<DepthTexture2D>>) {
+        self.scene_depth = depth_texture;
+    }
+
+    fn mesh(&self) -> &Mesh {
+        &self.mesh
+    }
+
+    ///
+    /// Renders the decal on top of the surfaces with the depth in the given depth texture, which must be rendered with the given viewer.
+    /// This can be used to apply decals when not using [RenderTarget::render_with_decals],
+    /// and must be called in the callback given as input to a [RenderTarget], [ColorTarget] or [DepthTarget] write method.
+    ///
+    pub fn render(
+        &self,
+        viewer: &dyn Viewer,
+        depth_texture: &DepthTexture2D,
+        lights: &[&dyn Light],
+    ) {
+        self.render_forward(viewer, depth_texture, None, lights);
+    }
+
+    ///
+    /// Renders the decal on top of the surfaces with the depth in the given depth texture,
+    /// except on the surfaces with the depth in the deferred depth texture if it is specified.
+    ///
+    pub(crate) fn render_forward(
+        &self,
+        viewer: &dyn Viewer,
+        depth_texture: &DepthTexture2D,
+        deferred_depth_texture: Option<&DepthTexture2D>,
+        lights: &[&dyn Light],
+    ) {
+        self.mesh.render_with_effect(
+            &DecalEffect {
+                decal: self,
+                depth_texture,
+                target: DecalTarget::Forward(deferred_depth_texture),
+            },
+            viewer,
+            lights,
+            None,
+            None,
+        );
+    }
+
+    ///
+    /// Applies the decal to the geometry buffer of the deferred rendering, where the depth texture is the depth of the geometry buffer
+    /// and the copy is a texture with the same size as the geometry buffer used for blending.
+    /// The viewer must have a viewport with origin in the lower left corner of the geometry buffer.
+    ///
+    pub(crate) fn apply_to_geometry_buffer(
+        &self,
+        viewer: &dyn Viewer,
+        geometry_buffer: &Texture2DArray,
+        geometry_buffer_copy: &Texture2DArray,
+        depth_texture: &DepthTexture2D,
+    ) {
+        let layers = [0, 1, 2];
+        let scissor_box = self.scissor_box(viewer);
+        geometry_buffer_copy
+            .as_color_target(&layers, None)
+            .apply_screen_effect_partially(
+                scissor_box,
+                &GeometryBufferCopyEffect { geometry_buffer },
+                Camera::new_2d(viewer.viewport()),
+                &[],
+                None,
+                None,
+            );
+        geometry_buffer
+            .as_color_target(&layers, None)
+            .write_partially::<RendererError>(scissor_box, || {
+                render_with_effect(
+                    &self.context,
+                    viewer,
+                    &self.mesh,
+                    &DecalEffect {
+                        decal: self,
+                        depth_texture,
+                        target: DecalTarget::Deferred(geometry_buffer_copy),
+                    },
+                    &[],
+                    None,
+                    None,
+                )
+            })
+            .unwrap();
+    }
+
+    ///
+    /// Returns the part of the viewport covered by the decal box.
+    ///
+    fn scissor_box(&self, viewer: &dyn Viewer) -> ScissorBox {
+        let viewport = viewer.viewport();
+        let view_projection = viewer.projection() * viewer.view();
+        let transformation = self.transformation();
+        let mut min = vec2(f32::MAX, f32::MAX);
+        let mut max = vec2(f32::MIN, f32::MIN);
+        for x in [-1.0, 1.0] {
+            for y in [-1.0, 1.0] {
+                for z in [-1.0, 1.0] {
+                    let p = view_projection * transformation * vec4(x, y, z, 1.0);
+                    if p.w <= 0.0 {
+                        // The box intersects the near plane
+                        return viewport.into();
+                    }
+                    let ndc = p.truncate().truncate() / p.w;
+                    min = vec2(min.x.min(ndc.x), min.y.min(ndc.y));
+                    max = vec2(max.x.max(ndc.x), max.y.max(ndc.y));
+                }
+            }
+        }
+        let x0 =
+            (viewport.x as f32 + (0.5 * min.x + 0.5) * viewport.width as f32).floor() as i32 - 1;
+        let y0 =
+            (viewport.y as f32 + (0.5 * min.y + 0.5) * viewport.height as f32).floor() as i32 - 1;
+        let x1 =
+            (viewport.x as f32 + (0.5 * max.x + 0.5) * viewport.width as f32).ceil() as i32 + 1;
+        let y1 =
+            (viewport.y as f32 + (0.5 * max.y + 0.5) * viewport.height as f32).ceil() as i32 + 1;
+        ScissorBox::from(viewport).intersection(ScissorBox {
+            x: x0,
+            y: y0,
+            width: (x1 - x0).max(0) as u32,
+            height: (y1 - y0).max(0) as u32,
+        })
+    }
+}
+
+impl<'a> IntoIterator for &'a Decal {
+    type Item = &'a dyn Object;
+    type IntoIter = std::iter::Once<&'a dyn Object>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        std::iter::once(self)
+    }
+}
+
+impl Geometry for Decal {
+    impl_geometry_body!(mesh);
+}
+
+impl Object for Decal {
+    fn render(&self, viewer: &dyn Viewer, lights: &[&dyn Light]) {
+        if let Some(depth_texture) = &self.scene_depth {
+            self.render_forward(viewer, depth_texture, None, lights);
+        }
+    }
+
+    fn material_type(&self) -> MaterialType {
+        MaterialType::Transparent
+    }
+}
+
+enum DecalTarget<'a> {
+    /// Blends the decal onto the render target, except on the surfaces with the depth in the given deferred depth texture.
+    Forward(Option<&'a DepthTexture2D>),
+    /// Writes the decal blended with the given copy of the geometry buffer into the geometry buffer.
+    Deferred(&'a Texture2DArray),
+}
+
+struct DecalEffect<'a> {
+    decal: &'a Decal,
+    depth_texture: &'a DepthTexture2D,
+    target: DecalTarget<'a>,
+}
+
+impl Effect for DecalEffect<'_> {
+    fn fragment_shader_source(
+        &self,
+        lights: &[&dyn Light],
+        _color_texture: Option<ColorTexture>,
+        _depth_texture: Option<DepthTexture>,
+    ) -> String {
+        let mut source = String::new();
+        if self.decal.albedo_texture.is_some() {
+            source.push_str("#define USE_ALBEDO_TEXTURE;\n");
+        }
+        if self.decal.normal_texture.is_some() {
+            source.push_str("#define USE_NORMAL_TEXTURE;\n");
+        }
+        match self.target {
+            DecalTarget::Forward(deferred_depth_texture) => {
+                if deferred_depth_texture.is_some() {
+                    source.push_str("#define USE_DEFERRED_DEPTH;\n");
+                }
+                source.push_str(&lights_shader_source(lights));
+                source.push_str(ToneMapping::fragment_shader_source());
+                source.push_str(ColorMapping::fragment_shader_source());
+            }
+            DecalTarget::Deferred(_) => {
+                source.push_str("#define DEFERRED;\n");
+                source.push_str(include_str!("../../core/shared.frag"));
+            }
+        }
+        source.push_str(include_str!("shaders/decal.frag"));
+        source
+    }
+
+    fn id(
+        &self,
+        _color_texture: Option<ColorTexture>,
+        _depth_texture: Option<DepthTexture>,
+    ) -> EffectMaterialId {
+        EffectMaterialId::DecalEffect(
+            matches!(self.target, DecalTarget::Deferred(_)),
+            matches!(self.target, DecalTarget::Forward(Some(_))),
+            self.decal.albedo_texture.is_some(),
+            self.decal.normal_texture.is_some(),
+        )
+    }
+
+    fn use_uniforms(
+        &self,
+        program: &Program,
+        viewer: &dyn Viewer,
+        lights: &[&dyn Light],
+        _color_texture: Option<ColorTexture>,
+        _depth_texture: Option<DepthTexture>,
+    ) {
+        let decal = self.decal;
+        let transformation = decal.transformation();
+        let viewport = viewer.viewport();
+        program.use_uniform(
+            "viewProjectionInverse",
+            (viewer.projection() * viewer.view()).invert().unwrap(),
+        );
+        program.use_uniform(
+            "viewport",
+            vec4(
+                viewport.x as f32,
+                viewport.y as f32,
+                viewport.width as f32,
+                viewport.height as f32,
+            ),
+        );
+        program.use_uniform(
+            "decalInverse",
+            transformation.invert().unwrap_or(Mat4::identity()),
+        );
+        program.use_uniform("decalDirection", -transformation.z.truncate().normalize());
+        program.use_uniform_if_required("decalTangent", transformation.x.truncate().normalize());
+        program.use_uniform(
+            "fadeCosines",
+            vec2(decal.fade_start_angle.0.cos(), decal.fade_end_angle.0.cos()),
+        );
+        program.use_uniform("albedo", decal.albedo.to_linear_srgb());
+        program.use_uniform("metallic", decal.metallic);
+        program.use_uniform_if_required("roughness", decal.roughness);
+        program.use_depth_texture("sceneDepth", self.depth_texture);
+        if let Some(texture) = &decal.albedo_texture {
+            program.use_uniform("albedoTexTransform", texture.transformation);
+            program.use_texture("albedoTexture", texture);
+        }
+        if let Some(texture) = &decal.normal_texture {
+            program.use_uniform("normalTexTransform", texture.transformation);
+            program.use_uniform("normalScale", decal.normal_scale);
+            program.use_texture("normalTexture", texture);
+        }
+        match self.target {
+            DecalTarget::Forward(deferred_depth_texture) => {
+                viewer.tone_mapping().use_uniforms(program);
+                viewer.color_mapping().use_uniforms(program);
+                program.use_uniform_if_required(
+                    "lightingModel",
+                    lighting_model_to_id(LightingModel::Cook(
+                        NormalDistributionFunction::TrowbridgeReitzGGX,
+                        GeometryFunction::SmithSchlickGGX,
+                    )),
+                );
+                for (i, light) in lights.iter().enumerate() {
+                    light.use_uniforms(program, i as u32);
+                }
+                program.use_uniform("cameraPosition", viewer.position());
+                if let Some(deferred_depth_texture) = deferred_depth_texture {
+                    program.use_depth_texture("deferredDepth", deferred_depth_texture);
+                }
+            }
+            DecalTarget::Deferred(geometry_buffer_copy) => {
+                program.use_texture_array("geometryBuffer", geometry_buffer_copy);
+            }
+        }
+    }
+
+    fn render_states(&self) -> RenderStates {
+        RenderStates {
+            write_mask: WriteMask::COLOR,
+            depth_test: DepthTest::Always,
+            blend: match self.target {
+                DecalTarget::Forward(_) => Blend::TRANSPARENCY,
+                DecalTarget::Deferred(_) => Blend::Disabled,
+            },
+            cull: Cull::Front,
+        }
+    }
+}
+
+struct GeometryBufferCopyEffect<'a> {
+    geometry_buffer: &'a Texture2DArray,
+}
+
+impl Effect for GeometryBufferCopyEffect<'_> {
+    fn fragment_shader_source(
+        &self,
+        _lights: &[&dyn Light],
+        _color_texture: Option<ColorTexture>,
+        _depth_texture: Option<DepthTexture>,
+    ) -> String {
+        include_str!("shaders/geometry_buffer_copy.frag").to_string()
+    }
+
+    fn id(
+        &self,
+        _color_texture: Option<ColorTexture>,
+        _depth_texture: Option<DepthTexture>,
+    ) -> EffectMaterialId {
+        EffectMaterialId::GeometryBufferCopyEffect
+    }
+
+    fn use_uniforms(
+        &self,
+        program: &Program,
+        _viewer: &dyn Viewer,
+        _lights: &[&dyn Light],
+        _color_texture: Option<ColorTexture>,
+        _depth_texture: Option<DepthTexture>,
+    ) {
+        program.use_texture_array("geometryBuffer", self.geometry_buffer);
+    }
+
+    fn render_states(&self) -> RenderStates {
+        RenderStates {
+            write_mask: WriteMask::COLOR,
+            depth_test: DepthTest::Always,
+            cull: Cull::Back,
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,476 @@
+use crate::core::*;
+use crate::renderer::*;
+use std::sync::RwLock;
+
+const TEXELS_PER_SPLAT: usize = 15;
+const SPLATS_PER_ROW: usize = 272;
+
+///
+/// A set of 3D Gaussian splats on the CPU, typically the result of Gaussian splatting reconstruction from photos, which can be rendered using [GaussianSplats].
+/// Each splat is a 3D Gaussian with a covariance defined by a scale and a rotation, an opacity and a view dependent color defined by spherical harmonics coefficients.
+///
+#[derive(Clone, Debug, Default)]
+pub struct CpuGaussianSplats {
+    /// The centers of the splats.
+    pub positions: Vec<Vec3>,
+    /// The standard deviation of each splat along its local x, y and z axes.
+    pub scales: Vec<Vec3>,
+    /// The rotation of each splat.
+    pub rotations: Vec<Quat>,
+    /// The opacity of each splat in the range `[0..1]`.
+    pub opacities: Vec<f32>,
+    /// The degree of the spherical harmonics, in the range `[0..3]`.
+    pub sh_degree: u32,
+    /// The spherical harmonics coefficients of the color, `(sh_degree + 1)^2` rgb coefficients for each splat.
+    pub sh_coefficients: Vec<Vec3>,
+}
+
+impl CpuGaussianSplats {
+    ///
+    /// Parses Gaussian splats from the bytes of a binary little endian `.ply` file in the format written by the original 3D Gaussian splatting implementation,
+    /// ie. with the vertex properties `x`, `y`, `z`, `f_dc_0..2`, `f_rest_0..`, `opacity`, `scale_0..2` and `rot_0..3`.
+    ///
+    pub fn from_ply(bytes: &[u8]) -> Result<Self, RendererError> {
+        let error = |message: &str| RendererError::GaussianSplatsParsing(message.to_string());
+        let header_end = bytes
+            .windows(11)
+            .position(|w| w == b"end_header\n")
+            .ok_or_else(|| error("missing end of header"))?;
+        let header = std::str::from_utf8(&bytes[..header_end])
+            .map_err(|_| error("the header is not valid text"))?;
+        let mut lines = header.lines();
+        if lines.next() != Some("ply") {
+            return Err(error("not a ply file"));
+        }
+
+        let mut count = None;
+        let mut properties = Vec::new();
+        let mut in_vertex_element = false;
+        for line in lines {
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            match words.as_slice() {
+                ["format", format, ..] if *format != "binary_little_endian" => {
+                    return Err(error("only binary little endian ply files are supported"));
+                }
+                ["element", "vertex", n] => {
+                    count = Some(
+                        n.parse::<usize>()
+                            .map_err(|_| error("invalid vertex count"))?,
+                    );
+                    in_vertex_element = true;
+                }
+                ["element", ..] => {
+                    if in_vertex_element {
+                        break;
+                    }
+                }
+                ["property", data_type, name] if in_vertex_element => {
+                    let size = match *data_type {
+                        "float" | "float32" => 4,
+                        "double" | "float64" => 8,
+                        "uchar" | "uint8" => 1,
+                        _ => return Err(error("unsupported property type")),
+                    };
+                    properties.push((name.to_string(), *data_type, size));
+                }
+                _ => {}
+            }
+        }
+        let count = count.ok_or_else(|| error("missing vertex element"))?;
+        let stride = properties.iter().map(|(_, _, size)| size).sum::<usize>();
+        let data = &bytes[header_end + 11..];
+        if data.len() < count * stride {
+            return Err(error("the file is too short"));
+        }
+
+        let mut offsets = std::collections::HashMap::new();
+        let mut offset = 0;
+        for (name, data_type, size) in properties.iter() {
+            offsets.insert(name.as_str(), (offset, *data_type));
+            offset += size;
+        }
+        for name in ["x", "y", "z", "f_dc_0", "f_dc_1", "f_dc_2", "opacity"] {
+            if !offsets.contains_key(name) {
+                return Err(error(&format!("missing property {}", name)));
+            }
+        }
+        let property = |name: &str| offsets.get(name).copied();
+        let read = |i: usize, property: Option<(usize, &str)>| -> Option<f32> {
+            property.map(|(offset, data_type)| {
+                let start = i * stride + offset;
+                match data_type {
+                    "double" | "float64" => {
+                        f64::from_le_bytes(data[start..start + 8].try_into().unwrap()) as f32
+                    }
+                    "uchar" | "uint8" => data[start] as f32 / 255.0,
+                    _ => f32::from_le_bytes(data[start..start + 4].try_into().unwrap()),
+                }
+            })
+        };
+
+        let rest_count = (0..)
+            .take_while(|i| offsets.contains_key(format!("f_rest_{}", i).as_str()))
+            .count()
+            / 3;
+        let sh_degree = match rest_count {
+            0..=2 => 0,
+            3..=7 => 1,
+            8..=14 => 2,
+            _ => 3,
+        };
+        let coefficient_count = (sh_degree + 1) * (sh_degree + 1);
+        let position = [property("x"), property("y"), property("z")];
+        let scale = [
+            property("scale_0"),
+            property("scale_1"),
+            property("scale_2"),
+        ];
+        let rotation = [
+            property("rot_0"),
+            property("rot_1"),
+            property("rot_2"),
+            property("rot_3"),
+        ];
+        let opacity = property("opacity");
+        let dc = [property("f_dc_0"), property("f_dc_1"), property("f_dc_2")];
+        // The remaining coefficients are stored with all the coefficients of the red channel first, then green and then blue
+        let rest = (1..coefficient_count)
+            .map(|k| {
+                [0, 1, 2]
+                    .map(|channel| property(&format!("f_rest_{}", channel * rest_count + k - 1)))
+            })
+            .collect::<Vec<_>>();
+
+        let mut splats = Self {
+            sh_degree: sh_degree as u32,
+            ..Default::default()
+        };
+        for i in 0..count {
+            let vector = |p: [Option<(usize, &str)>; 3], default: f32| {
+                let [x, y, z] = p.map(|p| read(i, p).unwrap_or(default));
+                vec3(x, y, z)
+            };
+            splats.positions.push(vector(position, 0.0));
+            let s = vector(scale, (0.01f32).ln());
+            splats.scales.push(vec3(s.x.exp(), s.y.exp(), s.z.exp()));
+            let [w, x, y, z] = [
+                read(i, rotation[0]).unwrap_or(1.0),
+                read(i, rotation[1]).unwrap_or(0.0),
+                read(i, rotation[2]).unwrap_or(0.0),
+                read(i, rotation[3]).unwrap_or(0.0),
+            ];
+            let q = Quat::new(w, x, y, z);
+            splats.rotations.push(if q.magnitude2() > 0.0 {
+                q.normalize()
+            } else {
+                Quat::new(1.0, 0.0, 0.0, 0.0)
+            });
+            splats
+                .opacities
+                .push(1.0 / (1.0 + (-read(i, opacity).unwrap_or(0.0)).exp()));
+            splats.sh_coefficients.push(vector(dc, 0.0));
+            for r in rest.iter() {
+                splats.sh_coefficients.push(vector(*r, 0.0));
+            }
+        }
+        Ok(splats)
+    }
+
+    ///
+    /// Returns the number of splats.
+    ///
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    ///
+    /// Returns whether or not there are no splats.
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+}
+
+///
+/// Renders 3D Gaussian splats, see [CpuGaussianSplats], together with other objects.
+///
+/// Each splat is projected onto the screen as an ellipse with a Gaussian falloff and a view dependent color evaluated from the spherical harmonics.
+/// The splats are sorted by depth on the CPU each time the viewer or the transformation changes and are alpha blended from back to front.
+/// Since the splats are transparent, they are rendered after the opaque objects and are hidden behind them, but they are not written to the depth buffer.
+///
+pub struct GaussianSplats {
+    context: Context,
+    corner_buffer: VertexBuffer<Vec2>,
+    data_texture: Texture2D,
+    positions: Vec<Vec3>,
+    sh_degree: u32,
+    transformation: Mat4,
+    aabb: AxisAlignedBoundingBox,
+    sorted_buffer: RwLock<(Option<Mat4>, InstanceBuffer<i32>)>,
+}
+
+impl GaussianSplats {
+    ///
+    /// Creates new Gaussian splats from the given [CpuGaussianSplats].
+    ///
+    pub fn new(context: &Context, cpu_splats: &CpuGaussianSplats) -> Self {
+        let count = cpu_splats.len();
+        let coefficient_count =
+            ((cpu_splats.sh_degree.min(3) + 1) * (cpu_splats.sh_degree.min(3) + 1)) as usize;
+        let rows = count.div_ceil(SPLATS_PER_ROW).max(1);
+        let width = SPLATS_PER_ROW * TEXELS_PER_SPLAT;
+        let mut data = vec![[0.0f32; 4]; width * rows];
+        for i in 0..count {
+            let texels = &mut data[i * TEXELS_PER_SPLAT..(i + 1) * TEXELS_PER_SPLAT];
+            let p = cpu_splats.positions[i];
+            texels[0] = [
+                p.x,
+                p.y,
+                p.z,
+                cpu_splats.opacities.get(i).copied().unwrap_or(1.0),
+            ];
+
+            // The covariance is R * S * S^T * R^T
+            let s = cpu_splats
+                .scales
+                .get(i)
+                .copied()
+                .unwrap_or(vec3(0.01, 0.01, 0.01));
+            let r = Mat3::from(
+                cpu_splats
+                    .rotations
+                    .get(i)
+                    .copied()
+                    .unwrap_or(Quat::new(1.0, 0.0, 0.0, 0.0)),
+            );
+            let m = Mat3::from_cols(r.x * s.x, r.y * s.y, r.z * s.z);
+            let c = m * m.transpose();
+            texels[1] = [c.x.x, c.x.y, c.x.z, c.y.y];
+            texels[2] = [c.y.z, c.z.z, 0.0, 0.0];
+
+            let coefficients = cpu_splats
+                .sh_coefficients
+                .get(i * coefficient_count..(i + 1) * coefficient_count)
+                .unwrap_or(&[]);
+            for (j, value) in coefficients
+                .iter()
+                .flat_map(|c| [c.x, c.y, c.z])
+                .enumerate()
+            {
+                texels[3 + j / 4][j % 4] = value;
+            }
+        }
+        let data_texture = Texture2D::new_empty::<[f32; 4]>(
+            context,
+            width as u32,
+            rows as u32,
+            Interpolation::Nearest,
+            Interpolation::Nearest,
+            None,
+            Wrapping::ClampToEdge,
+            Wrapping::ClampToEdge,
+        );
+        data_texture.fill(&data);
+        Self {
+            context: context.clone(),
+            corner_buffer: VertexBuffer::new_with_data(
+                context,
+                &[
+                    vec2(-1.0, -1.0),
+                    vec2(1.0, -1.0),
+                    vec2(1.0, 1.0),
+                    vec2(1.0, 1.0),
+                    vec2(-1.0, 1.0),
+                    vec2(-1.0, -1.0),
+                ],
+            ),
+            data_texture,
+            positions: cpu_splats.positions.clone(),
+            sh_degree: cpu_splats.sh_degree.min(3),
+            transformation: Mat4::identity(),
+            aabb: AxisAlignedBoundingBox::new_with_positions(&cpu_splats.positions),
+            sorted_buffer: RwLock::new((
+                None,
+                InstanceBuffer::new_with_data(
+                    context,
+                    &(0..count).map(|i| i as i32).collect::<Vec<_>>(),
+                ),
+            )),
+        }
+    }
+
+    ///
+    /// Returns the local to world transformation applied to all splats.
+    ///
+    pub fn transformation(&self) -> Mat4 {
+        self.transformation
+    }
+
+    ///
+    /// Set the local to world transformation applied to all splats.
+    ///
+    pub fn set_transformation(&mut self, transformation: Mat4) {
+        self.transformation = transformation;
+    }
+
+    ///
+    /// Sorts the splats from back to front as seen from the given viewer, unless they are already sorted for the same view.
+    ///
+    fn sort(&self, viewer: &dyn Viewer) {
+        let model_view = viewer.view() * self.transformation;
+        if self.sorted_buffer.read().unwrap().0 == Some(model_view) {
+            return;
+        }
+        let depths = self
+            .positions
+            .iter()
+            .map(|p| (model_view * p.extend(1.0)).z)
+            .collect::<Vec<_>>();
+        let mut indices = (0..self.positions.len()).collect::<Vec<_>>();
+        indices.sort_unstable_by(|a, b| {
+            depths[*a]
+                .partial_cmp(&depths[*b])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let mut sorted_buffer = self.sorted_buffer.write().unwrap();
+        sorted_buffer
+            .1
+            .fill(&indices.into_iter().map(|i| i as i32).collect::<Vec<_>>());
+        sorted_buffer.0 = Some(model_view);
+    }
+}
+
+impl<'a> IntoIterator for &'a GaussianSplats {
+    type Item = &'a dyn Object;
+    type IntoIter = std::iter::Once<&'a dyn Object>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        std::iter::once(self)
+    }
+}
+
+impl Geometry for GaussianSplats {
+    fn draw(&self, viewer: &dyn Viewer, program: &Program, render_states: RenderStates) {
+        if self.positions.is_empty() {
+            return;
+        }
+        self.sort(viewer);
+        let viewport = viewer.viewport();
+        let projection = viewer.projection();
+        program.use_uniform("view", viewer.view());
+        program.use_uniform("projection", projection);
+        program.use_uniform("transformation", self.transformation);
+        program.use_uniform(
+            "viewportSize",
+            vec2(viewport.width as f32, viewport.height as f32),
+        );
+        let eye = self.transformation.invert().unwrap_or(Mat4::identity())
+            * viewer.position().extend(1.0);
+        program.use_uniform("eyeLocal", eye.truncate() / eye.w);
+        program.use_uniform(
+            "perspective",
+            if projection.w.w == 0.0 { 1.0f32 } else { 0.0 },
+        );
+        program.use_uniform("shDegree", self.sh_degree as i32);
+        program.use_uniform("splatsPerRow", SPLATS_PER_ROW as i32);
+        program.use_texture("splatData", &self.data_texture);
+        program.use_vertex_attribute("corner", &self.corner_buffer);
+        let sorted_buffer = self.sorted_buffer.read().unwrap();
+        program.use_instance_attribute("splat_index", &sorted_buffer.1);
+        program.draw_arrays_instanced(
+            render_states,
+            viewport,
+            self.corner_buffer.vertex_count(),
+            sorted_buffer.1.instance_count(),
+        )
+    }
+
+    fn vertex_shader_source(&self) -> String {
+        include_str!("shaders/gaussian_splats.vert").to_owned()
+    }
+
+    fn id(&self) -> GeometryId {
+        GeometryId::GaussianSplats
+    }
+
+    fn render_with_material(
+        &self,
+        material: &dyn Material,
+        viewer: &dyn Viewer,
+        lights: &[&dyn Light],
+    ) {
+        if let Err(e) = render_with_material(&self.context, viewer, self, material, lights) {
+            panic!("{}", e.to_string());
+        }
+    }
+
+    fn render_with_effect(
+        &self,
+        material: &dyn Effect,
+        viewer: &dyn Viewer,
+        lights: &[&dyn Light],
+        color_texture: Option<ColorTexture>,
+        depth_texture: Option<DepthTexture>,
+    ) {
+        if let Err(e) = render_with_effect(
+            &self.context,
+            viewer,
+            self,
+            material,
+            lights,
+            color_texture,
+            depth_texture,
+        ) {
+            panic!("{}", e.to_string());
+        }
+    }
+
+    fn aabb(&self) -> AxisAlignedBoundingBox {
+        self.aabb.transformed(self.transformation)
+    }
+}
+
+impl Object for GaussianSplats {
+    fn render(&self, viewer: &dyn Viewer, lights: &[&dyn Light]) {
+        if let Err(e) =
+            render_with_material(&self.context, viewer, self, GaussianSplatsMaterial, lights)
+        {
+            panic!("{}", e.to_string());
+        }
+    }
+
+    fn material_type(&self) -> MaterialType {
+        MaterialType::Transparent
+    }
+}
+
+struct GaussianSplatsMaterial;
+
+impl Material for GaussianSplatsMaterial {
+    fn fragment_shader_source(&self, _lights: &[&dyn Light]) -> String {
+        let mut shader = ColorMapping::fragment_shader_source().to_string();
+        shader.push_str(include_str!("shaders/gaussian_splats.frag"));
+        shader
+    }
+
+    fn id(&self) -> EffectMaterialId {
+        EffectMaterialId::GaussianSplatsMaterial
+    }
+
+    fn use_uniforms(&self, program: &Program, viewer: &dyn Viewer, _lights: &[&dyn Light]) {
+        viewer.color_mapping().use_uniforms(program);
+    }
+
+    fn render_states(&self) -> RenderStates {
+        RenderStates {
+            write_mask: WriteMask::COLOR,
+            blend: Blend::TRANSPARENCY,
+            cull: Cull::None,
+            ..Default::default()
+        }
+    }
+
+    fn material_type(&self) -> MaterialType {
+        MaterialType::Transparent
+    }
+}
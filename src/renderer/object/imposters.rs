@@ -68,6 +68,13 @@ impl Imposters {
         self.material
             .update(aabb, objects, lights, max_texture_size);
     }
+
+    ///
+    /// Returns the material used to render the imposters.
+    ///
+    pub(super) fn material(&self) -> &dyn Material {
+        &self.material
+    }
 }
 
 fn get_sprite_transform(aabb: AxisAlignedBoundingBox) -> Mat4 {
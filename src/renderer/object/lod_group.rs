@@ -0,0 +1,244 @@
+use crate::core::*;
+use crate::renderer::*;
+
+///
+/// An object with several levels of detail, where the level of detail is selected based on how the object appears from the viewer,
+/// ie. the levels of detail are switched using [LodThreshold]s evaluated for the bounding box of the highest level of detail.
+/// Optionally, [Imposters] can be used instead of the geometries at the far end.
+///
+/// To hide the switch between two levels of detail, both are rendered with complementary dither patterns close to the threshold,
+/// which gradually cross-fades from one to the other, see [LodGroup::fade_width].
+///
+pub struct LodGroup<G: Geometry, M: Material> {
+    /// The highest level of detail.
+    pub geometry: G,
+    /// The lower levels of detail ordered from the highest to the lowest level of detail.
+    /// A level of detail is used when its threshold, and the thresholds of all higher levels of detail, are exceeded.
+    pub lods: Vec<(G, LodThreshold)>,
+    /// The material applied to all levels of detail.
+    pub material: M,
+    /// Imposters which are rendered instead of the geometries when the given threshold, and the thresholds of all the levels of detail, are exceeded.
+    /// The imposters should be placed at the position of this object.
+    pub imposters: Option<(Imposters, LodThreshold)>,
+    /// The width of the cross-fade between two levels of detail relative to the threshold,
+    /// for example `0.2` cross-fades between 90% and 110% of a distance threshold. Use `0.0` to switch without cross-fading.
+    pub fade_width: f32,
+}
+
+impl<G: Geometry, M: Material> LodGroup<G, M> {
+    ///
+    /// Creates a new level of detail group from the highest level of detail, the lower levels of detail and the material applied to all of them.
+    ///
+    pub fn new(geometry: G, lods: Vec<(G, LodThreshold)>, material: M) -> Self {
+        Self {
+            geometry,
+            lods,
+            material,
+            imposters: None,
+            fade_width: 0.2,
+        }
+    }
+
+    ///
+    /// Returns the level of detail to render when seen from the given viewer and, if cross-fading,
+    /// how far the cross-fade to the next lower level of detail has progressed in the range `]0..1[`.
+    /// The level after the last of the [LodGroup::lods] is the imposters.
+    ///
+    fn select_level(&self, viewer: &dyn Viewer) -> (usize, Option<f32>) {
+        let aabb = self.geometry.aabb();
+        let ratios = self
+            .lods
+            .iter()
+            .map(|(_, threshold)| threshold)
+            .chain(self.imposters.iter().map(|(_, threshold)| threshold))
+            .map(|threshold| threshold.ratio(viewer, &aabb))
+            .collect::<Vec<_>>();
+        let level = ratios.iter().take_while(|ratio| **ratio > 1.0).count();
+        if self.fade_width > 0.0 {
+            let fade = |ratio: f32| ((ratio - 1.0) / self.fade_width + 0.5).clamp(0.0, 1.0);
+            if level > 0 {
+                let f = fade(ratios[level - 1]);
+                if f < 1.0 {
+                    return (level - 1, Some(f));
+                }
+            }
+            if level < ratios.len() {
+                let f = fade(ratios[level]);
+                if f > 0.0 {
+                    return (level, Some(f));
+                }
+            }
+        }
+        (level, None)
+    }
+
+    fn geometry(&self, level: usize) -> &G {
+        match level.min(self.lods.len()) {
+            0 => &self.geometry,
+            level => &self.lods[level - 1].0,
+        }
+    }
+
+    ///
+    /// Renders the selected levels of detail with the given material or, if no material is given, the material of this group.
+    /// The imposters are only used when no material is given, otherwise the lowest level of detail is used instead.
+    ///
+    fn render_levels(
+        &self,
+        material: Option<&dyn Material>,
+        viewer: &dyn Viewer,
+        lights: &[&dyn Light],
+    ) {
+        let (level, fade) = self.select_level(viewer);
+        match fade {
+            Some(fade) => {
+                self.render_level(level, material, viewer, lights, Some((fade, false)));
+                self.render_level(level + 1, material, viewer, lights, Some((fade, true)));
+            }
+            None => self.render_level(level, material, viewer, lights, None),
+        }
+    }
+
+    fn render_level(
+        &self,
+        level: usize,
+        material: Option<&dyn Material>,
+        viewer: &dyn Viewer,
+        lights: &[&dyn Light],
+        fade: Option<(f32, bool)>,
+    ) {
+        let (geometry, material): (&dyn Geometry, &dyn Material) = match (material, &self.imposters)
+        {
+            (None, Some((imposters, _))) if level > self.lods.len() => {
+                (imposters, imposters.material())
+            }
+            (material, _) => (self.geometry(level), material.unwrap_or(&self.material)),
+        };
+        match fade {
+            Some((fade, fade_in)) => geometry.render_with_material(
+                &LodFadeMaterial {
+                    material,
+                    fade,
+                    fade_in,
+                },
+                viewer,
+                lights,
+            ),
+            None => geometry.render_with_material(material, viewer, lights),
+        }
+    }
+}
+
+impl<'a, G: Geometry, M: Material> IntoIterator for &'a LodGroup<G, M> {
+    type Item = &'a dyn Object;
+    type IntoIter = std::iter::Once<&'a dyn Object>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        std::iter::once(self)
+    }
+}
+
+impl<G: Geometry, M: Material> Geometry for LodGroup<G, M> {
+    fn draw(&self, viewer: &dyn Viewer, program: &Program, render_states: RenderStates) {
+        self.geometry.draw(viewer, program, render_states)
+    }
+
+    fn vertex_shader_source(&self) -> String {
+        self.geometry.vertex_shader_source()
+    }
+
+    fn id(&self) -> GeometryId {
+        self.geometry.id()
+    }
+
+    fn render_with_material(
+        &self,
+        material: &dyn Material,
+        viewer: &dyn Viewer,
+        lights: &[&dyn Light],
+    ) {
+        self.render_levels(Some(material), viewer, lights)
+    }
+
+    fn render_with_effect(
+        &self,
+        material: &dyn Effect,
+        viewer: &dyn Viewer,
+        lights: &[&dyn Light],
+        color_texture: Option<ColorTexture>,
+        depth_texture: Option<DepthTexture>,
+    ) {
+        // An effect cannot be dithered, so the level of detail which covers most of the object is rendered
+        let (level, fade) = self.select_level(viewer);
+        let level = if fade.map(|f| f > 0.5).unwrap_or(false) {
+            level + 1
+        } else {
+            level
+        };
+        self.geometry(level).render_with_effect(
+            material,
+            viewer,
+            lights,
+            color_texture,
+            depth_texture,
+        )
+    }
+
+    fn aabb(&self) -> AxisAlignedBoundingBox {
+        self.geometry.aabb()
+    }
+
+    fn animate(&mut self, time: f32) {
+        self.geometry.animate(time);
+        for (geometry, _) in self.lods.iter_mut() {
+            geometry.animate(time);
+        }
+    }
+}
+
+impl<G: Geometry, M: Material> Object for LodGroup<G, M> {
+    fn render(&self, viewer: &dyn Viewer, lights: &[&dyn Light]) {
+        self.render_levels(None, viewer, lights)
+    }
+
+    fn material_type(&self) -> MaterialType {
+        self.material.material_type()
+    }
+}
+
+///
+/// Wraps a material and discards the fragments using a dither pattern, so two levels of detail can be cross-faded.
+/// Since the shader depends on the wrapped material, it is identified by its source (the default [Material::id]).
+///
+struct LodFadeMaterial<'a> {
+    material: &'a dyn Material,
+    fade: f32,
+    fade_in: bool,
+}
+
+impl Material for LodFadeMaterial<'_> {
+    fn fragment_shader_source(&self, lights: &[&dyn Light]) -> String {
+        let source = self.material.fragment_shader_source(lights);
+        match rename_main_function(&source, "lod_faded_main") {
+            Some(mut output) => {
+                output.push_str(include_str!("shaders/lod_fade.frag"));
+                output
+            }
+            None => source,
+        }
+    }
+
+    fn use_uniforms(&self, program: &Program, viewer: &dyn Viewer, lights: &[&dyn Light]) {
+        self.material.use_uniforms(program, viewer, lights);
+        program.use_uniform_if_required("lodFade", self.fade);
+        program.use_uniform_if_required("lodFadeIn", if self.fade_in { 1 } else { 0 });
+    }
+
+    fn render_states(&self) -> RenderStates {
+        self.material.render_states()
+    }
+
+    fn material_type(&self) -> MaterialType {
+        self.material.material_type()
+    }
+}
@@ -0,0 +1,330 @@
+use crate::core::*;
+use crate::renderer::*;
+
+///
+/// A sky which is calculated from the direction of the sun using the analytic daylight model by Preetham et al.
+/// Use [ProceduralSky::bake] to create a cube map which can be used for image based lighting, see [Environment::new],
+/// and [ProceduralSky::update_directional_light] to make a [DirectionalLight] match the sun.
+/// The sky assumes that the positive y-axis is up.
+///
+pub struct ProceduralSky {
+    context: Context,
+    vertex_buffer: VertexBuffer<Vec3>,
+    /// The direction towards the sun.
+    pub sun_direction: Vec3,
+    /// The amount of haze in the atmosphere, ranging from 2 (clear sky) to 10 (hazy sky).
+    pub turbidity: f32,
+    /// The albedo of the ground, which is visible below the horizon.
+    pub ground_albedo: Srgba,
+    /// The scale applied to the luminance of the sky, which is given in kcd/m².
+    pub intensity: f32,
+    /// The angular radius of the sun disk in radians.
+    pub sun_disk_size: f32,
+    /// The intensity of the sun disk relative to the intensity of the sun light, see [ProceduralSky::sun_color].
+    pub sun_disk_intensity: f32,
+}
+
+impl ProceduralSky {
+    ///
+    /// Creates a new procedural sky with the sun in the given direction and with the given turbidity and ground albedo.
+    ///
+    pub fn new(
+        context: &Context,
+        sun_direction: Vec3,
+        turbidity: f32,
+        ground_albedo: Srgba,
+    ) -> Self {
+        Self {
+            context: context.clone(),
+            vertex_buffer: super::skybox::cube_vertex_buffer(context),
+            sun_direction,
+            turbidity,
+            ground_albedo,
+            intensity: 0.1,
+            sun_disk_size: 0.02,
+            sun_disk_intensity: 20.0,
+        }
+    }
+
+    ///
+    /// Renders the sky into a new cube map with the given resolution for each side.
+    /// The colors are in linear HDR color space, so the cube map can be used as the environment map in [Environment::new] or in a [Skybox].
+    ///
+    pub fn bake(&self, resolution: u32) -> TextureCubeMap {
+        let texture = TextureCubeMap::new_empty::<[f16; 4]>(
+            &self.context,
+            resolution,
+            resolution,
+            Interpolation::Linear,
+            Interpolation::Linear,
+            Some(Mipmap::default()),
+            Wrapping::ClampToEdge,
+            Wrapping::ClampToEdge,
+            Wrapping::ClampToEdge,
+        );
+        let viewport = Viewport::new_at_origo(resolution, resolution);
+        for side in CubeMapSide::iter() {
+            let mut camera = Camera::new_perspective(
+                viewport,
+                vec3(0.0, 0.0, 0.0),
+                side.direction(),
+                side.up(),
+                degrees(90.0),
+                0.1,
+                10.0,
+            );
+            camera.disable_tone_and_color_mapping();
+            let sides = [side];
+            texture
+                .as_color_target(&sides, None)
+                .clear(ClearState::color(0.0, 0.0, 0.0, 1.0))
+                .render(&camera, self, &[]);
+        }
+        texture
+    }
+
+    ///
+    /// Returns the color of the sunlight after it has passed through the atmosphere, in linear sRGB.
+    /// Each channel is in the range `[0, 1]`, where zero means that all of the light is scattered or absorbed,
+    /// for example when the sun is below the horizon.
+    ///
+    pub fn sun_color(&self) -> Vec3 {
+        let sun_direction = self.sun_direction.normalize();
+        if sun_direction.y <= 0.0 {
+            return vec3(0.0, 0.0, 0.0);
+        }
+        let theta = sun_direction.y.acos();
+        // Relative optical air mass (Kasten and Young)
+        let air_mass =
+            1.0 / (sun_direction.y + 0.15 * (93.885 - theta.to_degrees()).max(0.0001).powf(-1.253));
+        let beta = 0.04608 * self.clamped_turbidity() - 0.04586;
+        let transmittance = |wavelength: f32| {
+            let rayleigh = (-0.008735 * wavelength.powf(-4.08) * air_mass).exp();
+            let aerosol = (-beta * wavelength.powf(-1.3) * air_mass).exp();
+            rayleigh * aerosol
+        };
+        // Wavelengths in micrometers of the red, green and blue primaries
+        vec3(
+            transmittance(0.680),
+            transmittance(0.550),
+            transmittance(0.440),
+        )
+    }
+
+    ///
+    /// Updates the direction and color of the given directional light so that it matches the sun.
+    /// The intensity of the light is not changed, but the color fades to black when the sun sets.
+    /// Note that the shadow map of the light has to be generated again afterwards.
+    ///
+    pub fn update_directional_light(&self, light: &mut DirectionalLight) {
+        let color = self.sun_color();
+        light.direction = -self.sun_direction.normalize();
+        light.color = Srgba::new_opaque(
+            linear_to_srgb(color.x),
+            linear_to_srgb(color.y),
+            linear_to_srgb(color.z),
+        );
+    }
+
+    fn clamped_turbidity(&self) -> f32 {
+        self.turbidity.clamp(1.7, 10.0)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+    let value = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (value * 255.0).round() as u8
+}
+
+impl<'a> IntoIterator for &'a ProceduralSky {
+    type Item = &'a dyn Object;
+    type IntoIter = std::iter::Once<&'a dyn Object>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        std::iter::once(self)
+    }
+}
+
+impl Geometry for ProceduralSky {
+    fn draw(&self, viewer: &dyn Viewer, program: &Program, render_states: RenderStates) {
+        program.use_uniform("view", viewer.view());
+        program.use_uniform("projection", viewer.projection());
+        program.use_vertex_attribute("position", &self.vertex_buffer);
+        program.draw_arrays(render_states, viewer.viewport(), 36);
+    }
+
+    fn vertex_shader_source(&self) -> String {
+        include_str!("shaders/skybox.vert").to_owned()
+    }
+
+    fn id(&self) -> GeometryId {
+        GeometryId::Skybox
+    }
+
+    fn aabb(&self) -> AxisAlignedBoundingBox {
+        AxisAlignedBoundingBox::INFINITE
+    }
+
+    fn render_with_material(
+        &self,
+        material: &dyn Material,
+        viewer: &dyn Viewer,
+        lights: &[&dyn Light],
+    ) {
+        if let Err(e) = render_with_material(&self.context, viewer, &self, material, lights) {
+            panic!("{}", e.to_string());
+        }
+    }
+
+    fn render_with_effect(
+        &self,
+        material: &dyn Effect,
+        viewer: &dyn Viewer,
+        lights: &[&dyn Light],
+        color_texture: Option<ColorTexture>,
+        depth_texture: Option<DepthTexture>,
+    ) {
+        if let Err(e) = render_with_effect(
+            &self.context,
+            viewer,
+            self,
+            material,
+            lights,
+            color_texture,
+            depth_texture,
+        ) {
+            panic!("{}", e.to_string());
+        }
+    }
+}
+
+impl Object for ProceduralSky {
+    fn render(&self, viewer: &dyn Viewer, lights: &[&dyn Light]) {
+        if let Err(e) = render_with_material(
+            &self.context,
+            viewer,
+            self,
+            &ProceduralSkyMaterial { sky: self },
+            lights,
+        ) {
+            panic!("{}", e.to_string());
+        }
+    }
+
+    fn material_type(&self) -> MaterialType {
+        MaterialType::Opaque
+    }
+}
+
+struct ProceduralSkyMaterial<'a> {
+    sky: &'a ProceduralSky,
+}
+
+impl Material for ProceduralSkyMaterial<'_> {
+    fn id(&self) -> EffectMaterialId {
+        EffectMaterialId::ProceduralSkyMaterial
+    }
+
+    fn fragment_shader_source(&self, _lights: &[&dyn Light]) -> String {
+        format!(
+            "{}{}{}{}",
+            include_str!("../../core/shared.frag"),
+            ToneMapping::fragment_shader_source(),
+            ColorMapping::fragment_shader_source(),
+            include_str!("shaders/procedural_sky.frag")
+        )
+    }
+
+    fn use_uniforms(&self, program: &Program, viewer: &dyn Viewer, _lights: &[&dyn Light]) {
+        viewer.tone_mapping().use_uniforms(program);
+        viewer.color_mapping().use_uniforms(program);
+
+        let t = self.sky.clamped_turbidity();
+        let sun_direction = self.sky.sun_direction.normalize();
+        // The zenith angle of the sun, the model is not valid when the sun is below the horizon
+        let theta = sun_direction.y.max(0.0).acos();
+
+        // Coefficients of the Perez sky distribution function for the luminance Y and the chromaticities x and y
+        let a = vec3(
+            0.1787 * t - 1.4630,
+            -0.0193 * t - 0.2592,
+            -0.0167 * t - 0.2608,
+        );
+        let b = vec3(
+            -0.3554 * t + 0.4275,
+            -0.0665 * t + 0.0008,
+            -0.0950 * t + 0.0092,
+        );
+        let c = vec3(
+            -0.0227 * t + 5.3251,
+            -0.0004 * t + 0.2125,
+            -0.0079 * t + 0.2102,
+        );
+        let d = vec3(
+            0.1206 * t - 2.5771,
+            -0.0641 * t - 0.8989,
+            -0.0441 * t - 1.6537,
+        );
+        let e = vec3(
+            -0.0670 * t + 0.3703,
+            -0.0033 * t + 0.0452,
+            -0.0109 * t + 0.0529,
+        );
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (std::f32::consts::PI - 2.0 * theta);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let theta2 = theta * theta;
+        let theta3 = theta2 * theta;
+        let zenith_x = t * t * (0.00166 * theta3 - 0.00375 * theta2 + 0.00209 * theta)
+            + t * (-0.02903 * theta3 + 0.06377 * theta2 - 0.03202 * theta + 0.00394)
+            + (0.11693 * theta3 - 0.21196 * theta2 + 0.06052 * theta + 0.25886);
+        let zenith_y = t * t * (0.00275 * theta3 - 0.00610 * theta2 + 0.00317 * theta)
+            + t * (-0.04214 * theta3 + 0.08970 * theta2 - 0.04153 * theta + 0.00516)
+            + (0.15346 * theta3 - 0.26756 * theta2 + 0.06670 * theta + 0.26688);
+
+        // The Perez function evaluated at the zenith, used to normalize the distribution
+        let perez_zenith = |i: usize| {
+            (1.0 + a[i] * b[i].exp())
+                * (1.0 + c[i] * (d[i] * theta).exp() + e[i] * theta.cos() * theta.cos())
+        };
+        let zenith = vec3(
+            zenith_luminance.max(0.0) / perez_zenith(0),
+            zenith_x / perez_zenith(1),
+            zenith_y / perez_zenith(2),
+        );
+
+        let ground_albedo = self.sky.ground_albedo.to_linear_srgb();
+        program.use_uniform("sunDirection", sun_direction);
+        program.use_uniform("sunColor", self.sky.sun_color());
+        program.use_uniform("sunDiskSize", self.sky.sun_disk_size.max(0.0001));
+        program.use_uniform("sunDiskIntensity", self.sky.sun_disk_intensity);
+        program.use_uniform(
+            "groundAlbedo",
+            vec3(ground_albedo.x, ground_albedo.y, ground_albedo.z),
+        );
+        program.use_uniform("intensity", self.sky.intensity);
+        program.use_uniform("perezA", a);
+        program.use_uniform("perezB", b);
+        program.use_uniform("perezC", c);
+        program.use_uniform("perezD", d);
+        program.use_uniform("perezE", e);
+        program.use_uniform("zenith", zenith);
+    }
+
+    fn render_states(&self) -> RenderStates {
+        RenderStates {
+            depth_test: DepthTest::LessOrEqual,
+            cull: Cull::Front,
+            ..Default::default()
+        }
+    }
+
+    fn material_type(&self) -> MaterialType {
+        MaterialType::Opaque
+    }
+}
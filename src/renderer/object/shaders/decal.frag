@@ -0,0 +1,105 @@
+uniform mat4 viewProjectionInverse;
+uniform vec4 viewport;
+uniform mat4 decalInverse;
+uniform vec3 decalDirection;
+uniform vec3 decalTangent;
+uniform vec2 fadeCosines;
+uniform vec4 albedo;
+uniform float metallic;
+uniform float roughness;
+uniform sampler2D sceneDepth;
+
+#ifdef USE_ALBEDO_TEXTURE
+uniform sampler2D albedoTexture;
+uniform mat3 albedoTexTransform;
+#endif
+
+#ifdef USE_NORMAL_TEXTURE
+uniform sampler2D normalTexture;
+uniform mat3 normalTexTransform;
+uniform float normalScale;
+#endif
+
+#ifdef DEFERRED
+uniform sampler2DArray geometryBuffer;
+
+layout (location = 0) out vec4 outColor;
+layout (location = 1) out vec4 outNormal;
+layout (location = 2) out vec4 outEmissive;
+#else
+uniform vec3 cameraPosition;
+#ifdef USE_DEFERRED_DEPTH
+uniform sampler2D deferredDepth;
+#endif
+
+layout (location = 0) out vec4 outColor;
+#endif
+
+void main()
+{
+    ivec2 texel = ivec2(gl_FragCoord.xy - viewport.xy);
+    float depth = texelFetch(sceneDepth, texel, 0).r;
+    if (depth > 0.99999) {
+        discard;
+    }
+#ifdef USE_DEFERRED_DEPTH
+    // The surface is rendered with a deferred material, which is not affected by this decal
+    if (texelFetch(deferredDepth, texel, 0).r <= depth) {
+        discard;
+    }
+#endif
+    vec3 position = world_pos_from_depth(viewProjectionInverse, depth, (gl_FragCoord.xy - viewport.xy) / viewport.zw);
+    vec3 local_position = (decalInverse * vec4(position, 1.0)).xyz;
+    if (any(greaterThan(abs(local_position), vec3(1.0)))) {
+        discard;
+    }
+    vec2 uv = vec2(0.5 + 0.5 * local_position.x, 0.5 - 0.5 * local_position.y);
+
+#ifdef DEFERRED
+    vec4 c0 = texelFetch(geometryBuffer, ivec3(texel, 0), 0);
+    vec4 c1 = texelFetch(geometryBuffer, ivec3(texel, 1), 0);
+    vec4 c2 = texelFetch(geometryBuffer, ivec3(texel, 2), 0);
+    vec2 n2 = c1.xy * 2.0 - 1.0;
+    float z = 1.0 - n2.x * n2.x - n2.y * n2.y;
+    if (z > 0.0001) {
+        z = sqrt(z);
+    }
+    int packed = int(floor(c1.z * 255.0));
+    vec3 normal = normalize(vec3(n2.x, n2.y, (packed & 128) == 128 ? z : -z));
+#else
+    vec3 normal = normalize(cross(dFdx(position), dFdy(position)));
+    normal = dot(normal, cameraPosition - position) < 0.0 ? -normal : normal;
+#endif
+
+    // Fade out when the surface is not facing the projection direction
+    float fade = clamp((dot(normal, -decalDirection) - fadeCosines.y) / max(fadeCosines.x - fadeCosines.y, 0.0001), 0.0, 1.0);
+    vec4 color = albedo;
+#ifdef USE_ALBEDO_TEXTURE
+    color *= texture(albedoTexture, (albedoTexTransform * vec3(uv, 1.0)).xy);
+#endif
+    float alpha = color.a * fade;
+    if (alpha < 0.001) {
+        discard;
+    }
+
+    vec3 decal_normal = normal;
+#ifdef USE_NORMAL_TEXTURE
+    vec3 tangent = normalize(decalTangent - dot(decalTangent, normal) * normal);
+    vec3 bitangent = cross(normal, tangent);
+    vec3 n = (2.0 * texture(normalTexture, (normalTexTransform * vec3(uv, 1.0)).xy).xyz - 1.0) * vec3(normalScale, normalScale, 1.0);
+    decal_normal = normalize(mix(normal, mat3(tangent, bitangent, normal) * n, alpha));
+#endif
+
+#ifdef DEFERRED
+    // Blend with the content of the geometry buffer, while keeping the occlusion and emissive
+    outColor = vec4(mix(c0.rgb, color.rgb, alpha), mix(c0.a, metallic, alpha));
+    int nz = decal_normal.z < 0.0 ? 0 : 1;
+    outNormal = vec4(0.5 * decal_normal.xy + 0.5, float((packed & 127) | nz << 7) / 255.0, mix(c1.a, roughness, alpha));
+    outEmissive = c2;
+#else
+    outColor.rgb = calculate_lighting(cameraPosition, color.rgb, position, decal_normal, metallic, roughness, 1.0);
+    outColor.rgb = tone_mapping(outColor.rgb);
+    outColor.rgb = color_mapping(outColor.rgb);
+    outColor.a = alpha;
+#endif
+}
@@ -0,0 +1,15 @@
+in vec4 col;
+in vec2 uvs;
+
+layout (location = 0) out vec4 outColor;
+
+void main()
+{
+    // The quad covers three standard deviations of the Gaussian along each axis
+    vec2 p = 3.0 * (2.0 * uvs - 1.0);
+    float alpha = col.a * exp(-0.5 * dot(p, p));
+    if (alpha < 1.0 / 255.0) {
+        discard;
+    }
+    outColor = vec4(color_mapping(col.rgb), min(alpha, 0.99));
+}
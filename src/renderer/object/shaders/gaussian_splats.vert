@@ -0,0 +1,121 @@
+uniform mat4 view;
+uniform mat4 projection;
+uniform mat4 transformation;
+uniform vec2 viewportSize;
+uniform vec3 eyeLocal;
+uniform float perspective;
+uniform int shDegree;
+uniform int splatsPerRow;
+uniform sampler2D splatData;
+
+in vec2 corner;
+in int splat_index;
+
+out vec3 pos;
+out vec4 col;
+out vec2 uvs;
+flat out int instance_id;
+
+const int TEXELS_PER_SPLAT = 15;
+
+vec4 fetch(int i) {
+    int splat = splat_index;
+    ivec2 size = textureSize(splatData, 0);
+    // The rows are flipped when the data is uploaded to the texture
+    return texelFetch(splatData, ivec2((splat % splatsPerRow) * TEXELS_PER_SPLAT + i, size.y - 1 - splat / splatsPerRow), 0);
+}
+
+// Returns the spherical harmonics coefficient with the given index, where the coefficients are packed as consecutive rgb values
+vec3 sh(int k) {
+    vec4 t0 = fetch(3 + (3 * k) / 4);
+    int offset = (3 * k) % 4;
+    if (offset == 0) {
+        return t0.xyz;
+    } else if (offset == 1) {
+        return t0.yzw;
+    }
+    vec4 t1 = fetch(4 + (3 * k) / 4);
+    return offset == 2 ? vec3(t0.zw, t1.x) : vec3(t0.w, t1.xy);
+}
+
+vec3 srgb_to_linear(vec3 color) {
+    return mix(color / 12.92, pow((color + 0.055) / 1.055, vec3(2.4)), step(vec3(0.04045), color));
+}
+
+vec3 view_dependent_color(vec3 dir) {
+    vec3 result = 0.28209479177387814 * sh(0);
+    if (shDegree > 0) {
+        float x = dir.x;
+        float y = dir.y;
+        float z = dir.z;
+        result += 0.4886025119029199 * (-y * sh(1) + z * sh(2) - x * sh(3));
+        if (shDegree > 1) {
+            float xx = x * x, yy = y * y, zz = z * z;
+            float xy = x * y, yz = y * z, xz = x * z;
+            result += 1.0925484305920792 * xy * sh(4)
+                - 1.0925484305920792 * yz * sh(5)
+                + 0.31539156525252005 * (2.0 * zz - xx - yy) * sh(6)
+                - 1.0925484305920792 * xz * sh(7)
+                + 0.5462742152960396 * (xx - yy) * sh(8);
+            if (shDegree > 2) {
+                result += -0.5900435899266435 * y * (3.0 * xx - yy) * sh(9)
+                    + 2.890611442640554 * xy * z * sh(10)
+                    - 0.4570457994644658 * y * (4.0 * zz - xx - yy) * sh(11)
+                    + 0.3731763325901154 * z * (2.0 * zz - 3.0 * xx - 3.0 * yy) * sh(12)
+                    - 0.4570457994644658 * x * (4.0 * zz - xx - yy) * sh(13)
+                    + 1.445305721320277 * z * (xx - yy) * sh(14)
+                    - 0.5900435899266435 * x * (xx - 3.0 * yy) * sh(15);
+            }
+        }
+    }
+    return max(result + 0.5, vec3(0.0));
+}
+
+void main()
+{
+    vec4 t0 = fetch(0);
+    vec4 t1 = fetch(1);
+    vec4 t2 = fetch(2);
+    vec3 center = t0.xyz;
+    mat3 covariance = mat3(t1.x, t1.y, t1.z,
+                           t1.y, t1.w, t2.x,
+                           t1.z, t2.x, t2.y);
+
+    vec4 world_center = transformation * vec4(center, 1.0);
+    vec4 view_center = view * world_center;
+    float depth = mix(1.0, -view_center.z, perspective);
+    vec4 clip_center = projection * view_center;
+    if (depth <= 0.0 || any(greaterThan(abs(clip_center.xy), vec2(1.3 * clip_center.w)))) {
+        // Behind the viewer or outside the screen
+        gl_Position = vec4(0.0, 0.0, 2.0, 1.0);
+        return;
+    }
+
+    // Project the covariance onto the screen using the Jacobian of the projection
+    mat3 model_view = mat3(view) * mat3(transformation);
+    mat3 view_covariance = model_view * covariance * transpose(model_view);
+    vec2 focal = 0.5 * viewportSize * vec2(projection[0][0], projection[1][1]);
+    vec3 j0 = vec3(focal.x / depth, 0.0, perspective * focal.x * view_center.x / (depth * depth));
+    vec3 j1 = vec3(0.0, focal.y / depth, perspective * focal.y * view_center.y / (depth * depth));
+    // Add a small amount to the diagonal so each splat covers at least a pixel
+    float a = dot(j0, view_covariance * j0) + 0.3;
+    float b = dot(j0, view_covariance * j1);
+    float c = dot(j1, view_covariance * j1) + 0.3;
+
+    // The axes of the ellipse are the eigenvectors of the 2D covariance
+    float mid = 0.5 * (a + c);
+    float radius = sqrt(max(0.25 * (a - c) * (a - c) + b * b, 0.0));
+    float lambda1 = mid + radius;
+    float lambda2 = max(mid - radius, 0.1);
+    vec2 axis1 = abs(b) > 1e-6 ? normalize(vec2(b, lambda1 - a)) : (a >= c ? vec2(1.0, 0.0) : vec2(0.0, 1.0));
+    vec2 axis2 = vec2(-axis1.y, axis1.x);
+    // Cover three standard deviations along each axis
+    vec2 offset = 3.0 * (corner.x * sqrt(lambda1) * axis1 + corner.y * sqrt(lambda2) * axis2);
+
+    gl_Position = clip_center + vec4(2.0 * offset / viewportSize * clip_center.w, 0.0, 0.0);
+
+    pos = world_center.xyz;
+    col = vec4(srgb_to_linear(view_dependent_color(normalize(center - eyeLocal))), t0.w);
+    uvs = 0.5 + 0.5 * corner;
+    instance_id = splat_index;
+}
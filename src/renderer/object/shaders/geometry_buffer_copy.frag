@@ -0,0 +1,13 @@
+uniform sampler2DArray geometryBuffer;
+
+layout (location = 0) out vec4 outColor;
+layout (location = 1) out vec4 outNormal;
+layout (location = 2) out vec4 outEmissive;
+
+void main()
+{
+    ivec2 texel = ivec2(gl_FragCoord.xy);
+    outColor = texelFetch(geometryBuffer, ivec3(texel, 0), 0);
+    outNormal = texelFetch(geometryBuffer, ivec3(texel, 1), 0);
+    outEmissive = texelFetch(geometryBuffer, ivec3(texel, 2), 0);
+}
@@ -0,0 +1,15 @@
+
+uniform float lodFade;
+uniform int lodFadeIn;
+
+void main()
+{
+    // A 4x4 ordered dither pattern, so the fragments kept for the two cross-faded levels of detail are complementary
+    const float bayer[16] = float[16](0.0, 8.0, 2.0, 10.0, 12.0, 4.0, 14.0, 6.0, 3.0, 11.0, 1.0, 9.0, 15.0, 7.0, 13.0, 5.0);
+    ivec2 p = ivec2(gl_FragCoord.xy) % 4;
+    float threshold = (bayer[p.y * 4 + p.x] + 0.5) / 16.0;
+    if ((threshold < lodFade) != (lodFadeIn == 1)) {
+        discard;
+    }
+    lod_faded_main();
+}
@@ -0,0 +1,53 @@
+uniform vec3 sunDirection;
+uniform vec3 sunColor;
+uniform float sunDiskSize;
+uniform float sunDiskIntensity;
+uniform vec3 groundAlbedo;
+uniform float intensity;
+uniform vec3 perezA;
+uniform vec3 perezB;
+uniform vec3 perezC;
+uniform vec3 perezD;
+uniform vec3 perezE;
+uniform vec3 zenith;
+
+in vec3 coords;
+
+layout (location = 0) out vec4 outColor;
+
+vec3 perez(float cos_theta, float gamma, float cos_gamma)
+{
+    return (1.0 + perezA * exp(perezB / max(cos_theta, 0.01))) * (1.0 + perezC * exp(perezD * gamma) + perezE * cos_gamma * cos_gamma);
+}
+
+vec3 xyY_to_linear_srgb(vec3 Yxy)
+{
+    float Y = Yxy.x;
+    float x = Yxy.y;
+    float y = max(Yxy.z, 0.0001);
+    vec3 XYZ = vec3(x * Y / y, Y, (1.0 - x - y) * Y / y);
+    return max(mat3(3.2406, -0.9689, 0.0557, -1.5372, 1.8758, -0.2040, -0.4986, 0.0415, 1.0570) * XYZ, vec3(0.0));
+}
+
+vec3 sky(vec3 direction)
+{
+    float cos_gamma = clamp(dot(direction, sunDirection), -1.0, 1.0);
+    float gamma = acos(cos_gamma);
+    return xyY_to_linear_srgb(zenith * perez(direction.y, gamma, cos_gamma)) * intensity;
+}
+
+void main() {
+    vec3 direction = normalize(coords);
+    vec3 horizon_direction = normalize(vec3(direction.x, max(direction.y, 0.0), direction.z) + vec3(0.0, 0.001, 0.0));
+    vec3 color = sky(horizon_direction);
+    if(direction.y > 0.0) {
+        float sun = smoothstep(cos(sunDiskSize), cos(sunDiskSize * 0.9), dot(direction, sunDirection));
+        color += sun * sunColor * sunDiskIntensity;
+    }
+    vec3 ground = groundAlbedo * sky(vec3(0.0, 1.0, 0.0));
+    color = mix(color, ground, smoothstep(0.0, 0.02, -direction.y));
+
+    outColor = vec4(color, 1.0);
+    outColor.rgb = tone_mapping(outColor.rgb);
+    outColor.rgb = color_mapping(outColor.rgb);
+}
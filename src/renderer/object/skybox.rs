@@ -82,47 +82,7 @@ impl Skybox {
     /// The colors are assumed to be in linear sRGB (`RgbU8`), linear sRGB with an alpha channel (`RgbaU8`) or HDR color space.
     ///
     pub fn new_with_texture(context: &Context, texture: Arc<TextureCubeMap>) -> Self {
-        let vertex_buffer = VertexBuffer::new_with_data(
-            context,
-            &[
-                vec3(1.0, 1.0, -1.0),
-                vec3(-1.0, 1.0, -1.0),
-                vec3(1.0, 1.0, 1.0),
-                vec3(-1.0, 1.0, 1.0),
-                vec3(1.0, 1.0, 1.0),
-                vec3(-1.0, 1.0, -1.0),
-                vec3(-1.0, -1.0, -1.0),
-                vec3(1.0, -1.0, -1.0),
-                vec3(1.0, -1.0, 1.0),
-                vec3(1.0, -1.0, 1.0),
-                vec3(-1.0, -1.0, 1.0),
-                vec3(-1.0, -1.0, -1.0),
-                vec3(1.0, -1.0, -1.0),
-                vec3(-1.0, -1.0, -1.0),
-                vec3(1.0, 1.0, -1.0),
-                vec3(-1.0, 1.0, -1.0),
-                vec3(1.0, 1.0, -1.0),
-                vec3(-1.0, -1.0, -1.0),
-                vec3(-1.0, -1.0, 1.0),
-                vec3(1.0, -1.0, 1.0),
-                vec3(1.0, 1.0, 1.0),
-                vec3(1.0, 1.0, 1.0),
-                vec3(-1.0, 1.0, 1.0),
-                vec3(-1.0, -1.0, 1.0),
-                vec3(1.0, -1.0, -1.0),
-                vec3(1.0, 1.0, -1.0),
-                vec3(1.0, 1.0, 1.0),
-                vec3(1.0, 1.0, 1.0),
-                vec3(1.0, -1.0, 1.0),
-                vec3(1.0, -1.0, -1.0),
-                vec3(-1.0, 1.0, -1.0),
-                vec3(-1.0, -1.0, -1.0),
-                vec3(-1.0, 1.0, 1.0),
-                vec3(-1.0, -1.0, 1.0),
-                vec3(-1.0, 1.0, 1.0),
-                vec3(-1.0, -1.0, -1.0),
-            ],
-        );
+        let vertex_buffer = cube_vertex_buffer(context);
 
         Skybox {
             context: context.clone(),
@@ -139,6 +99,53 @@ impl Skybox {
     }
 }
 
+///
+/// Returns a vertex buffer containing the 36 vertex positions of the triangles of a cube centered at origo with side length 2.
+///
+pub(super) fn cube_vertex_buffer(context: &Context) -> VertexBuffer<Vec3> {
+    VertexBuffer::new_with_data(
+        context,
+        &[
+            vec3(1.0, 1.0, -1.0),
+            vec3(-1.0, 1.0, -1.0),
+            vec3(1.0, 1.0, 1.0),
+            vec3(-1.0, 1.0, 1.0),
+            vec3(1.0, 1.0, 1.0),
+            vec3(-1.0, 1.0, -1.0),
+            vec3(-1.0, -1.0, -1.0),
+            vec3(1.0, -1.0, -1.0),
+            vec3(1.0, -1.0, 1.0),
+            vec3(1.0, -1.0, 1.0),
+            vec3(-1.0, -1.0, 1.0),
+            vec3(-1.0, -1.0, -1.0),
+            vec3(1.0, -1.0, -1.0),
+            vec3(-1.0, -1.0, -1.0),
+            vec3(1.0, 1.0, -1.0),
+            vec3(-1.0, 1.0, -1.0),
+            vec3(1.0, 1.0, -1.0),
+            vec3(-1.0, -1.0, -1.0),
+            vec3(-1.0, -1.0, 1.0),
+            vec3(1.0, -1.0, 1.0),
+            vec3(1.0, 1.0, 1.0),
+            vec3(1.0, 1.0, 1.0),
+            vec3(-1.0, 1.0, 1.0),
+            vec3(-1.0, -1.0, 1.0),
+            vec3(1.0, -1.0, -1.0),
+            vec3(1.0, 1.0, -1.0),
+            vec3(1.0, 1.0, 1.0),
+            vec3(1.0, 1.0, 1.0),
+            vec3(1.0, -1.0, 1.0),
+            vec3(1.0, -1.0, -1.0),
+            vec3(-1.0, 1.0, -1.0),
+            vec3(-1.0, -1.0, -1.0),
+            vec3(-1.0, 1.0, 1.0),
+            vec3(-1.0, -1.0, 1.0),
+            vec3(-1.0, 1.0, 1.0),
+            vec3(-1.0, -1.0, -1.0),
+        ],
+    )
+}
+
 impl<'a> IntoIterator for &'a Skybox {
     type Item = &'a dyn Object;
     type IntoIter = std::iter::Once<&'a dyn Object>;
@@ -0,0 +1,229 @@
+use crate::core::*;
+use crate::renderer::*;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+///
+/// Statistics about the batching done by a [StaticBatcher], see [StaticBatch::stats].
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StaticBatchStats {
+    /// The number of objects added to the [StaticBatcher], ie. the number of draw calls without batching.
+    pub objects: u32,
+    /// The number of batches, ie. the number of draw calls with batching.
+    pub batches: u32,
+}
+
+///
+/// Collects static objects, each defined by a [CpuMesh], a material and a transformation, and merges the objects that share both the [CpuMesh]
+/// and the material into one [InstancedMesh] where the transformation of each object is an instance transformation.
+/// This reduces the number of draw calls and uniform uploads when rendering many copies of the same objects, for example the buildings or trees in a scene.
+///
+/// The [CpuMesh]es are matched by content, so meshes that are loaded or cloned separately are batched together if they contain the same data.
+/// The materials are matched by reference only, ie. two materials with the same properties are not batched together unless they are the same instance,
+/// so create one material for each unique material, for example each [CpuMaterial] in a loaded model, and use it for all the objects with that material.
+///
+pub struct StaticBatcher<'a, M: Material> {
+    batches: Vec<(&'a CpuMesh, &'a M, Vec<Mat4>)>,
+    // The indices of the batches for each mesh hash and material, where all the batches have different meshes
+    batch_indices: HashMap<(u64, *const M), Vec<usize>>,
+    mesh_hashes: HashMap<*const CpuMesh, u64>,
+}
+
+impl<'a, M: Material> StaticBatcher<'a, M> {
+    ///
+    /// Creates a new empty static batcher.
+    ///
+    pub fn new() -> Self {
+        Self {
+            batches: Vec::new(),
+            batch_indices: HashMap::new(),
+            mesh_hashes: HashMap::new(),
+        }
+    }
+
+    ///
+    /// Adds an object with the given mesh, material and transformation.
+    ///
+    pub fn add(&mut self, cpu_mesh: &'a CpuMesh, material: &'a M, transformation: Mat4) {
+        let mesh_hash = *self
+            .mesh_hashes
+            .entry(cpu_mesh as *const CpuMesh)
+            .or_insert_with(|| mesh_hash(cpu_mesh));
+        let key = (mesh_hash, material as *const M);
+        let indices = self.batch_indices.entry(key).or_default();
+        // Compare the meshes with the same hash, so a hash collision does not merge different meshes
+        let index = match indices.iter().find(|i| {
+            let batch_mesh = self.batches[**i].0;
+            std::ptr::eq(batch_mesh, cpu_mesh) || mesh_eq(batch_mesh, cpu_mesh)
+        }) {
+            Some(index) => *index,
+            None => {
+                self.batches.push((cpu_mesh, material, Vec::new()));
+                indices.push(self.batches.len() - 1);
+                self.batches.len() - 1
+            }
+        };
+        self.batches[index].2.push(transformation);
+    }
+
+    ///
+    /// Returns the number of objects added and the number of batches they are merged into.
+    ///
+    pub fn stats(&self) -> StaticBatchStats {
+        StaticBatchStats {
+            objects: self.batches.iter().map(|(_, _, t)| t.len() as u32).sum(),
+            batches: self.batches.len() as u32,
+        }
+    }
+}
+
+impl<M: Material + Clone> StaticBatcher<'_, M> {
+    ///
+    /// Builds the batches by constructing an [InstancedMesh] for each unique combination of [CpuMesh] and material.
+    ///
+    pub fn build(&self, context: &Context) -> StaticBatch<M> {
+        StaticBatch {
+            batches: self
+                .batches
+                .iter()
+                .map(|(cpu_mesh, material, transformations)| {
+                    Gm::new(
+                        InstancedMesh::new(
+                            context,
+                            &Instances {
+                                transformations: transformations.clone(),
+                                ..Default::default()
+                            },
+                            cpu_mesh,
+                        ),
+                        (*material).clone(),
+                    )
+                })
+                .collect(),
+            stats: self.stats(),
+        }
+    }
+}
+
+///
+/// Returns a hash of the vertex data and indices of the given mesh.
+///
+fn mesh_hash(cpu_mesh: &CpuMesh) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    for p in cpu_mesh.positions.to_f32() {
+        [p.x, p.y, p.z].map(f32::to_bits).hash(&mut hasher);
+    }
+    cpu_mesh.normals.is_some().hash(&mut hasher);
+    for n in cpu_mesh.normals.iter().flatten() {
+        [n.x, n.y, n.z].map(f32::to_bits).hash(&mut hasher);
+    }
+    cpu_mesh.tangents.is_some().hash(&mut hasher);
+    for t in cpu_mesh.tangents.iter().flatten() {
+        [t.x, t.y, t.z, t.w].map(f32::to_bits).hash(&mut hasher);
+    }
+    cpu_mesh.uvs.is_some().hash(&mut hasher);
+    for uv in cpu_mesh.uvs.iter().flatten() {
+        [uv.x, uv.y].map(f32::to_bits).hash(&mut hasher);
+    }
+    cpu_mesh.colors.is_some().hash(&mut hasher);
+    for c in cpu_mesh.colors.iter().flatten() {
+        [c.r, c.g, c.b, c.a].hash(&mut hasher);
+    }
+    indices(cpu_mesh).hash(&mut hasher);
+    hasher.finish()
+}
+
+///
+/// Returns whether the given meshes have the same vertex data and indices.
+///
+fn mesh_eq(a: &CpuMesh, b: &CpuMesh) -> bool {
+    fn attribute_eq<T, U: PartialEq>(
+        a: Option<&Vec<T>>,
+        b: Option<&Vec<T>>,
+        value: impl Fn(&T) -> U,
+    ) -> bool {
+        match (a, b) {
+            (Some(a), Some(b)) => {
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| value(a) == value(b))
+            }
+            (None, None) => true,
+            _ => false,
+        }
+    }
+    attribute_eq(
+        Some(&a.positions.to_f32()),
+        Some(&b.positions.to_f32()),
+        |p| [p.x, p.y, p.z].map(f32::to_bits),
+    ) && attribute_eq(a.normals.as_ref(), b.normals.as_ref(), |n| {
+        [n.x, n.y, n.z].map(f32::to_bits)
+    }) && attribute_eq(a.tangents.as_ref(), b.tangents.as_ref(), |t| {
+        [t.x, t.y, t.z, t.w].map(f32::to_bits)
+    }) && attribute_eq(a.uvs.as_ref(), b.uvs.as_ref(), |uv| {
+        [uv.x, uv.y].map(f32::to_bits)
+    }) && attribute_eq(a.colors.as_ref(), b.colors.as_ref(), |c| {
+        [c.r, c.g, c.b, c.a]
+    }) && indices(a) == indices(b)
+}
+
+///
+/// Returns the indices of the given mesh as u32, or `None` if the mesh is not indexed.
+///
+fn indices(cpu_mesh: &CpuMesh) -> Option<Vec<u32>> {
+    match &cpu_mesh.indices {
+        Indices::U8(ind) => Some(ind.iter().map(|i| *i as u32).collect()),
+        Indices::U16(ind) => Some(ind.iter().map(|i| *i as u32).collect()),
+        Indices::U32(ind) => Some(ind.clone()),
+        Indices::None => None,
+    }
+}
+
+impl<M: Material> Default for StaticBatcher<'_, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+///
+/// A set of batched objects built by a [StaticBatcher], where each batch is rendered with one draw call.
+///
+pub struct StaticBatch<M: Material> {
+    batches: Vec<Gm<InstancedMesh, M>>,
+    stats: StaticBatchStats,
+}
+
+impl<M: Material> StaticBatch<M> {
+    ///
+    /// Returns the number of objects in this batch and the number of batches they are merged into,
+    /// ie. the number of draw calls without and with batching.
+    ///
+    pub fn stats(&self) -> StaticBatchStats {
+        self.stats
+    }
+}
+
+impl<'a, M: Material> IntoIterator for &'a StaticBatch<M> {
+    type Item = &'a dyn Object;
+    type IntoIter = std::vec::IntoIter<&'a dyn Object>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+            .map(|m| m as &dyn Object)
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+}
+
+use std::ops::Deref;
+impl<M: Material> Deref for StaticBatch<M> {
+    type Target = Vec<Gm<InstancedMesh, M>>;
+    fn deref(&self) -> &Self::Target {
+        &self.batches
+    }
+}
+
+impl<M: Material> std::ops::DerefMut for StaticBatch<M> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.batches
+    }
+}
@@ -4,6 +4,10 @@
 //! Reductions of the public use ID ranges will be considered a breaking change and result in the appropriate version increment.
 //! The allocation of internal use IDs should be considered unstable.
 //!
+//! Alternatively, return the `FromSource` ID (which is also the default implementation of the `id` methods) to identify the shader by its source instead,
+//! or identify all shaders by their source using [ProgramCacheKey::ShaderSource](crate::core::ProgramCacheKey::ShaderSource).
+//! Then there is no need to allocate an ID at all.
+//!
 
 use crate::texture::{ColorTexture, DepthTexture};
 
@@ -97,9 +101,14 @@ pub enum GeometryId {
     TerrainPatch = 0x8002,
     Sprites = 0x8004,
     WaterPatch = 0x8005,
-    MeshBase = 0x8010,           // To 0x801F
-    ParticleSystemBase = 0x8040, // To 0x807F
-    InstancedMeshBase = 0x8080,  // To 0x80FF
+    Polyline3D = 0x8006,
+    GaussianSplats = 0x8007,
+    PointCloudGeometryBase = 0x8008, // To 0x800B
+    LabelsBase = 0x800C,             // To 0x800D
+    MeshBase = 0x8010,               // To 0x801F
+    ParticleSystemBase = 0x8040,     // To 0x807F
+    InstancedMeshBase = 0x8080,      // To 0x80FF
+    FromSource = 0xFFFF,             // Identified by the shader source
 }
 
 impl GeometryId {
@@ -112,6 +121,11 @@ impl GeometryId {
         InstancedMeshBase,
         InstancedMesh(normal, tangents, uv, color, instance_color, instance_uv)
     );
+    enum_bitfield!(
+        PointCloudGeometryBase,
+        PointCloudGeometry(colors, intensities)
+    );
+    enum_bitfield!(LabelsBase, Labels(uvs));
 }
 
 ///
@@ -122,12 +136,19 @@ impl GeometryId {
 #[open_enum]
 #[repr(u16)]
 pub enum EffectMaterialId {
-    LightingPassEffectBase = 0x5000, // To 0x503F
-    WaterEffectBase = 0x5800,        // To 0x583F
-    CopyEffectBase = 0x6000,         // To 0x603F
-    ScreenEffectBase = 0x6800,       // To 0x683F
-    FogEffectBase = 0x7000,          // To 0x703F
-    FxaaEffectBase = 0x7800,         // To 0x7838 (has holes)
+    LightingPassEffectBase = 0x5000,    // To 0x503F
+    WaterEffectBase = 0x5800,           // To 0x583F
+    CopyEffectBase = 0x6000,            // To 0x603F
+    ScreenEffectBase = 0x6800,          // To 0x683F
+    FogEffectBase = 0x7000,             // To 0x703F
+    EyeDomeLightingEffectBase = 0x7100, // To 0x713F
+    FxaaEffectBase = 0x7800,            // To 0x7838 (has holes)
+    SelectionOutlineEffect = 0x7840,
+    HiZReduceEffect = 0x7841,
+    GeometryBufferCopyEffect = 0x7842,
+    DecalEffectBase = 0x7850,                // To 0x785F
+    VolumetricLightMarchEffectBase = 0x7880, // To 0x78BF
+    VolumetricLightEffectBase = 0x78C0,      // To 0x78CF
 
     ColorMaterialBase = 0x8000, // To 0x8001
     DepthMaterial = 0x8002,
@@ -135,6 +156,9 @@ pub enum EffectMaterialId {
     SkyboxMaterial = 0x8004,
     UVMaterial = 0x8005,
     NormalMaterialBase = 0x8006, // To 0x8007
+    SelectionMaskMaterial = 0x8008,
+    CubeMapResampleMaterial = 0x8009,
+    ProceduralSkyMaterial = 0x800A,
     IntersectionMaterial = 0x800B,
     IsosurfaceMaterial = 0x800C,
     ImpostersMaterial = 0x800D,
@@ -144,6 +168,11 @@ pub enum EffectMaterialId {
     PhysicalMaterialBase = 0x8020,         // To 0x803F
     DeferredPhysicalMaterialBase = 0x8040, // To 0x807F
     PrefilterMaterial = 0x8080,
+    VolumeMaterialBase = 0x8082, // To 0x8083
+    RegionPickMaterial = 0x8084,
+    GaussianSplatsMaterial = 0x8085,
+    TextMaterial = 0x8086,
+    FromSource = 0xFFFF, // Identified by the shader source
 }
 
 impl EffectMaterialId {
@@ -152,10 +181,39 @@ impl EffectMaterialId {
     enum_effectfield!(CopyEffectBase, CopyEffect(Option<...Default>));
     enum_effectfield!(ScreenEffectBase, ScreenEffect(Option<...Default>));
     enum_effectfield!(FogEffectBase, FogEffect(...Default));
+    enum_effectfield!(
+        EyeDomeLightingEffectBase,
+        EyeDomeLightingEffect(...Default)
+    );
     enum_effectfield!(FxaaEffectBase, FxaaEffect(color_texture: ColorTexture));
+    enum_effectfield!(
+        VolumetricLightEffectBase,
+        VolumetricLightEffect(depth_texture: DepthTexture)
+    );
+
+    #[allow(non_snake_case)]
+    #[inline]
+    pub(crate) fn VolumetricLightMarchEffect(
+        depth_texture: DepthTexture,
+        spot_light: bool,
+        shadow_map: bool,
+    ) -> Self {
+        Self(
+            Self::VolumetricLightMarchEffectBase.0
+                | depth_texture.id()
+                | if spot_light { 1 << 4 } else { 0 }
+                | if shadow_map { 1 << 5 } else { 0 },
+        )
+    }
+
+    enum_bitfield!(
+        DecalEffectBase,
+        DecalEffect(deferred, deferred_depth, albedo_texture, normal_texture)
+    );
 
     enum_bitfield!(ColorMaterialBase, ColorMaterial(texture));
     enum_bitfield!(NormalMaterialBase, NormalMaterial(normal_texture));
+    enum_bitfield!(VolumeMaterialBase, VolumeMaterial(lighting));
     enum_bitfield!(
         ORMMaterialBase,
         ORMMaterial(metallic_roughness_texture, occlusion_texture)
@@ -194,11 +252,40 @@ pub enum LightId {
     AmbientLightBase = 0x80,     // To 0x81
     DirectionalLightBase = 0x82, // To 0x83
     PointLight = 0x84,
-    SpotLightBase = 0x86, // To 0x87
+    SpotLightBase = 0x86,                          // To 0x87
+    AmbientLightWithReflectionProbesBase = 0x88,   // To 0x8F
+    AmbientLightWithSphericalHarmonicsBase = 0x90, // To 0x99
+    FromSource = 0xFF,                             // Identified by the shader source
 }
 
 impl LightId {
     enum_bitfield!(AmbientLightBase, AmbientLight(environment));
     enum_bitfield!(DirectionalLightBase, DirectionalLight(shadow_texture));
     enum_bitfield!(SpotLightBase, SpotLight(shadow_texture));
+
+    ///
+    /// The probe count must be between 1 and 4
+    ///
+    #[allow(non_snake_case)]
+    #[inline]
+    pub(crate) fn AmbientLightWithReflectionProbes(environment: bool, probe_count: u8) -> Self {
+        Self(
+            Self::AmbientLightWithReflectionProbesBase.0
+                | if environment { 1 } else { 0 }
+                | ((probe_count - 1) << 1),
+        )
+    }
+
+    ///
+    /// The probe count must be between 0 and 4
+    ///
+    #[allow(non_snake_case)]
+    #[inline]
+    pub(crate) fn AmbientLightWithSphericalHarmonics(environment: bool, probe_count: u8) -> Self {
+        Self(
+            Self::AmbientLightWithSphericalHarmonicsBase.0
+                | if environment { 1 } else { 0 }
+                | (probe_count << 1),
+        )
+    }
 }
@@ -3,33 +3,35 @@ use lyon::math::Point;
 use lyon::path::Path;
 use lyon::tessellation::*;
 use std::collections::HashMap;
+use std::sync::RwLock;
+use swash::scale::{ScaleContext, Scaler};
 use swash::zeno::{Command, PathData};
-use swash::{scale::ScaleContext, shape::ShapeContext, FontRef, GlyphId};
+use swash::{FontRef, GlyphId};
 
-///
-/// Options for text layout.
-///
-#[derive(Debug, Clone, Copy)]
-pub struct TextLayoutOptions {
-    ///
-    /// The line height multiplier where 1.0 corresponds to the maximum height of the font.
-    /// Default is 1.2.
-    ///
-    pub line_height: f32,
-}
+mod layout;
+#[doc(inline)]
+pub use layout::*;
+use layout::{layout_text, LayoutFont};
 
-impl Default for TextLayoutOptions {
-    fn default() -> Self {
-        Self { line_height: 1.2 }
-    }
-}
+mod sdf_text;
+#[doc(inline)]
+pub use sdf_text::*;
+
+mod text_material;
+#[doc(inline)]
+pub use text_material::*;
+
+mod labels;
+#[doc(inline)]
+pub use labels::*;
 
 ///
 /// A utility struct for generating a [CpuMesh] from a text string with a given font.
+/// Glyphs that are missing in the font are taken from the first fallback font that contains them, see [TextGenerator::add_fallback_font].
 ///
 pub struct TextGenerator<'a> {
-    map: HashMap<GlyphId, CpuMesh>,
-    font: FontRef<'a>,
+    map: RwLock<HashMap<(usize, GlyphId), Option<CpuMesh>>>,
+    fonts: Vec<FontRef<'a>>,
     max_height: f32,
     size: f32,
 }
@@ -47,105 +49,150 @@ impl<'a> TextGenerator<'a> {
         let mut map = HashMap::new();
         let mut max_height: f32 = 0.0;
         font.charmap().enumerate(|_, id| {
-            if let Some(outline) = scaler.scale_outline(id) {
-                let mut builder = Path::builder();
-                for command in outline.path().commands() {
-                    match command {
-                        Command::MoveTo(p) => {
-                            builder.begin(Point::new(p.x, p.y));
-                        }
-                        Command::LineTo(p) => {
-                            builder.line_to(Point::new(p.x, p.y));
-                        }
-                        Command::CurveTo(p1, p2, p3) => {
-                            builder.cubic_bezier_to(
-                                Point::new(p1.x, p1.y),
-                                Point::new(p2.x, p2.y),
-                                Point::new(p3.x, p3.y),
-                            );
-                        }
-                        Command::QuadTo(p1, p2) => {
-                            builder.quadratic_bezier_to(
-                                Point::new(p1.x, p1.y),
-                                Point::new(p2.x, p2.y),
-                            );
-                        }
-                        Command::Close => builder.close(),
-                    }
-                }
-                let path = builder.build();
-
-                let mut tessellator = FillTessellator::new();
-                let mut geometry: VertexBuffers<Vec3, u32> = VertexBuffers::new();
-                let options = FillOptions::default();
-                if tessellator
-                    .tessellate_path(
-                        &path,
-                        &options,
-                        &mut BuffersBuilder::new(&mut geometry, |vertex: FillVertex| {
-                            vec3(vertex.position().x, vertex.position().y, 0.0)
-                        }),
-                    )
-                    .is_ok()
-                {
-                    let mesh = CpuMesh {
-                        positions: Positions::F32(geometry.vertices),
-                        indices: Indices::U32(geometry.indices),
-                        ..Default::default()
-                    };
-                    max_height = max_height.max(mesh.compute_aabb().size().y);
-                    map.insert(id, mesh);
-                }
+            if let Some(mesh) = tessellate(&mut scaler, id) {
+                max_height = max_height.max(mesh.compute_aabb().size().y);
+                map.insert((0, id), Some(mesh));
             }
         });
         Ok(Self {
-            map,
-            font,
+            map: RwLock::new(map),
+            fonts: vec![font],
             max_height,
             size,
         })
     }
 
+    ///
+    /// Adds a font which is used for the glyphs that are missing in the font given at construction and the previously added fallback fonts,
+    /// for example a font with CJK characters or emojis.
+    /// The glyphs of fallback fonts are generated when they are first used.
+    ///
+    pub fn add_fallback_font(
+        &mut self,
+        font_bytes: &'a [u8],
+        font_index: u32,
+    ) -> Result<(), RendererError> {
+        let font = FontRef::from_index(font_bytes, font_index as usize)
+            .ok_or(RendererError::MissingFont(font_index))?;
+        self.fonts.push(font);
+        Ok(())
+    }
+
     ///
     /// Generates a [CpuMesh] from the given text string.
     ///
     pub fn generate(&self, text: &str, options: TextLayoutOptions) -> CpuMesh {
-        let mut shape_context = ShapeContext::new();
-        let mut shaper = shape_context.builder(self.font).size(self.size).build();
+        self.layout(&[TextSpan::new(text)], options).mesh
+    }
+
+    ///
+    /// Generates a [CpuMesh] from the given styled spans and returns it together with the bounding box of each glyph in the text.
+    ///
+    pub fn layout(&self, spans: &[TextSpan], options: TextLayoutOptions) -> TextLayout {
+        let fonts = self
+            .fonts
+            .iter()
+            .enumerate()
+            .map(|(i, font)| LayoutFont {
+                font: *font,
+                line_height: if i == 0 {
+                    self.max_height / self.size
+                } else {
+                    let metrics = font.metrics(&[]);
+                    (metrics.ascent + metrics.descent) / metrics.units_per_em as f32
+                },
+            })
+            .collect::<Vec<_>>();
+        let (glyphs, bounds) = layout_text(&fonts, self.size, spans, options);
+
+        let has_colors = glyphs.iter().any(|glyph| glyph.color.is_some());
         let mut positions = Vec::new();
         let mut indices = Vec::new();
-        let mut position = vec2(0.0, 0.0);
+        let mut colors = Vec::new();
+        let mut map = self.map.write().unwrap();
+        for glyph in glyphs {
+            let mesh = map.entry((glyph.font, glyph.id)).or_insert_with(|| {
+                let mut context = ScaleContext::new();
+                let mut scaler = context
+                    .builder(self.fonts[glyph.font])
+                    .size(self.size)
+                    .build();
+                tessellate(&mut scaler, glyph.id)
+            });
+            let Some(mesh) = mesh else {
+                continue;
+            };
+
+            let index_offset = positions.len() as u32;
+            let Indices::U32(mesh_indices) = &mesh.indices else {
+                unreachable!()
+            };
+            indices.extend(mesh_indices.iter().map(|i| i + index_offset));
 
-        shaper.add_str(text);
-        shaper.shape_with(|cluster| {
-            let t = text.get(cluster.source.to_range());
-            if matches!(t, Some("\n")) {
-                // Move to the next line
-                position.y -= self.max_height * options.line_height;
-                position.x = 0.0;
+            let scale = glyph.size / self.size;
+            let position_offset = glyph.position.extend(0.0);
+            let Positions::F32(mesh_positions) = &mesh.positions else {
+                unreachable!()
+            };
+            positions.extend(mesh_positions.iter().map(|p| p * scale + position_offset));
+            if has_colors {
+                colors.resize(positions.len(), glyph.color.unwrap_or(Srgba::WHITE));
             }
-            for glyph in cluster.glyphs {
-                let mesh = self.map.get(&glyph.id).unwrap();
+        }
 
-                let index_offset = positions.len() as u32;
-                let Indices::U32(mesh_indices) = &mesh.indices else {
-                    unreachable!()
-                };
-                indices.extend(mesh_indices.iter().map(|i| i + index_offset));
+        TextLayout {
+            mesh: CpuMesh {
+                positions: Positions::F32(positions),
+                indices: Indices::U32(indices),
+                colors: has_colors.then_some(colors),
+                ..Default::default()
+            },
+            glyphs: bounds,
+        }
+    }
+}
 
-                let position_offset = (position + vec2(glyph.x, glyph.y)).extend(0.0);
-                let Positions::F32(mesh_positions) = &mesh.positions else {
-                    unreachable!()
-                };
-                positions.extend(mesh_positions.iter().map(|p| p + position_offset));
+fn tessellate(scaler: &mut Scaler, id: GlyphId) -> Option<CpuMesh> {
+    let outline = scaler.scale_outline(id)?;
+    let mut builder = Path::builder();
+    for command in outline.path().commands() {
+        match command {
+            Command::MoveTo(p) => {
+                builder.begin(Point::new(p.x, p.y));
             }
-            position.x += cluster.advance();
-        });
-
-        CpuMesh {
-            positions: Positions::F32(positions),
-            indices: Indices::U32(indices),
-            ..Default::default()
+            Command::LineTo(p) => {
+                builder.line_to(Point::new(p.x, p.y));
+            }
+            Command::CurveTo(p1, p2, p3) => {
+                builder.cubic_bezier_to(
+                    Point::new(p1.x, p1.y),
+                    Point::new(p2.x, p2.y),
+                    Point::new(p3.x, p3.y),
+                );
+            }
+            Command::QuadTo(p1, p2) => {
+                builder.quadratic_bezier_to(Point::new(p1.x, p1.y), Point::new(p2.x, p2.y));
+            }
+            Command::Close => builder.close(),
         }
     }
+    let path = builder.build();
+
+    let mut tessellator = FillTessellator::new();
+    let mut geometry: VertexBuffers<Vec3, u32> = VertexBuffers::new();
+    let options = FillOptions::default();
+    tessellator
+        .tessellate_path(
+            &path,
+            &options,
+            &mut BuffersBuilder::new(&mut geometry, |vertex: FillVertex| {
+                vec3(vertex.position().x, vertex.position().y, 0.0)
+            }),
+        )
+        .ok()?;
+    Some(CpuMesh {
+        positions: Positions::F32(geometry.vertices),
+        indices: Indices::U32(geometry.indices),
+        ..Default::default()
+    })
 }
@@ -0,0 +1,488 @@
+use crate::core::*;
+use crate::renderer::*;
+use std::collections::HashMap;
+use std::ops::Range;
+
+///
+/// A text label placed at a position in world space, see [Labels].
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    /// The world space position the label is placed at.
+    pub position: Vec3,
+    /// The text of the label, which can contain multiple lines.
+    pub text: String,
+}
+
+///
+/// A set of text labels placed at positions in world space, for example for annotating measurements or the names of nodes in a 3D view.
+/// The labels always face the viewer and have a constant size in physical pixels given by the size of the [SdfTextGenerator] used to create them.
+/// The text of each label is centered at the projected position plus an offset in pixels, see [Labels::set_offset].
+///
+/// All labels are rendered in at most two draw calls, one for the background boxes and one for the text.
+/// Labels that overlap other labels on the screen can be hidden by calling [Labels::update] each frame,
+/// where the labels given first have the highest priority.
+///
+pub struct Labels {
+    context: Context,
+    text: LabelsGeometry,
+    background: LabelsGeometry,
+    positions: Vec<Vec3>,
+    bounds: Vec<(Vec2, Vec2)>,
+    visible: Vec<bool>,
+    offset: Vec2,
+    background_padding: f32,
+    /// The material used for the text which defines the text color, outline, shadow and glow.
+    pub material: TextMaterial,
+    /// The color of the box behind each label. Set to `None` to not render the boxes.
+    pub background_color: Option<Srgba>,
+    /// Whether the labels are hidden behind objects that are closer to the viewer than the label position. Default is `true`.
+    pub depth_test: bool,
+    /// Whether labels that overlap labels with a higher priority are hidden in [Labels::update]. Default is `true`.
+    pub avoid_overlap: bool,
+}
+
+impl Labels {
+    ///
+    /// Creates a new set of labels using the given text generator for generating the glyphs.
+    ///
+    pub fn new(context: &Context, generator: &mut SdfTextGenerator, labels: &[Label]) -> Self {
+        let mut result = Self {
+            context: context.clone(),
+            text: LabelsGeometry::new(context, true),
+            background: LabelsGeometry::new(context, false),
+            positions: Vec::new(),
+            bounds: Vec::new(),
+            visible: Vec::new(),
+            offset: vec2(0.0, 0.0),
+            background_padding: 4.0,
+            material: TextMaterial::new(context, generator),
+            background_color: None,
+            depth_test: true,
+            avoid_overlap: true,
+        };
+        result.set_labels(generator, labels);
+        result
+    }
+
+    ///
+    /// Replaces all labels with the given labels using the given text generator for generating the glyphs.
+    /// The glyph atlas of the material is updated if the labels contain glyphs that have not been generated before.
+    ///
+    pub fn set_labels(&mut self, generator: &mut SdfTextGenerator, labels: &[Label]) {
+        let options = TextLayoutOptions {
+            align: TextAlign::Center,
+            vertical_anchor: VerticalAnchor::Middle,
+            ..Default::default()
+        };
+        let mut anchors = Vec::new();
+        let mut offsets = Vec::new();
+        let mut uvs = Vec::new();
+        let mut indices = Vec::new();
+        let mut ranges = Vec::new();
+        self.bounds.clear();
+        for label in labels {
+            let layout = generator.layout(&[TextSpan::new(&label.text)], options);
+            let (min, max) = layout.glyphs.iter().fold(
+                (
+                    vec2(f32::INFINITY, f32::INFINITY),
+                    vec2(f32::NEG_INFINITY, f32::NEG_INFINITY),
+                ),
+                |(min, max), glyph| {
+                    (
+                        vec2(min.x.min(glyph.min.x), min.y.min(glyph.min.y)),
+                        vec2(max.x.max(glyph.max.x), max.y.max(glyph.max.y)),
+                    )
+                },
+            );
+            // Center the text horizontally around the anchor
+            let (shift, min, max) = if layout.glyphs.is_empty() {
+                (vec2(0.0, 0.0), vec2(0.0, 0.0), vec2(0.0, 0.0))
+            } else {
+                let shift = vec2(-0.5 * (min.x + max.x), 0.0);
+                (shift, min + shift, max + shift)
+            };
+            self.bounds.push((min, max));
+
+            let Positions::F32(positions) = &layout.mesh.positions else {
+                unreachable!()
+            };
+            let Indices::U32(label_indices) = &layout.mesh.indices else {
+                unreachable!()
+            };
+            let index_offset = anchors.len() as u32;
+            let start = indices.len();
+            indices.extend(label_indices.iter().map(|i| i + index_offset));
+            ranges.push(start..indices.len());
+            anchors.extend(positions.iter().map(|_| label.position));
+            offsets.extend(positions.iter().map(|p| p.truncate() + shift));
+            uvs.extend(layout.mesh.uvs.iter().flatten().copied());
+        }
+        self.positions = labels.iter().map(|label| label.position).collect();
+        self.visible = vec![true; labels.len()];
+        self.text
+            .set_data(&anchors, &offsets, Some(&uvs), indices, ranges);
+        self.update_background();
+        self.material.set_atlas(&self.context, generator);
+    }
+
+    ///
+    /// Returns the offset in pixels from the projected label positions to the center of the labels.
+    ///
+    pub fn offset(&self) -> Vec2 {
+        self.offset
+    }
+
+    ///
+    /// Sets the offset in pixels from the projected label positions to the center of the labels, for example to place the labels above the positions.
+    ///
+    pub fn set_offset(&mut self, offset: Vec2) {
+        self.offset = offset;
+        self.text.offset = offset;
+        self.background.offset = offset;
+    }
+
+    ///
+    /// Returns the distance in pixels from the text to the edge of the background boxes.
+    ///
+    pub fn background_padding(&self) -> f32 {
+        self.background_padding
+    }
+
+    ///
+    /// Sets the distance in pixels from the text to the edge of the background boxes.
+    ///
+    pub fn set_background_padding(&mut self, padding: f32) {
+        self.background_padding = padding;
+        self.update_background();
+    }
+
+    ///
+    /// Returns whether the label with the given index was visible after the last call to [Labels::update].
+    ///
+    pub fn is_visible(&self, index: usize) -> bool {
+        self.visible[index]
+    }
+
+    ///
+    /// Updates which labels are visible from the given viewer.
+    /// If [Labels::avoid_overlap] is enabled, labels that overlap a label with a higher priority on the screen are hidden,
+    /// where the labels given first have the highest priority. Labels that are behind the viewer or outside the viewport do not hide other labels.
+    /// Call this every frame before rendering, or whenever the viewer has changed.
+    ///
+    pub fn update(&mut self, viewer: impl Viewer) {
+        if !self.avoid_overlap {
+            if self.visible.iter().any(|visible| !visible) {
+                self.visible = vec![true; self.positions.len()];
+                self.text.set_visible(&self.visible);
+                self.background.set_visible(&self.visible);
+            }
+            return;
+        }
+
+        const CELL_SIZE: f32 = 64.0;
+        let viewport = viewer.viewport();
+        let viewport_size = vec2(viewport.width as f32, viewport.height as f32);
+        let view_projection = viewer.projection() * viewer.view();
+        let padding = if self.background_color.is_some() {
+            vec2(self.background_padding, self.background_padding)
+        } else {
+            vec2(0.0, 0.0)
+        };
+        let mut placed: Vec<(Vec2, Vec2)> = Vec::new();
+        let mut grid: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+        let mut visible = Vec::with_capacity(self.positions.len());
+        for (position, &(min, max)) in self.positions.iter().zip(self.bounds.iter()) {
+            let clip = view_projection * position.extend(1.0);
+            if clip.w <= 0.0 {
+                visible.push(false);
+                continue;
+            }
+            let pixel = vec2(
+                (0.5 * clip.x / clip.w + 0.5) * viewport_size.x,
+                (0.5 * clip.y / clip.w + 0.5) * viewport_size.y,
+            ) + self.offset;
+            let min = pixel + min - padding;
+            let max = pixel + max + padding;
+            if max.x < 0.0 || max.y < 0.0 || min.x > viewport_size.x || min.y > viewport_size.y {
+                visible.push(false);
+                continue;
+            }
+            let cells_x = (min.x / CELL_SIZE).floor() as i32..=(max.x / CELL_SIZE).floor() as i32;
+            let cells_y = (min.y / CELL_SIZE).floor() as i32..=(max.y / CELL_SIZE).floor() as i32;
+            let overlaps = cells_x.clone().any(|x| {
+                cells_y.clone().any(|y| {
+                    grid.get(&(x, y)).is_some_and(|indices| {
+                        indices.iter().any(|&i| {
+                            let (other_min, other_max) = placed[i];
+                            min.x < other_max.x
+                                && max.x > other_min.x
+                                && min.y < other_max.y
+                                && max.y > other_min.y
+                        })
+                    })
+                })
+            });
+            if !overlaps {
+                for x in cells_x {
+                    for y in cells_y.clone() {
+                        grid.entry((x, y)).or_default().push(placed.len());
+                    }
+                }
+                placed.push((min, max));
+            }
+            visible.push(!overlaps);
+        }
+        if visible != self.visible {
+            self.visible = visible;
+            self.text.set_visible(&self.visible);
+            self.background.set_visible(&self.visible);
+        }
+    }
+
+    fn update_background(&mut self) {
+        let mut anchors = Vec::new();
+        let mut offsets = Vec::new();
+        let mut indices = Vec::new();
+        let mut ranges = Vec::new();
+        let padding = vec2(self.background_padding, self.background_padding);
+        for (position, &(min, max)) in self.positions.iter().zip(self.bounds.iter()) {
+            let (min, max) = (min - padding, max + padding);
+            let index_offset = anchors.len() as u32;
+            let start = indices.len();
+            indices.extend([0, 1, 2, 2, 3, 0].map(|i| i + index_offset));
+            ranges.push(start..indices.len());
+            anchors.extend([*position; 4]);
+            offsets.extend([min, vec2(max.x, min.y), max, vec2(min.x, max.y)]);
+        }
+        self.background
+            .set_data(&anchors, &offsets, None, indices, ranges);
+        self.background.set_visible(&self.visible);
+    }
+
+    fn render_states(&self) -> RenderStates {
+        RenderStates {
+            write_mask: WriteMask::COLOR,
+            blend: Blend::TRANSPARENCY,
+            cull: Cull::None,
+            depth_test: if self.depth_test {
+                DepthTest::LessOrEqual
+            } else {
+                DepthTest::Always
+            },
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a Labels {
+    type Item = &'a dyn Object;
+    type IntoIter = std::iter::Once<&'a dyn Object>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        std::iter::once(self)
+    }
+}
+
+impl Geometry for Labels {
+    fn draw(&self, viewer: &dyn Viewer, program: &Program, render_states: RenderStates) {
+        self.text.draw(program, render_states, viewer);
+    }
+
+    fn vertex_shader_source(&self) -> String {
+        self.text.vertex_shader_source()
+    }
+
+    fn id(&self) -> GeometryId {
+        self.text.id()
+    }
+
+    fn render_with_material(
+        &self,
+        material: &dyn Material,
+        viewer: &dyn Viewer,
+        lights: &[&dyn Light],
+    ) {
+        self.text.render_with_material(material, viewer, lights);
+    }
+
+    fn render_with_effect(
+        &self,
+        material: &dyn Effect,
+        viewer: &dyn Viewer,
+        lights: &[&dyn Light],
+        color_texture: Option<ColorTexture>,
+        depth_texture: Option<DepthTexture>,
+    ) {
+        self.text
+            .render_with_effect(material, viewer, lights, color_texture, depth_texture);
+    }
+
+    fn aabb(&self) -> AxisAlignedBoundingBox {
+        AxisAlignedBoundingBox::new_with_positions(&self.positions)
+    }
+}
+
+impl Object for Labels {
+    fn render(&self, viewer: &dyn Viewer, lights: &[&dyn Light]) {
+        let render_states = self.render_states();
+        if let Some(background_color) = self.background_color {
+            let material = ColorMaterial {
+                color: background_color,
+                texture: None,
+                render_states,
+                is_transparent: true,
+            };
+            self.background
+                .render_with_material(&material, viewer, lights);
+        }
+        let mut material = self.material.clone();
+        material.render_states = render_states;
+        self.text.render_with_material(&material, viewer, lights);
+    }
+
+    fn material_type(&self) -> MaterialType {
+        MaterialType::Transparent
+    }
+}
+
+///
+/// The vertices of either the text or the background boxes of a set of labels.
+///
+struct LabelsGeometry {
+    context: Context,
+    anchor_buffer: VertexBuffer<Vec3>,
+    offset_buffer: VertexBuffer<Vec2>,
+    uv_buffer: Option<VertexBuffer<Vec2>>,
+    element_buffer: ElementBuffer<u32>,
+    indices: Vec<u32>,
+    ranges: Vec<Range<usize>>,
+    offset: Vec2,
+}
+
+impl LabelsGeometry {
+    fn new(context: &Context, uvs: bool) -> Self {
+        Self {
+            context: context.clone(),
+            anchor_buffer: VertexBuffer::new(context),
+            offset_buffer: VertexBuffer::new(context),
+            uv_buffer: uvs.then(|| VertexBuffer::new(context)),
+            element_buffer: ElementBuffer::new(context),
+            indices: Vec::new(),
+            ranges: Vec::new(),
+            offset: vec2(0.0, 0.0),
+        }
+    }
+
+    fn set_data(
+        &mut self,
+        anchors: &[Vec3],
+        offsets: &[Vec2],
+        uvs: Option<&[Vec2]>,
+        indices: Vec<u32>,
+        ranges: Vec<Range<usize>>,
+    ) {
+        self.anchor_buffer.fill(anchors);
+        self.offset_buffer.fill(offsets);
+        if let (Some(uv_buffer), Some(uvs)) = (&mut self.uv_buffer, uvs) {
+            uv_buffer.fill(uvs);
+        }
+        self.element_buffer.fill(&indices);
+        self.indices = indices;
+        self.ranges = ranges;
+    }
+
+    fn set_visible(&mut self, visible: &[bool]) {
+        let indices = self
+            .ranges
+            .iter()
+            .zip(visible)
+            .filter(|(_, visible)| **visible)
+            .flat_map(|(range, _)| self.indices[range.clone()].iter().copied())
+            .collect::<Vec<_>>();
+        self.element_buffer.fill(&indices);
+    }
+
+    fn draw(&self, program: &Program, render_states: RenderStates, viewer: &dyn Viewer) {
+        if self.element_buffer.count() == 0 {
+            return;
+        }
+        let viewport = viewer.viewport();
+        program.use_uniform("viewProjection", viewer.projection() * viewer.view());
+        program.use_uniform(
+            "viewportSize",
+            vec2(viewport.width as f32, viewport.height as f32),
+        );
+        program.use_uniform("labelOffset", self.offset);
+        program.use_vertex_attribute("anchor", &self.anchor_buffer);
+        program.use_vertex_attribute("offset", &self.offset_buffer);
+        if let Some(uv_buffer) = &self.uv_buffer {
+            if program.requires_attribute("uv_coordinates") {
+                program.use_vertex_attribute("uv_coordinates", uv_buffer);
+            }
+        }
+        program.draw_elements(render_states, viewport, &self.element_buffer);
+    }
+}
+
+impl<'a> IntoIterator for &'a LabelsGeometry {
+    type Item = &'a dyn Geometry;
+    type IntoIter = std::iter::Once<&'a dyn Geometry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        std::iter::once(self)
+    }
+}
+
+impl Geometry for LabelsGeometry {
+    fn draw(&self, viewer: &dyn Viewer, program: &Program, render_states: RenderStates) {
+        self.draw(program, render_states, viewer);
+    }
+
+    fn vertex_shader_source(&self) -> String {
+        if self.uv_buffer.is_some() {
+            format!("#define USE_UVS\n{}", include_str!("shaders/labels.vert"))
+        } else {
+            include_str!("shaders/labels.vert").to_owned()
+        }
+    }
+
+    fn id(&self) -> GeometryId {
+        GeometryId::Labels(self.uv_buffer.is_some())
+    }
+
+    fn render_with_material(
+        &self,
+        material: &dyn Material,
+        viewer: &dyn Viewer,
+        lights: &[&dyn Light],
+    ) {
+        if let Err(e) = render_with_material(&self.context, viewer, self, material, lights) {
+            panic!("{}", e.to_string());
+        }
+    }
+
+    fn render_with_effect(
+        &self,
+        material: &dyn Effect,
+        viewer: &dyn Viewer,
+        lights: &[&dyn Light],
+        color_texture: Option<ColorTexture>,
+        depth_texture: Option<DepthTexture>,
+    ) {
+        if let Err(e) = render_with_effect(
+            &self.context,
+            viewer,
+            self,
+            material,
+            lights,
+            color_texture,
+            depth_texture,
+        ) {
+            panic!("{}", e.to_string());
+        }
+    }
+
+    fn aabb(&self) -> AxisAlignedBoundingBox {
+        AxisAlignedBoundingBox::INFINITE
+    }
+}
@@ -0,0 +1,394 @@
+use crate::core::*;
+use crate::renderer::*;
+use std::ops::Range;
+use swash::shape::ShapeContext;
+use swash::{FontRef, GlyphId};
+
+///
+/// The horizontal alignment of the lines in a text, see [TextLayoutOptions].
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextAlign {
+    /// The lines start at x = 0.
+    #[default]
+    Left,
+    /// The lines are centered within the layout width.
+    Center,
+    /// The lines end at the layout width.
+    Right,
+    /// The space between the words is stretched such that the lines fill the layout width, except for the last line of each paragraph which is left aligned.
+    Justify,
+}
+
+///
+/// The vertical placement of a text relative to y = 0, see [TextLayoutOptions].
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VerticalAnchor {
+    /// The baseline of the first line is at y = 0.
+    #[default]
+    Baseline,
+    /// The top of the first line is at y = 0.
+    Top,
+    /// The text is vertically centered around y = 0.
+    Middle,
+    /// The bottom of the last line is at y = 0.
+    Bottom,
+}
+
+///
+/// Options for text layout.
+///
+#[derive(Debug, Clone, Copy)]
+pub struct TextLayoutOptions {
+    ///
+    /// The line height multiplier where 1.0 corresponds to the maximum height of the font.
+    /// Default is 1.2.
+    ///
+    pub line_height: f32,
+    ///
+    /// The horizontal alignment of the lines within the layout width,
+    /// which is the maximum width if specified and otherwise the width of the longest line.
+    /// Default is [TextAlign::Left].
+    ///
+    pub align: TextAlign,
+    ///
+    /// The maximum width of a line in the same unit as the font size.
+    /// Lines are wrapped at whitespace if possible and otherwise between characters so no line is wider than the maximum width.
+    /// Default is `None`, ie. lines are only broken at newline characters.
+    ///
+    pub max_width: Option<f32>,
+    ///
+    /// The vertical placement of the text relative to y = 0.
+    /// Default is [VerticalAnchor::Baseline].
+    ///
+    pub vertical_anchor: VerticalAnchor,
+}
+
+impl Default for TextLayoutOptions {
+    fn default() -> Self {
+        Self {
+            line_height: 1.2,
+            align: TextAlign::default(),
+            max_width: None,
+            vertical_anchor: VerticalAnchor::default(),
+        }
+    }
+}
+
+///
+/// A part of a text with its own style.
+/// Used for generating text where parts of the text have a different size or color than the rest, for example [TextGenerator::layout].
+///
+#[derive(Debug, Clone, Copy)]
+pub struct TextSpan<'a> {
+    /// The text in this span.
+    pub text: &'a str,
+    /// The font size in pixels per em. If `None`, the size of the text generator is used.
+    pub size: Option<f32>,
+    /// The color of the text which is multiplied with the color of the material. If `None`, the color of the material is used.
+    pub color: Option<Srgba>,
+}
+
+impl<'a> TextSpan<'a> {
+    ///
+    /// Creates a new span with the given text and the size and color of the text generator and material.
+    ///
+    pub fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            size: None,
+            color: None,
+        }
+    }
+}
+
+///
+/// The bounding box of a cluster of glyphs in a [TextLayout], which usually corresponds to a single character.
+/// The bounding box spans the advance of the cluster horizontally and the line vertically, which makes it suitable for hit testing.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlyphBounds {
+    /// The index of the [TextSpan] this cluster belongs to.
+    pub span: usize,
+    /// The byte range of the cluster in the text of the span.
+    pub text_range: Range<usize>,
+    /// The index of the line this cluster is placed on.
+    pub line: usize,
+    /// The minimum corner of the bounding box.
+    pub min: Vec2,
+    /// The maximum corner of the bounding box.
+    pub max: Vec2,
+}
+
+impl GlyphBounds {
+    ///
+    /// Returns whether the given point is inside this bounding box.
+    ///
+    pub fn contains(&self, point: Vec2) -> bool {
+        point.x >= self.min.x
+            && point.x <= self.max.x
+            && point.y >= self.min.y
+            && point.y <= self.max.y
+    }
+}
+
+///
+/// The result of laying out text with styled spans, for example using [TextGenerator::layout].
+///
+pub struct TextLayout {
+    /// The generated mesh which has per vertex colors if any of the spans specified a color.
+    pub mesh: CpuMesh,
+    /// The bounding box of each cluster of glyphs in the text, except newline characters, in the order they appear in the text.
+    pub glyphs: Vec<GlyphBounds>,
+}
+
+impl TextLayout {
+    ///
+    /// Returns the bounding box of the cluster of glyphs at the given point, if any.
+    ///
+    pub fn glyph_at(&self, point: Vec2) -> Option<&GlyphBounds> {
+        self.glyphs.iter().find(|glyph| glyph.contains(point))
+    }
+}
+
+pub(super) struct LayoutFont<'a> {
+    pub font: FontRef<'a>,
+    /// The height of a line in em used as the distance between lines before applying [TextLayoutOptions::line_height].
+    pub line_height: f32,
+}
+
+pub(super) struct PositionedGlyph {
+    pub font: usize,
+    pub id: GlyphId,
+    pub position: Vec2,
+    pub size: f32,
+    pub color: Option<Srgba>,
+}
+
+struct Line {
+    clusters: Range<usize>,
+    is_paragraph_end: bool,
+    /// The width of the line without trailing whitespace.
+    width: f32,
+    /// The number of clusters in the line without trailing whitespace.
+    content_end: usize,
+    /// The number of whitespace clusters in the line without trailing whitespace.
+    spacing_count: usize,
+    ascent: f32,
+    descent: f32,
+    height: f32,
+}
+
+struct Cluster {
+    span: usize,
+    text_range: Range<usize>,
+    font: usize,
+    size: f32,
+    glyphs: Vec<(GlyphId, Vec2, f32)>,
+    advance: f32,
+    is_whitespace: bool,
+    is_newline: bool,
+}
+
+///
+/// Lays out the given spans using the first font that contains each character and returns the positioned glyphs and the bounding box of each cluster.
+///
+pub(super) fn layout_text(
+    fonts: &[LayoutFont],
+    default_size: f32,
+    spans: &[TextSpan],
+    options: TextLayoutOptions,
+) -> (Vec<PositionedGlyph>, Vec<GlyphBounds>) {
+    let clusters = shape(fonts, default_size, spans);
+
+    // Break the clusters into lines
+    let mut line_ranges = Vec::new();
+    let mut line_start = 0;
+    let mut width = 0.0f32;
+    let mut last_break = None;
+    for (i, cluster) in clusters.iter().enumerate() {
+        if cluster.is_newline {
+            line_ranges.push((line_start..i + 1, true));
+            line_start = i + 1;
+            width = 0.0;
+            last_break = None;
+            continue;
+        }
+        if let Some(max_width) = options.max_width {
+            if !cluster.is_whitespace && i > line_start && width + cluster.advance > max_width {
+                let break_index = last_break.unwrap_or(i);
+                line_ranges.push((line_start..break_index, false));
+                line_start = break_index;
+                width = clusters[line_start..i].iter().map(|c| c.advance).sum();
+                last_break = None;
+            }
+        }
+        width += cluster.advance;
+        if cluster.is_whitespace {
+            last_break = Some(i + 1);
+        }
+    }
+    line_ranges.push((line_start..clusters.len(), true));
+
+    // Compute the width and vertical metrics of each line
+    let metrics = |font: usize, size: f32| {
+        let m = fonts[font].font.metrics(&[]).scale(size);
+        (m.ascent, m.descent, fonts[font].line_height * size)
+    };
+    let lines = line_ranges
+        .into_iter()
+        .map(|(range, is_paragraph_end)| {
+            let line = &clusters[range.clone()];
+            let content_end = line
+                .iter()
+                .rposition(|c| !c.is_whitespace && !c.is_newline)
+                .map(|i| i + 1)
+                .unwrap_or(0);
+            let width: f32 = line[..content_end].iter().map(|c| c.advance).sum();
+            let spacing_count = line[..content_end]
+                .iter()
+                .filter(|c| c.is_whitespace)
+                .count();
+            let (mut ascent, mut descent, mut height) = (0.0f32, 0.0f32, 0.0f32);
+            if line.is_empty() {
+                (ascent, descent, height) = metrics(0, default_size);
+            }
+            for cluster in line {
+                let (a, d, h) = metrics(cluster.font, cluster.size);
+                ascent = ascent.max(a);
+                descent = descent.max(d);
+                height = height.max(h);
+            }
+            Line {
+                clusters: range,
+                is_paragraph_end,
+                width,
+                content_end,
+                spacing_count,
+                ascent,
+                descent,
+                height,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let layout_width = options
+        .max_width
+        .unwrap_or_else(|| lines.iter().map(|line| line.width).fold(0.0, f32::max));
+
+    // Find the baseline of each line and the vertical offset given by the anchor
+    let mut baselines = Vec::with_capacity(lines.len());
+    let mut baseline = 0.0;
+    for (i, line) in lines.iter().enumerate() {
+        if i > 0 {
+            baseline -= line.height * options.line_height;
+        }
+        baselines.push(baseline);
+    }
+    let top = lines[0].ascent;
+    let bottom = baseline - lines[lines.len() - 1].descent;
+    let vertical_offset = match options.vertical_anchor {
+        VerticalAnchor::Baseline => 0.0,
+        VerticalAnchor::Top => -top,
+        VerticalAnchor::Middle => -0.5 * (top + bottom),
+        VerticalAnchor::Bottom => -bottom,
+    };
+
+    // Position the glyphs
+    let mut glyphs = Vec::new();
+    let mut bounds = Vec::new();
+    for (line_index, line) in lines.iter().enumerate() {
+        let remaining = (layout_width - line.width).max(0.0);
+        let (mut x, extra_spacing) = match options.align {
+            TextAlign::Left => (0.0, 0.0),
+            TextAlign::Center => (0.5 * remaining, 0.0),
+            TextAlign::Right => (remaining, 0.0),
+            TextAlign::Justify if !line.is_paragraph_end && line.spacing_count > 0 => {
+                (0.0, remaining / line.spacing_count as f32)
+            }
+            TextAlign::Justify => (0.0, 0.0),
+        };
+        let baseline = baselines[line_index] + vertical_offset;
+        for (i, cluster) in clusters[line.clusters.clone()].iter().enumerate() {
+            if cluster.is_newline {
+                continue;
+            }
+            let mut pen = x;
+            for &(id, offset, advance) in cluster.glyphs.iter() {
+                glyphs.push(PositionedGlyph {
+                    font: cluster.font,
+                    id,
+                    position: vec2(pen, baseline) + offset,
+                    size: cluster.size,
+                    color: spans[cluster.span].color,
+                });
+                pen += advance;
+            }
+            let mut advance = cluster.advance;
+            if cluster.is_whitespace && i < line.content_end {
+                advance += extra_spacing;
+            }
+            bounds.push(GlyphBounds {
+                span: cluster.span,
+                text_range: cluster.text_range.clone(),
+                line: line_index,
+                min: vec2(x, baseline - line.descent),
+                max: vec2(x + advance, baseline + line.ascent),
+            });
+            x += advance;
+        }
+    }
+    (glyphs, bounds)
+}
+
+///
+/// Splits the spans into runs that use the same font and shapes each run.
+///
+fn shape(fonts: &[LayoutFont], default_size: f32, spans: &[TextSpan]) -> Vec<Cluster> {
+    let mut shape_context = ShapeContext::new();
+    let mut clusters = Vec::new();
+    for (span_index, span) in spans.iter().enumerate() {
+        let size = span.size.unwrap_or(default_size);
+        let mut runs: Vec<(usize, Range<usize>)> = Vec::new();
+        for (i, c) in span.text.char_indices() {
+            let font = if c.is_whitespace() || c.is_control() {
+                runs.last().map(|(font, _)| *font).unwrap_or(0)
+            } else {
+                fonts
+                    .iter()
+                    .position(|f| f.font.charmap().map(c) != 0)
+                    .unwrap_or(0)
+            };
+            match runs.last_mut() {
+                Some((run_font, range)) if *run_font == font => range.end = i + c.len_utf8(),
+                _ => runs.push((font, i..i + c.len_utf8())),
+            }
+        }
+
+        for (font, range) in runs {
+            let text = &span.text[range.clone()];
+            let mut shaper = shape_context.builder(fonts[font].font).size(size).build();
+            shaper.add_str(text);
+            shaper.shape_with(|cluster| {
+                let source = cluster.source.to_range();
+                let t = text.get(source.clone()).unwrap_or_default();
+                clusters.push(Cluster {
+                    span: span_index,
+                    text_range: range.start + source.start..range.start + source.end,
+                    font,
+                    size,
+                    glyphs: cluster
+                        .glyphs
+                        .iter()
+                        .map(|glyph| (glyph.id, vec2(glyph.x, glyph.y), glyph.advance))
+                        .collect(),
+                    advance: cluster.advance(),
+                    is_whitespace: !t.is_empty() && t.chars().all(char::is_whitespace),
+                    is_newline: t.contains('\n'),
+                });
+            });
+        }
+    }
+    clusters
+}
@@ -0,0 +1,349 @@
+use super::layout::{layout_text, LayoutFont};
+use crate::core::*;
+use crate::renderer::*;
+use std::collections::HashMap;
+use swash::scale::{Render, ScaleContext, Source};
+use swash::zeno::Format;
+use swash::{FontRef, GlyphId};
+
+/// The number of atlas texels per em the glyphs are rasterized with.
+pub(super) const SDF_GLYPH_SIZE: f32 = 48.0;
+/// The maximum distance in atlas texels stored in the distance field, both inside and outside the glyph outlines.
+pub(super) const SDF_SPREAD: u32 = 12;
+const ATLAS_WIDTH: u32 = 1024;
+const INF: f64 = 1e20;
+
+#[derive(Clone, Copy)]
+struct SdfGlyph {
+    /// The top left corner of the glyph in the atlas in texels measured from the top of the atlas.
+    atlas_position: Vec2,
+    /// The size of the glyph including padding in atlas texels.
+    size: Vec2,
+    /// The offset from the glyph origin to the top left corner of the glyph in atlas texels.
+    offset: Vec2,
+}
+
+///
+/// A utility struct for generating text as textured quads that are rendered using a signed distance field glyph atlas, see [TextMaterial].
+/// Compared to [TextGenerator], the text has smooth edges at any scale and each glyph consists of only two triangles,
+/// so it is well suited for small text sizes and long texts.
+///
+/// The glyphs are rasterized into the atlas when they are first used in [SdfTextGenerator::generate].
+/// The atlas only grows, so meshes generated earlier are still valid after generating more text,
+/// but the [TextMaterial] needs to be updated with the new atlas using [TextMaterial::set_atlas].
+/// The atlas height is limited by the maximum texture size supported by the graphics context,
+/// and glyphs that do not fit into a full atlas are left out of the generated text.
+/// Glyphs that are missing in the font are taken from the first fallback font that contains them, see [SdfTextGenerator::add_fallback_font].
+///
+pub struct SdfTextGenerator<'a> {
+    fonts: Vec<FontRef<'a>>,
+    size: f32,
+    scale_context: ScaleContext,
+    glyphs: HashMap<(usize, GlyphId), Option<SdfGlyph>>,
+    atlas_data: Vec<u8>,
+    atlas_height: u32,
+    max_atlas_height: u32,
+    cursor: (u32, u32),
+    shelf_height: u32,
+}
+
+impl<'a> SdfTextGenerator<'a> {
+    ///
+    /// Creates a new SdfTextGenerator with the given font and size in pixels per em.
+    /// The index indicates the specific font in a font collection. Set to 0 if unsure.
+    /// The context is only used to query the maximum size of the glyph atlas.
+    ///
+    pub fn new(
+        context: &Context,
+        font_bytes: &'a [u8],
+        font_index: u32,
+        size: f32,
+    ) -> Result<Self, RendererError> {
+        let font = FontRef::from_index(font_bytes, font_index as usize)
+            .ok_or(RendererError::MissingFont(font_index))?;
+        let atlas_height = 128;
+        let max_atlas_height =
+            unsafe { context.get_parameter_i32(crate::context::MAX_TEXTURE_SIZE) } as u32;
+        Ok(Self {
+            fonts: vec![font],
+            size,
+            scale_context: ScaleContext::new(),
+            glyphs: HashMap::new(),
+            atlas_data: vec![0; (ATLAS_WIDTH * atlas_height) as usize],
+            atlas_height,
+            max_atlas_height: max_atlas_height.max(atlas_height),
+            cursor: (0, 0),
+            shelf_height: 0,
+        })
+    }
+
+    ///
+    /// Adds a font which is used for the glyphs that are missing in the font given at construction and the previously added fallback fonts,
+    /// for example a font with CJK characters.
+    ///
+    pub fn add_fallback_font(
+        &mut self,
+        font_bytes: &'a [u8],
+        font_index: u32,
+    ) -> Result<(), RendererError> {
+        let font = FontRef::from_index(font_bytes, font_index as usize)
+            .ok_or(RendererError::MissingFont(font_index))?;
+        self.fonts.push(font);
+        Ok(())
+    }
+
+    ///
+    /// Generates a [CpuMesh] with a textured quad for each glyph in the given text string.
+    /// The uv coordinates refer to the glyph atlas returned by [SdfTextGenerator::atlas] and are only meant to be used with a [TextMaterial].
+    ///
+    pub fn generate(&mut self, text: &str, options: TextLayoutOptions) -> CpuMesh {
+        self.layout(&[TextSpan::new(text)], options).mesh
+    }
+
+    ///
+    /// Generates a [CpuMesh] with a textured quad for each glyph in the given styled spans and returns it together with the bounding box of each glyph in the text.
+    /// The uv coordinates refer to the glyph atlas returned by [SdfTextGenerator::atlas] and are only meant to be used with a [TextMaterial].
+    ///
+    pub fn layout(&mut self, spans: &[TextSpan], options: TextLayoutOptions) -> TextLayout {
+        let fonts = self
+            .fonts
+            .iter()
+            .map(|font| {
+                let metrics = font.metrics(&[]);
+                LayoutFont {
+                    font: *font,
+                    line_height: (metrics.ascent + metrics.descent) / metrics.units_per_em as f32,
+                }
+            })
+            .collect::<Vec<_>>();
+        let (glyphs, bounds) = layout_text(&fonts, self.size, spans, options);
+
+        let has_colors = glyphs.iter().any(|glyph| glyph.color.is_some());
+        let mut positions = Vec::new();
+        let mut uvs = Vec::new();
+        let mut colors = Vec::new();
+        let mut indices = Vec::new();
+        for positioned_glyph in glyphs {
+            let Some(glyph) = self.glyph(positioned_glyph.font, positioned_glyph.id) else {
+                continue;
+            };
+            let scale = positioned_glyph.size / SDF_GLYPH_SIZE;
+            let index_offset = positions.len() as u32;
+            let top_left = positioned_glyph.position + glyph.offset * scale;
+            let size = glyph.size * scale;
+            positions.extend([
+                vec3(top_left.x, top_left.y - size.y, 0.0),
+                vec3(top_left.x + size.x, top_left.y - size.y, 0.0),
+                vec3(top_left.x + size.x, top_left.y, 0.0),
+                vec3(top_left.x, top_left.y, 0.0),
+            ]);
+            let uv = glyph.atlas_position;
+            uvs.extend([
+                vec2(uv.x, uv.y + glyph.size.y),
+                vec2(uv.x + glyph.size.x, uv.y + glyph.size.y),
+                vec2(uv.x + glyph.size.x, uv.y),
+                uv,
+            ]);
+            indices.extend([0, 1, 2, 2, 3, 0].map(|i| i + index_offset));
+            if has_colors {
+                colors.resize(
+                    positions.len(),
+                    positioned_glyph.color.unwrap_or(Srgba::WHITE),
+                );
+            }
+        }
+
+        TextLayout {
+            mesh: CpuMesh {
+                positions: Positions::F32(positions),
+                uvs: Some(uvs),
+                colors: has_colors.then_some(colors),
+                indices: Indices::U32(indices),
+                ..Default::default()
+            },
+            glyphs: bounds,
+        }
+    }
+
+    ///
+    /// Returns the signed distance field glyph atlas containing all glyphs used in the text generated so far.
+    ///
+    pub fn atlas(&self) -> CpuTexture {
+        CpuTexture {
+            data: TextureData::RU8(self.atlas_data.clone()),
+            width: ATLAS_WIDTH,
+            height: self.atlas_height,
+            min_filter: Interpolation::Linear,
+            mag_filter: Interpolation::Linear,
+            mipmap: None,
+            wrap_s: Wrapping::ClampToEdge,
+            wrap_t: Wrapping::ClampToEdge,
+            ..Default::default()
+        }
+    }
+
+    fn glyph(&mut self, font: usize, id: GlyphId) -> Option<SdfGlyph> {
+        if let Some(glyph) = self.glyphs.get(&(font, id)) {
+            return *glyph;
+        }
+        let mut scaler = self
+            .scale_context
+            .builder(self.fonts[font])
+            .size(SDF_GLYPH_SIZE)
+            .build();
+        let image = Render::new(&[Source::Outline])
+            .format(Format::Alpha)
+            .render(&mut scaler, id)
+            .filter(|image| image.placement.width > 0 && image.placement.height > 0);
+        let glyph = image.and_then(|image| {
+            let width = image.placement.width + 2 * SDF_SPREAD;
+            let height = image.placement.height + 2 * SDF_SPREAD;
+            let (x, y) = self.allocate(width, height)?;
+            let distance_field = distance_field(
+                &image.data,
+                image.placement.width as usize,
+                image.placement.height as usize,
+            );
+            for row in 0..height as usize {
+                let start = (y as usize + row) * ATLAS_WIDTH as usize + x as usize;
+                self.atlas_data[start..start + width as usize].copy_from_slice(
+                    &distance_field[row * width as usize..(row + 1) * width as usize],
+                );
+            }
+            Some(SdfGlyph {
+                atlas_position: vec2(x as f32, y as f32),
+                size: vec2(width as f32, height as f32),
+                offset: vec2(
+                    image.placement.left as f32 - SDF_SPREAD as f32,
+                    image.placement.top as f32 + SDF_SPREAD as f32,
+                ),
+            })
+        });
+        self.glyphs.insert((font, id), glyph);
+        glyph
+    }
+
+    ///
+    /// Allocates a region in the atlas using shelf packing and grows the atlas downwards if there is not enough space.
+    /// Returns `None` if the region does not fit into the atlas with the maximum height.
+    ///
+    fn allocate(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        if width > ATLAS_WIDTH {
+            return None;
+        }
+        if self.cursor.0 + width > ATLAS_WIDTH {
+            self.cursor = (0, self.cursor.1 + self.shelf_height);
+            self.shelf_height = 0;
+        }
+        let required_height = self.cursor.1 + height;
+        if required_height > self.max_atlas_height {
+            return None;
+        }
+        if required_height > self.atlas_height {
+            self.atlas_height = required_height
+                .max(2 * self.atlas_height)
+                .min(self.max_atlas_height);
+            self.atlas_data
+                .resize((ATLAS_WIDTH * self.atlas_height) as usize, 0);
+        }
+        let position = self.cursor;
+        self.cursor.0 += width + 1;
+        self.shelf_height = self.shelf_height.max(height + 1);
+        Some(position)
+    }
+}
+
+///
+/// Computes the signed distance field of the given alpha mask padded with [SDF_SPREAD] texels on each side.
+/// The distance is mapped such that the outline is at 0.5, inside is above and outside is below.
+///
+fn distance_field(alpha: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let padding = SDF_SPREAD as usize;
+    let padded_width = width + 2 * padding;
+    let padded_height = height + 2 * padding;
+    let mut outer = vec![INF; padded_width * padded_height];
+    let mut inner = vec![0.0; padded_width * padded_height];
+    for y in 0..height {
+        for x in 0..width {
+            let a = alpha[y * width + x] as f64 / 255.0;
+            let i = (y + padding) * padded_width + x + padding;
+            if a >= 1.0 {
+                outer[i] = 0.0;
+                inner[i] = INF;
+            } else if a > 0.0 {
+                // Approximate the sub-texel position of the outline from the coverage
+                let d = 0.5 - a;
+                outer[i] = if d > 0.0 { d * d } else { 0.0 };
+                inner[i] = if d < 0.0 { d * d } else { 0.0 };
+            }
+        }
+    }
+    distance_transform(&mut outer, padded_width, padded_height);
+    distance_transform(&mut inner, padded_width, padded_height);
+    outer
+        .iter()
+        .zip(inner.iter())
+        .map(|(outer, inner)| {
+            let distance = outer.sqrt() - inner.sqrt();
+            let value = 0.5 - distance / (2.0 * SDF_SPREAD as f64);
+            (value.clamp(0.0, 1.0) * 255.0).round() as u8
+        })
+        .collect()
+}
+
+///
+/// Computes the squared euclidean distance transform of the given grid in place (Felzenszwalb and Huttenlocher).
+///
+fn distance_transform(grid: &mut [f64], width: usize, height: usize) {
+    let n = width.max(height);
+    let mut f = vec![0.0; n];
+    let mut v = vec![0; n];
+    let mut z = vec![0.0; n + 1];
+    for x in 0..width {
+        distance_transform_1d(grid, x, width, height, &mut f, &mut v, &mut z);
+    }
+    for y in 0..height {
+        distance_transform_1d(grid, y * width, 1, width, &mut f, &mut v, &mut z);
+    }
+}
+
+fn distance_transform_1d(
+    grid: &mut [f64],
+    offset: usize,
+    stride: usize,
+    length: usize,
+    f: &mut [f64],
+    v: &mut [usize],
+    z: &mut [f64],
+) {
+    v[0] = 0;
+    z[0] = -INF;
+    z[1] = INF;
+    f[0] = grid[offset];
+    let mut k = 0;
+    for q in 1..length {
+        f[q] = grid[offset + q * stride];
+        let mut s;
+        loop {
+            let r = v[k];
+            s = (f[q] - f[r] + (q * q) as f64 - (r * r) as f64) / (2.0 * (q - r) as f64);
+            if s > z[k] {
+                break;
+            }
+            k -= 1;
+        }
+        k += 1;
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = INF;
+    }
+    let mut k = 0;
+    for q in 0..length {
+        while z[k + 1] < q as f64 {
+            k += 1;
+        }
+        let r = v[k];
+        let d = q as f64 - r as f64;
+        grid[offset + q * stride] = f[r] + d * d;
+    }
+}
@@ -0,0 +1,35 @@
+uniform mat4 viewProjection;
+uniform vec2 viewportSize;
+uniform vec2 labelOffset;
+
+in vec3 anchor;
+in vec2 offset;
+
+#ifdef USE_UVS
+in vec2 uv_coordinates;
+out vec2 uvs;
+#endif
+
+out vec3 pos;
+out vec4 col;
+
+void main()
+{
+    vec4 clip = viewProjection * vec4(anchor, 1.0);
+    if (clip.w <= 0.0) {
+        // Behind the viewer
+        gl_Position = vec4(0.0, 0.0, 2.0, 1.0);
+        return;
+    }
+
+    // Snap the anchor to the pixel grid to keep the text sharp
+    vec2 anchorPixel = floor((0.5 * clip.xy / clip.w + 0.5) * viewportSize + 0.5);
+    vec2 pixel = anchorPixel + labelOffset + offset;
+    gl_Position = vec4((2.0 * pixel / viewportSize - 1.0) * clip.w, clip.z, clip.w);
+
+    pos = anchor;
+    col = vec4(1.0);
+#ifdef USE_UVS
+    uvs = uv_coordinates;
+#endif
+}
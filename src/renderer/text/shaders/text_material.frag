@@ -0,0 +1,56 @@
+uniform sampler2D atlas;
+uniform float glyphSize;
+uniform float distanceRange;
+uniform vec4 textColor;
+uniform vec4 outlineColor;
+uniform float outlineWidth;
+uniform vec4 shadowColor;
+uniform vec2 shadowOffset;
+uniform float shadowSoftness;
+uniform vec4 glowColor;
+uniform float glowWidth;
+
+in vec2 uvs;
+in vec4 col;
+
+layout (location = 0) out vec4 outColor;
+
+// Returns the distance to the glyph outline in em, positive outside and negative inside the glyph
+float signed_distance(vec2 uv) {
+    return (0.5 - texture(atlas, uv).r) * distanceRange;
+}
+
+// Blends the color with the given coverage on top of the premultiplied color
+vec4 blend_over(vec4 result, vec4 color, float coverage) {
+    float alpha = color.a * coverage;
+    return vec4(color.rgb * alpha, alpha) + result * (1.0 - alpha);
+}
+
+void main()
+{
+    // The uv coordinates are in texels measured from the top of the atlas, since the atlas grows downwards
+    vec2 atlasSize = vec2(textureSize(atlas, 0));
+    vec2 uv = vec2(uvs.x, atlasSize.y - uvs.y) / atlasSize;
+
+    float edgeDistance = signed_distance(uv);
+    float width = max(fwidth(edgeDistance), 1e-5);
+
+    vec4 result = vec4(0.0);
+    if (shadowColor.a > 0.0) {
+        float shadowDistance = signed_distance(uv - shadowOffset * glyphSize / atlasSize);
+        float softness = max(shadowSoftness, width);
+        result = blend_over(result, shadowColor, smoothstep(softness, -softness, shadowDistance));
+    }
+    if (glowWidth > 0.0) {
+        result = blend_over(result, glowColor, 1.0 - smoothstep(0.0, glowWidth, edgeDistance));
+    }
+    if (outlineWidth > 0.0) {
+        result = blend_over(result, outlineColor, clamp(0.5 - (edgeDistance - outlineWidth) / width, 0.0, 1.0));
+    }
+    result = blend_over(result, textColor * col, clamp(0.5 - edgeDistance / width, 0.0, 1.0));
+
+    if (result.a < 0.5 / 255.0) {
+        discard;
+    }
+    outColor = vec4(color_mapping(result.rgb / result.a), result.a);
+}
@@ -0,0 +1,105 @@
+use super::sdf_text::{SDF_GLYPH_SIZE, SDF_SPREAD};
+use crate::core::*;
+use crate::renderer::*;
+
+///
+/// A material for rendering text generated by a [SdfTextGenerator] using the signed distance field glyph atlas.
+/// The glyph edges are anti-aliased based on the screen space size of the glyphs, so the text is crisp at any scale.
+/// Besides the text color, the material supports an outline, a drop shadow and a glow around the glyphs.
+/// The outline width, shadow offset, shadow softness and glow width are specified in em, ie. relative to the font size,
+/// and are limited to a quarter of an em since that is the maximum distance stored in the glyph atlas.
+///
+/// This material is not affected by lights and is always transparent.
+///
+#[derive(Clone)]
+pub struct TextMaterial {
+    /// The signed distance field glyph atlas, see [SdfTextGenerator::atlas].
+    pub atlas: Texture2DRef,
+    /// The color of the text. The color is multiplied with the per vertex colors if specified.
+    pub color: Srgba,
+    /// The color of the outline.
+    pub outline_color: Srgba,
+    /// The width of the outline in em. Set to zero to disable the outline.
+    pub outline_width: f32,
+    /// The color of the drop shadow. Set the alpha value to zero to disable the shadow.
+    pub shadow_color: Srgba,
+    /// The offset of the drop shadow in em.
+    pub shadow_offset: Vec2,
+    /// The softness of the drop shadow edges in em.
+    pub shadow_softness: f32,
+    /// The color of the glow.
+    pub glow_color: Srgba,
+    /// The distance from the glyph edges at which the glow has faded out in em. Set to zero to disable the glow.
+    pub glow_width: f32,
+    /// Render states.
+    pub render_states: RenderStates,
+}
+
+impl TextMaterial {
+    ///
+    /// Creates a new text material with the glyph atlas from the given [SdfTextGenerator] and black text without outline, shadow or glow.
+    ///
+    pub fn new(context: &Context, generator: &SdfTextGenerator) -> Self {
+        Self {
+            atlas: Texture2DRef::from_cpu_texture(context, &generator.atlas()),
+            color: Srgba::BLACK,
+            outline_color: Srgba::WHITE,
+            outline_width: 0.0,
+            shadow_color: Srgba::new(0, 0, 0, 0),
+            shadow_offset: vec2(0.05, -0.05),
+            shadow_softness: 0.05,
+            glow_color: Srgba::WHITE,
+            glow_width: 0.0,
+            render_states: RenderStates {
+                write_mask: WriteMask::COLOR,
+                blend: Blend::TRANSPARENCY,
+                cull: Cull::None,
+                ..Default::default()
+            },
+        }
+    }
+
+    ///
+    /// Updates the glyph atlas from the given [SdfTextGenerator].
+    /// This is needed after generating text which contains glyphs that were not in the atlas when this material was created.
+    ///
+    pub fn set_atlas(&mut self, context: &Context, generator: &SdfTextGenerator) {
+        self.atlas = Texture2DRef::from_cpu_texture(context, &generator.atlas());
+    }
+}
+
+impl Material for TextMaterial {
+    fn id(&self) -> EffectMaterialId {
+        EffectMaterialId::TextMaterial
+    }
+
+    fn fragment_shader_source(&self, _lights: &[&dyn Light]) -> String {
+        let mut shader = String::new();
+        shader.push_str(ColorMapping::fragment_shader_source());
+        shader.push_str(include_str!("shaders/text_material.frag"));
+        shader
+    }
+
+    fn use_uniforms(&self, program: &Program, viewer: &dyn Viewer, _lights: &[&dyn Light]) {
+        viewer.color_mapping().use_uniforms(program);
+        program.use_texture("atlas", &self.atlas);
+        program.use_uniform("glyphSize", SDF_GLYPH_SIZE);
+        program.use_uniform("distanceRange", 2.0 * SDF_SPREAD as f32 / SDF_GLYPH_SIZE);
+        program.use_uniform("textColor", self.color.to_linear_srgb());
+        program.use_uniform("outlineColor", self.outline_color.to_linear_srgb());
+        program.use_uniform("outlineWidth", self.outline_width);
+        program.use_uniform("shadowColor", self.shadow_color.to_linear_srgb());
+        program.use_uniform("shadowOffset", self.shadow_offset);
+        program.use_uniform("shadowSoftness", self.shadow_softness);
+        program.use_uniform("glowColor", self.glow_color.to_linear_srgb());
+        program.use_uniform("glowWidth", self.glow_width);
+    }
+
+    fn render_states(&self) -> RenderStates {
+        self.render_states
+    }
+
+    fn material_type(&self) -> MaterialType {
+        MaterialType::Transparent
+    }
+}
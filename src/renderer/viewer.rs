@@ -7,6 +7,12 @@ pub use color_space::*;
 mod camera;
 pub use camera::*;
 
+mod clipping;
+pub use clipping::*;
+
+mod occlusion_culler;
+pub use occlusion_culler::*;
+
 use crate::*;
 
 pub use three_d_asset::Frustum;
@@ -44,6 +50,10 @@ macro_rules! impl_viewer_body {
         fn tone_mapping(&self) -> ToneMapping {
             self.$inner().tone_mapping()
         }
+
+        fn clipping(&self) -> Clipping {
+            self.$inner().clipping()
+        }
     };
 }
 
@@ -75,6 +85,11 @@ pub trait Viewer {
 
     /// Defines the [ToneMapping] applied to the final rendered image.
     fn tone_mapping(&self) -> ToneMapping;
+
+    /// Defines the [Clipping] applied to the geometries rendered with this viewer. Defaults to no clipping.
+    fn clipping(&self) -> Clipping {
+        Clipping::default()
+    }
 }
 
 use std::ops::Deref;
@@ -134,4 +149,23 @@ impl<T: Viewer> Viewer for std::sync::RwLock<T> {
     fn tone_mapping(&self) -> ToneMapping {
         self.read().unwrap().tone_mapping()
     }
+
+    fn clipping(&self) -> Clipping {
+        self.read().unwrap().clipping()
+    }
+}
+
+///
+/// Returns how much of the screen the given bounding box covers when seen from the given viewer,
+/// ie. the diameter of the bounding sphere of the box projected onto the screen relative to the height of the viewport.
+/// For example, a value of `1.0` means that the bounding sphere covers the entire height of the viewport.
+///
+pub fn screen_coverage(viewer: &dyn Viewer, aabb: &AxisAlignedBoundingBox) -> f32 {
+    if aabb.is_empty() {
+        return 0.0;
+    }
+    let radius = 0.5 * aabb.size().magnitude();
+    let projection = viewer.projection();
+    let w = (projection * viewer.view() * aabb.center().extend(1.0)).w;
+    radius * projection.y.y / w.max(f32::EPSILON)
 }
@@ -10,6 +10,8 @@ pub struct Camera {
     pub tone_mapping: ToneMapping,
     /// This color mapping is applied to the final color of renders using this camera.
     pub color_mapping: ColorMapping,
+    /// The clipping applied to the geometries rendered using this camera.
+    pub clipping: Clipping,
 }
 
 impl Viewer for Camera {
@@ -44,6 +46,10 @@ impl Viewer for Camera {
     fn tone_mapping(&self) -> ToneMapping {
         self.tone_mapping
     }
+
+    fn clipping(&self) -> Clipping {
+        self.clipping.clone()
+    }
 }
 
 impl Camera {
@@ -65,6 +71,7 @@ impl Camera {
             ),
             tone_mapping: ToneMapping::default(),
             color_mapping: ColorMapping::default(),
+            clipping: Clipping::default(),
         }
     }
 
@@ -92,6 +99,7 @@ impl Camera {
             ),
             tone_mapping: ToneMapping::default(),
             color_mapping: ColorMapping::default(),
+            clipping: Clipping::default(),
         }
     }
 
@@ -0,0 +1,227 @@
+use crate::renderer::*;
+
+///
+/// The maximum number of [ClippingPlane]s that are used by a [Clipping], any additional planes are ignored.
+///
+pub const MAX_CLIPPING_PLANES: usize = 8;
+
+///
+/// A plane in world space which clips away everything on the opposite side of where the normal points.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClippingPlane {
+    /// A point on the plane.
+    pub point: Vec3,
+    /// The normal of the plane pointing towards the side that is kept.
+    pub normal: Vec3,
+}
+
+impl ClippingPlane {
+    ///
+    /// Creates a new clipping plane through the given point which keeps everything on the side the normal points to.
+    ///
+    pub fn new(point: Vec3, normal: Vec3) -> Self {
+        Self { point, normal }
+    }
+
+    fn equation(&self) -> Vec4 {
+        let normal = self.normal.normalize();
+        normal.extend(-normal.dot(self.point))
+    }
+}
+
+///
+/// An oriented box in world space which clips away everything outside of the box.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SectionBox {
+    /// The transformation from the cube with corners in `(-1, -1, -1)` and `(1, 1, 1)` to the box in world space.
+    pub transformation: Mat4,
+}
+
+impl SectionBox {
+    ///
+    /// Creates a new section box with the given center, half size along each axis and rotation.
+    ///
+    pub fn new(center: Vec3, half_size: Vec3, rotation: Mat3) -> Self {
+        Self {
+            transformation: Mat4::from_translation(center)
+                * Mat4::from(rotation)
+                * Mat4::from_nonuniform_scale(half_size.x, half_size.y, half_size.z),
+        }
+    }
+
+    ///
+    /// Creates a new axis aligned section box which is equal to the given bounding box.
+    ///
+    pub fn from_aabb(aabb: AxisAlignedBoundingBox) -> Self {
+        Self::new(aabb.center(), 0.5 * aabb.size(), Mat3::identity())
+    }
+}
+
+///
+/// Defines which parts of the geometries are clipped away, ie. not rendered, when rendering with a [Viewer], see [Viewer::clipping].
+/// This can for example be used to cut models open with section planes.
+/// The clipping is applied to all geometries rendered with any material or effect, except geometries with an infinite bounding box, like a [Skybox].
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Clipping {
+    /// Planes which each clip away everything on one side of the plane. At most [MAX_CLIPPING_PLANES] are used.
+    pub planes: Vec<ClippingPlane>,
+    /// An optional box which clips away everything outside the box.
+    pub section_box: Option<SectionBox>,
+    /// If specified, the surfaces where closed geometries are cut open are filled with this color.
+    /// The caps are drawn by drawing the back faces, which are visible through the cut, in this color without lighting.
+    /// Therefore it only works for closed geometries rendered with forward opaque materials that write to `outColor`.
+    pub cap_color: Option<Srgba>,
+}
+
+impl Clipping {
+    ///
+    /// Returns true if any clipping planes or a section box is specified.
+    ///
+    pub fn is_enabled(&self) -> bool {
+        !self.planes.is_empty() || self.section_box.is_some()
+    }
+}
+
+///
+/// Returns whether a geometry with the given bounding box should be clipped using the given clipping.
+///
+pub(crate) fn is_clipped(clipping: &Clipping, aabb: &AxisAlignedBoundingBox) -> bool {
+    clipping.is_enabled() && aabb.min().x.is_finite() && aabb.max().x.is_finite()
+}
+
+///
+/// Wraps the `main` function of the given fragment shader source in a function which discards the fragments that are clipped away
+/// before calling the original `main` function.
+/// The world position of the fragment is reconstructed from the fragment coordinate and depth, so no input from the vertex shader is needed.
+/// Returns the source unchanged if it does not contain a `main` function.
+///
+pub(crate) fn clipping_fragment_shader_source(source: String, capping: bool) -> String {
+    let mut output = match rename_main_function(&source, "clipped_main") {
+        Some(output) => output,
+        None => return source,
+    };
+    let capping = capping && source.contains("outColor");
+    output.push_str(&format!(
+        "
+uniform mat4 clippingViewProjectionInverse;
+uniform vec4 clippingViewport;
+uniform int clippingPlaneCount;
+uniform vec4 clippingPlanes[{}];
+uniform int clippingUseBox;
+uniform mat4 clippingBoxInverse;
+{}
+void main()
+{{
+    vec2 clipping_ndc = (gl_FragCoord.xy - clippingViewport.xy) / clippingViewport.zw * 2.0 - 1.0;
+    vec4 clipping_position = clippingViewProjectionInverse * vec4(clipping_ndc, gl_FragCoord.z * 2.0 - 1.0, 1.0);
+    vec3 clipping_world_position = clipping_position.xyz / clipping_position.w;
+    for (int i = 0; i < clippingPlaneCount; i++) {{
+        if (dot(clippingPlanes[i].xyz, clipping_world_position) + clippingPlanes[i].w < 0.0) {{
+            discard;
+        }}
+    }}
+    if (clippingUseBox == 1) {{
+        vec3 clipping_box_position = (clippingBoxInverse * vec4(clipping_world_position, 1.0)).xyz;
+        if (any(greaterThan(abs(clipping_box_position), vec3(1.0)))) {{
+            discard;
+        }}
+    }}
+    clipped_main();
+    {}
+}}
+",
+        MAX_CLIPPING_PLANES,
+        if capping {
+            "uniform vec4 clippingCapColor;"
+        } else {
+            ""
+        },
+        if capping {
+            "if (!gl_FrontFacing) { outColor = clippingCapColor; }"
+        } else {
+            ""
+        }
+    ));
+    output
+}
+
+///
+/// Renames the `main` function of the given fragment shader source to the given name,
+/// so that it can be called from a new `main` function appended to the returned source.
+/// Returns `None` if the source does not contain a `main` function.
+///
+pub(crate) fn rename_main_function(source: &str, name: &str) -> Option<String> {
+    let start = source.find("void main(")?;
+    let end = start + source[start..].find(')')? + 1;
+    let mut output = String::with_capacity(source.len() + 1024);
+    output.push_str(&source[..start]);
+    output.push_str("void ");
+    output.push_str(name);
+    output.push_str("()");
+    output.push_str(&source[end..]);
+    Some(output)
+}
+
+///
+/// Sends the uniforms needed for the clipping to the given program, if the program was built with [clipping_fragment_shader_source].
+///
+pub(crate) fn use_clipping_uniforms(program: &Program, viewer: &dyn Viewer) {
+    if !program.requires_uniform("clippingPlaneCount") {
+        return;
+    }
+    let clipping = viewer.clipping();
+    let viewport = viewer.viewport();
+    program.use_uniform(
+        "clippingViewProjectionInverse",
+        (viewer.projection() * viewer.view())
+            .invert()
+            .unwrap_or(Mat4::identity()),
+    );
+    program.use_uniform(
+        "clippingViewport",
+        vec4(
+            viewport.x as f32,
+            viewport.y as f32,
+            viewport.width as f32,
+            viewport.height as f32,
+        ),
+    );
+    let mut planes = [vec4(0.0, 0.0, 0.0, 0.0); MAX_CLIPPING_PLANES];
+    for (i, plane) in clipping.planes.iter().take(MAX_CLIPPING_PLANES).enumerate() {
+        planes[i] = plane.equation();
+    }
+    program.use_uniform(
+        "clippingPlaneCount",
+        clipping.planes.len().min(MAX_CLIPPING_PLANES) as i32,
+    );
+    program.use_uniform_array("clippingPlanes", &planes);
+    program.use_uniform(
+        "clippingUseBox",
+        if clipping.section_box.is_some() { 1 } else { 0 },
+    );
+    program.use_uniform(
+        "clippingBoxInverse",
+        clipping
+            .section_box
+            .and_then(|b| b.transformation.invert())
+            .unwrap_or(Mat4::identity()),
+    );
+    if let Some(color) = clipping.cap_color {
+        program.use_uniform_if_required(
+            "clippingCapColor",
+            if viewer.color_mapping() == ColorMapping::None {
+                color.to_linear_srgb()
+            } else {
+                vec4(
+                    color.r as f32 / 255.0,
+                    color.g as f32 / 255.0,
+                    color.b as f32 / 255.0,
+                    color.a as f32 / 255.0,
+                )
+            },
+        );
+    }
+}
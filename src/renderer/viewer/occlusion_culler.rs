@@ -0,0 +1,301 @@
+use crate::core::*;
+use crate::renderer::*;
+
+///
+/// Statistics about the objects culled by an [OcclusionCuller] since the last [OcclusionCuller::update].
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OcclusionCullingStats {
+    /// The number of objects that have been tested.
+    pub tested: u32,
+    /// The number of objects that have been culled, ie. found to be hidden behind other objects.
+    pub culled: u32,
+}
+
+///
+/// Culls objects which are hidden behind other objects using a hierarchical depth buffer (Hi-Z),
+/// ie. a pyramid of depth images where each pixel contains the maximum depth of the pixels it covers in the level below.
+///
+/// The depth pyramid is built from a depth texture using [OcclusionCuller::update], usually the depth of the previous frame
+/// or the depth of a depth pre-pass rendering only large occluders.
+/// The bounding box of each object is then projected onto the screen using the viewer used for rendering the depth texture,
+/// and the object is culled if the bounding box is behind the maximum depth in the area it covers, see [OcclusionCuller::cull]
+/// and [RenderTarget::render_with_occlusion_culling].
+///
+/// When using the depth of the previous frame, objects that become visible can appear one frame late.
+///
+pub struct OcclusionCuller {
+    context: Context,
+    view_projection: Mat4,
+    levels: Vec<(u32, u32, Vec<f32>)>,
+    stats: OcclusionCullingStats,
+    /// The maximum width and height of the depth pyramid used for testing the objects.
+    /// The depth texture is reduced to this size on the GPU and then read back to the CPU, so a higher resolution culls more objects at a higher cost.
+    pub max_resolution: u32,
+}
+
+impl OcclusionCuller {
+    ///
+    /// Constructs a new occlusion culler which does not cull any objects until [OcclusionCuller::update] is called.
+    ///
+    pub fn new(context: &Context) -> Self {
+        Self {
+            context: context.clone(),
+            view_projection: Mat4::identity(),
+            levels: Vec::new(),
+            stats: OcclusionCullingStats::default(),
+            max_resolution: 256,
+        }
+    }
+
+    ///
+    /// Builds the depth pyramid from the given depth texture, which must contain the depth of the scene rendered with the given viewer.
+    /// Also resets the [OcclusionCuller::stats].
+    ///
+    pub fn update(&mut self, viewer: impl Viewer, depth_texture: &DepthTexture2D) {
+        self.view_projection = viewer.projection() * viewer.view();
+        self.stats = OcclusionCullingStats::default();
+
+        // Reduce the depth on the GPU until the size is at most the maximum resolution
+        let max_resolution = self.max_resolution.max(1);
+        let mut width = depth_texture.width();
+        let mut height = depth_texture.height();
+        let mut texture: Option<Texture2D> = None;
+        while texture.is_none() || width > max_resolution || height > max_resolution {
+            let (target_width, target_height) = ((width + 1) / 2, (height + 1) / 2);
+            let target = Texture2D::new_empty::<f32>(
+                &self.context,
+                target_width,
+                target_height,
+                Interpolation::Nearest,
+                Interpolation::Nearest,
+                None,
+                Wrapping::ClampToEdge,
+                Wrapping::ClampToEdge,
+            );
+            let effect = HiZReduceEffect {
+                source: texture.as_ref(),
+                depth_texture,
+                source_size: (width, height),
+            };
+            target.as_color_target(None).apply_screen_effect(
+                &effect,
+                Camera::new_2d(Viewport::new_at_origo(target_width, target_height)),
+                &[],
+                None,
+                None,
+            );
+            texture = Some(target);
+            width = target_width;
+            height = target_height;
+        }
+        let depths = texture
+            .unwrap()
+            .as_color_target(None)
+            .read::<[f32; 4]>()
+            .into_iter()
+            .map(|c| c[0])
+            .collect::<Vec<_>>();
+        self.levels = depth_pyramid(width, height, depths);
+    }
+
+    ///
+    /// Returns true if the given bounding box is completely hidden according to the depth pyramid built in the last [OcclusionCuller::update].
+    /// Bounding boxes that are partly outside the screen or intersect the near plane are never considered occluded.
+    ///
+    pub fn is_occluded(&self, aabb: &AxisAlignedBoundingBox) -> bool {
+        is_occluded(&self.levels, self.view_projection, aabb)
+    }
+
+    ///
+    /// Returns the given objects except the ones that are occluded, see [OcclusionCuller::is_occluded].
+    /// The number of tested and culled objects are added to the [OcclusionCuller::stats].
+    ///
+    pub fn cull<G: Geometry>(&mut self, objects: impl IntoIterator<Item = G>) -> Vec<G> {
+        let mut visible = Vec::new();
+        for object in objects {
+            self.stats.tested += 1;
+            if self.is_occluded(&object.aabb()) {
+                self.stats.culled += 1;
+            } else {
+                visible.push(object);
+            }
+        }
+        visible
+    }
+
+    ///
+    /// Returns the statistics about the objects tested and culled since the last [OcclusionCuller::update].
+    ///
+    pub fn stats(&self) -> OcclusionCullingStats {
+        self.stats
+    }
+}
+
+///
+/// Builds the depth pyramid from the given depths with the given size, where the rows are ordered from the top of the screen as returned when reading a texture.
+/// The rows of each level in the returned pyramid are ordered from the bottom of the screen, like the uv coordinates.
+///
+fn depth_pyramid(width: u32, height: u32, depths: Vec<f32>) -> Vec<(u32, u32, Vec<f32>)> {
+    let mut depths = depths;
+    flip_rows(&mut depths, width as usize, height as usize);
+    let mut levels = vec![(width, height, depths)];
+    let (mut width, mut height) = (width, height);
+    while width > 1 || height > 1 {
+        let (next_width, next_height) = ((width + 1) / 2, (height + 1) / 2);
+        let mut next = vec![0.0f32; (next_width * next_height) as usize];
+        let depths = &levels.last().unwrap().2;
+        for y in 0..height {
+            for x in 0..width {
+                let i = ((y / 2) * next_width + x / 2) as usize;
+                next[i] = next[i].max(depths[(y * width + x) as usize]);
+            }
+        }
+        levels.push((next_width, next_height, next));
+        width = next_width;
+        height = next_height;
+    }
+    levels
+}
+
+fn flip_rows(data: &mut [f32], width: usize, height: usize) {
+    for y in 0..height / 2 {
+        let (top, bottom) = data.split_at_mut((height - 1 - y) * width);
+        top[y * width..(y + 1) * width].swap_with_slice(&mut bottom[..width]);
+    }
+}
+
+///
+/// Returns true if the given bounding box is completely hidden according to the given depth pyramid, see [OcclusionCuller::is_occluded].
+///
+fn is_occluded(
+    levels: &[(u32, u32, Vec<f32>)],
+    view_projection: Mat4,
+    aabb: &AxisAlignedBoundingBox,
+) -> bool {
+    if levels.is_empty() || aabb.is_empty() || !aabb.size().magnitude().is_finite() {
+        return false;
+    }
+    let (min, max) = (aabb.min(), aabb.max());
+    let mut min_uv = vec2(f32::MAX, f32::MAX);
+    let mut max_uv = vec2(f32::MIN, f32::MIN);
+    let mut min_depth = f32::MAX;
+    for corner in [
+        vec3(min.x, min.y, min.z),
+        vec3(max.x, min.y, min.z),
+        vec3(min.x, max.y, min.z),
+        vec3(max.x, max.y, min.z),
+        vec3(min.x, min.y, max.z),
+        vec3(max.x, min.y, max.z),
+        vec3(min.x, max.y, max.z),
+        vec3(max.x, max.y, max.z),
+    ] {
+        let p = view_projection * corner.extend(1.0);
+        if p.w <= 0.0 {
+            return false;
+        }
+        let ndc = p.truncate() / p.w;
+        let uv = 0.5 * ndc.truncate() + vec2(0.5, 0.5);
+        min_uv = vec2(min_uv.x.min(uv.x), min_uv.y.min(uv.y));
+        max_uv = vec2(max_uv.x.max(uv.x), max_uv.y.max(uv.y));
+        min_depth = min_depth.min(0.5 * ndc.z + 0.5);
+    }
+    if min_depth < 0.0 || min_uv.x < 0.0 || min_uv.y < 0.0 || max_uv.x > 1.0 || max_uv.y > 1.0 {
+        return false;
+    }
+
+    // Find the rectangle covered by the bounding box at the highest resolution level
+    let (width, height, _) = &levels[0];
+    let x0 = ((min_uv.x * *width as f32) as u32).min(width - 1);
+    let x1 = ((max_uv.x * *width as f32) as u32).min(width - 1);
+    let y0 = ((min_uv.y * *height as f32) as u32).min(height - 1);
+    let y1 = ((max_uv.y * *height as f32) as u32).min(height - 1);
+
+    // Use the level where the rectangle covers at most 2x2 pixels
+    let mut level = 0;
+    while level + 1 < levels.len()
+        && ((x1 >> level) - (x0 >> level) > 1 || (y1 >> level) - (y0 >> level) > 1)
+    {
+        level += 1;
+    }
+    let (width, _, depths) = &levels[level];
+    let mut max_depth = 0.0f32;
+    for y in (y0 >> level)..=(y1 >> level) {
+        for x in (x0 >> level)..=(x1 >> level) {
+            max_depth = max_depth.max(depths[(y * width + x) as usize]);
+        }
+    }
+    min_depth > max_depth
+}
+
+struct HiZReduceEffect<'a> {
+    source: Option<&'a Texture2D>,
+    depth_texture: &'a DepthTexture2D,
+    source_size: (u32, u32),
+}
+
+impl Effect for HiZReduceEffect<'_> {
+    fn fragment_shader_source(
+        &self,
+        _lights: &[&dyn Light],
+        _color_texture: Option<ColorTexture>,
+        _depth_texture: Option<DepthTexture>,
+    ) -> String {
+        include_str!("shaders/hi_z_reduce.frag").to_string()
+    }
+
+    fn id(
+        &self,
+        _color_texture: Option<ColorTexture>,
+        _depth_texture: Option<DepthTexture>,
+    ) -> EffectMaterialId {
+        EffectMaterialId::HiZReduceEffect
+    }
+
+    fn use_uniforms(
+        &self,
+        program: &Program,
+        _viewer: &dyn Viewer,
+        _lights: &[&dyn Light],
+        _color_texture: Option<ColorTexture>,
+        _depth_texture: Option<DepthTexture>,
+    ) {
+        match self.source {
+            Some(texture) => program.use_texture("sourceDepth", texture),
+            None => program.use_depth_texture("sourceDepth", self.depth_texture),
+        }
+        program.use_uniform(
+            "sourceSize",
+            vec2(self.source_size.0 as i32, self.source_size.1 as i32),
+        );
+    }
+
+    fn render_states(&self) -> RenderStates {
+        RenderStates {
+            write_mask: WriteMask::COLOR,
+            depth_test: DepthTest::Always,
+            cull: Cull::Back,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn occluder_in_upper_half() {
+        // The depths as read back from a texture, ie. the first row is the top of the screen
+        let (width, height) = (8, 8);
+        let depths = (0..width * height)
+            .map(|i| if i / width < height / 2 { 0.1 } else { 1.0 })
+            .collect::<Vec<_>>();
+        let levels = depth_pyramid(width, height, depths);
+        let behind = |y0: f32, y1: f32| {
+            AxisAlignedBoundingBox::new_with_positions(&[vec3(-0.5, y0, 0.5), vec3(0.5, y1, 0.6)])
+        };
+        assert!(is_occluded(&levels, Mat4::identity(), &behind(0.2, 0.8)));
+        assert!(!is_occluded(&levels, Mat4::identity(), &behind(-0.8, -0.2)));
+    }
+}
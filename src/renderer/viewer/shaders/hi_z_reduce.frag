@@ -0,0 +1,19 @@
+uniform sampler2D sourceDepth;
+uniform ivec2 sourceSize;
+
+layout (location = 0) out vec4 outColor;
+
+void main()
+{
+    ivec2 p = 2 * ivec2(gl_FragCoord.xy);
+    // Includes an extra row or column when the source size is odd, so no source texels are skipped
+    ivec2 extent = ivec2(sourceSize.x % 2 == 1 ? 3 : 2, sourceSize.y % 2 == 1 ? 3 : 2);
+    float depth = 0.0;
+    for (int y = 0; y < extent.y; y++) {
+        for (int x = 0; x < extent.x; x++) {
+            ivec2 q = min(p + ivec2(x, y), sourceSize - 1);
+            depth = max(depth, texelFetch(sourceDepth, q, 0).r);
+        }
+    }
+    outColor = vec4(depth, 0.0, 0.0, 1.0);
+}